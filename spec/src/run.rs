@@ -1,4 +1,4 @@
-use parity_wasm::elements::{deserialize_buffer, Module};
+use parity_wasm::elements::{deserialize_buffer, validate::validate, Module};
 use wabt::script::{Command, CommandKind, ScriptParser};
 
 fn read_file(filename: &str) -> String {
@@ -14,6 +14,30 @@ fn read_file(filename: &str) -> String {
 	contents
 }
 
+/// Expected failures that proposals implemented by the crate turned into valid modules.
+const SUPERSEDED_MESSAGES: &[&str] = &[
+	// Reserved bytes of `call_indirect` and `memory.*` are table and memory indices
+	// with reference types and multi-memory.
+	"zero flag expected",
+	// Multi-value.
+	"invalid result arity",
+	// Reference types.
+	"multiple tables",
+	// Multi-memory.
+	"multiple memories",
+];
+
+/// Individual cases that proposals implemented by the crate turned into valid modules.
+const SUPERSEDED_CASES: &[(&str, u64)] = &[
+	// `$type-br_table-label-num-vs-label-num-after-unreachable`, `meet-bottom` in
+	// unreached-valid.wast since reference types.
+	("unreached-invalid", 539),
+];
+
+fn superseded(path: &str, line: u64, message: &str) -> bool {
+	SUPERSEDED_MESSAGES.contains(&message) || SUPERSEDED_CASES.contains(&(path, line))
+}
+
 pub fn spec(path: &str) {
 	let mut parser = {
 		let source = read_file(&format!("./testsuite/{}.wast", path));
@@ -21,16 +45,34 @@ pub fn spec(path: &str) {
 	};
 	while let Some(Command { kind, line }) = parser.next().expect("Failed to iterate") {
 		match kind {
+			CommandKind::AssertMalformed { ref message, .. } | CommandKind::AssertInvalid { ref message, .. }
+				if superseded(path, line, message) =>
+			{
+				println!("assertion at line {} - skipped, superseded by a proposal", line);
+			}
 			CommandKind::AssertMalformed { module, .. } => {
 				match deserialize_buffer::<Module>(&module.into_vec()) {
 					Ok(_) => panic!("Expected invalid module definition, got some module! at line {}", line),
-					Err(e) => println!("assert_invalid at line {} - success ({:?})", line, e),
+					Err(e) => println!("assert_malformed at line {} - success ({:?})", line, e),
 				}
 			}
-			CommandKind::Module { module, .. } => {
+			CommandKind::AssertInvalid { module, .. } => {
 				match deserialize_buffer::<Module>(&module.into_vec()) {
-					Ok(_) => println!("module at line {} - parsed ok", line),
+					Ok(module) => match validate(&module) {
+						Ok(_) => panic!("Expected invalid module, got valid module! at line {}", line),
+						Err(e) => println!("assert_invalid at line {} - success ({:?})", line, e),
+					},
+					Err(e) => println!("assert_invalid at line {} - rejected by decoder ({:?})", line, e),
+				}
+			}
+			CommandKind::Module { module, .. } => {
+				let module = match deserialize_buffer::<Module>(&module.into_vec()) {
+					Ok(module) => module,
 					Err(e) => panic!("Valid module reported error ({:?})", e),
+				};
+				match validate(&module) {
+					Ok(_) => println!("module at line {} - parsed ok", line),
+					Err(e) => panic!("Valid module failed validation at line {} ({:?})", line, e),
 				}
 			}
 			_ => {
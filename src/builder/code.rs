@@ -45,9 +45,21 @@ impl<F> SignatureBuilder<F> where F: Invoke<elements::FunctionType> {
 		self
 	}
 
-	/// Override signature return type
+	/// Add result to signature builder
+	pub fn with_result(mut self, value_type: impl Into<elements::ValueType>) -> Self {
+		self.signature.results_mut().push(value_type.into());
+		self
+	}
+
+	/// Add multiple results to signature builder
+	pub fn with_results(mut self, value_types: Vec<elements::ValueType>) -> Self {
+		self.signature.results_mut().extend(value_types);
+		self
+	}
+
+	/// Override signature results with a single optional return type
 	pub fn with_return_type(mut self, return_type: Option<elements::ValueType>) -> Self {
-		*self.signature.return_type_mut() = return_type;
+		*self.signature.results_mut() = return_type.into_iter().collect();
 		self
 	}
 
@@ -66,6 +78,16 @@ impl<F> SignatureBuilder<F> where F: Invoke<elements::FunctionType> {
 		OptionalValueTypeBuilder::with_callback(self)
 	}
 
+	/// Start build new result
+	pub fn result(self) -> ValueTypeBuilder<SignatureResults<F>> {
+		ValueTypeBuilder::with_callback(SignatureResults(self))
+	}
+
+	/// Start build multiple results
+	pub fn results(self) -> ValueTypesBuilder<SignatureResults<F>> {
+		ValueTypesBuilder::with_callback(SignatureResults(self))
+	}
+
 	/// Finish current builder
 	pub fn build(self) -> F::Result {
 		self.callback.invoke(self.signature)
@@ -107,6 +129,29 @@ impl<F> Invoke<elements::ValueType> for SignatureBuilder<F>
 	}
 }
 
+/// Results being added to a signature builder
+pub struct SignatureResults<F>(SignatureBuilder<F>);
+
+impl<F> Invoke<elements::ValueType> for SignatureResults<F>
+	where F: Invoke<elements::FunctionType>
+{
+	type Result = SignatureBuilder<F>;
+
+	fn invoke(self, arg: elements::ValueType) -> SignatureBuilder<F> {
+		self.0.with_result(arg)
+	}
+}
+
+impl<F> Invoke<Vec<elements::ValueType>> for SignatureResults<F>
+	where F: Invoke<elements::FunctionType>
+{
+	type Result = SignatureBuilder<F>;
+
+	fn invoke(self, args: Vec<elements::ValueType>) -> SignatureBuilder<F> {
+		self.0.with_results(args)
+	}
+}
+
 /// Type (signature) reference builder (for function/import/indirect call)
 pub struct TypeRefBuilder<F=Identity> {
 	callback: F,
@@ -374,7 +419,7 @@ pub fn function() -> FunctionBuilder {
 #[cfg(test)]
 mod tests {
 
-	use super::{signatures, function};
+	use super::{signatures, signature, function};
 	use crate::elements;
 
 	#[test]
@@ -396,6 +441,21 @@ mod tests {
 		assert_eq!(result.len(), 1);
 	}
 
+	#[test]
+	fn multi_value() {
+		let i32 = elements::ValueType::from(elements::NumType::I32);
+		let i64 = elements::ValueType::from(elements::NumType::I64);
+		let sig = signature()
+			.param().i32()
+			.result().i32()
+			.results().i64().i32().build()
+			.build();
+		assert_eq!(sig.results(), &[i32, i64, i32]);
+
+		let sig = signature().with_results(vec![i64, i64]).return_type().i32().build();
+		assert_eq!(sig.results(), &[i32]);
+	}
+
 	#[test]
 	fn func_example() {
 		let func = function()
@@ -7,7 +7,7 @@ pub struct DataSegmentBuilder<F=Identity> {
 	callback: F,
 	// todo: add mapper once multiple memory refs possible
 	mem_index: u32,
-	offset: elements::InitExpr,
+	offset: Option<elements::InitExpr>,
 	value: Vec<u8>,
 }
 
@@ -24,14 +24,21 @@ impl<F> DataSegmentBuilder<F> {
 		DataSegmentBuilder {
 			callback: callback,
 			mem_index: 0,
-			offset: elements::InitExpr::empty(),
+			offset: Some(elements::InitExpr::empty()),
 			value: Vec::new(),
 		}
 	}
 
 	/// Set offset initialization instruction. `End` instruction will be added automatically.
 	pub fn offset(mut self, instruction: elements::Instruction) -> Self {
-		self.offset = elements::InitExpr::new(vec![instruction, elements::Instruction::End]);
+		self.offset = Some(elements::InitExpr::new(vec![instruction, elements::Instruction::End]));
+		self
+	}
+
+	/// Make the segment passive, so it is only used by instructions like `memory.init`
+	/// or `array.new_data`.
+	pub fn passive(mut self) -> Self {
+		self.offset = None;
 		self
 	}
 
@@ -45,12 +52,13 @@ impl<F> DataSegmentBuilder<F> {
 impl<F> DataSegmentBuilder<F> where F: Invoke<elements::DataSegment> {
 	/// Finish current builder, spawning resulting struct
 	pub fn build(self) -> F::Result {
+		let passive = self.offset.is_none();
 		self.callback.invoke(
 			elements::DataSegment::new(
 				self.mem_index,
-				Some(self.offset),
+				self.offset,
 				self.value,
-				false,
+				passive,
 			)
 		)
 	}
@@ -13,7 +13,7 @@ mod data;
 
 pub use self::code::{
 	signatures, signature, function, SignatureBuilder, SignaturesBuilder,
-	FunctionBuilder, TypeRefBuilder, FuncBodyBuilder, FunctionDefinition,
+	FunctionBuilder, TypeRefBuilder, FuncBodyBuilder, FunctionDefinition, SignatureResults,
 };
 pub use self::data::DataSegmentBuilder;
 pub use self::export::{export, ExportBuilder, ExportInternalBuilder};
@@ -36,6 +36,7 @@ struct ModuleScaffold {
 	pub export: elements::ExportSection,
 	pub start: Option<u32>,
 	pub element: elements::ElementSection,
+	pub data_count: bool,
 	pub code: elements::CodeSection,
 	pub data: elements::DataSection,
 	pub other: Vec<elements::Section>,
@@ -52,6 +53,7 @@ impl From<elements::Module> for ModuleScaffold {
 		let mut export: Option<elements::ExportSection> = None;
 		let mut start: Option<u32> = None;
 		let mut element: Option<elements::ElementSection> = None;
+		let mut data_count = false;
 		let mut code: Option<elements::CodeSection> = None;
 		let mut data: Option<elements::DataSection> = None;
 
@@ -67,6 +69,7 @@ impl From<elements::Module> for ModuleScaffold {
 				elements::Section::Export(sect) => { export = Some(sect); }
 				elements::Section::Start(index) => { start = Some(index); }
 				elements::Section::Element(sect) => { element = Some(sect); }
+				elements::Section::DataCount(_) => { data_count = true; }
 				elements::Section::Code(sect) => { code = Some(sect); }
 				elements::Section::Data(sect) => { data = Some(sect); }
 				_ => {}
@@ -83,6 +86,7 @@ impl From<elements::Module> for ModuleScaffold {
 			export: export.unwrap_or_default(),
 			start: start,
 			element: element.unwrap_or_default(),
+			data_count: data_count,
 			code: code.unwrap_or_default(),
 			data: data.unwrap_or_default(),
 			other: sections,
@@ -129,15 +133,24 @@ impl From<ModuleScaffold> for elements::Module {
 		if element.entries().len() > 0 {
 			sections.push(elements::Section::Element(element));
 		}
+		// The data count is derived from the data section, so that code can refer to data segments.
+		let mut data_count = module.data_count;
+		let other: Vec<_> = module.other.into_iter().filter(|section| match *section {
+			elements::Section::DataCount(_) => { data_count = true; false },
+			_ => true,
+		}).collect();
+		let data = module.data;
+		if data_count || !data.entries().is_empty() {
+			sections.push(elements::Section::DataCount(data.entries().len() as u32));
+		}
 		let code = module.code;
 		if code.bodies().len() > 0 {
 			sections.push(elements::Section::Code(code));
 		}
-		let data = module.data;
-		if data.entries().len() > 0 {
+		if !data.entries().is_empty() {
 			sections.push(elements::Section::Data(data));
 		}
-		sections.extend(module.other);
+		sections.extend(other);
 		elements::Module::new(sections)
 	}
 }
@@ -388,10 +401,30 @@ impl<F> ModuleBuilder<F> where F: Invoke<elements::Module> {
 
 	/// Add data segment to the builder
 	pub fn with_data_segment(mut self, segment: elements::DataSegment) -> Self {
-		self.module.data.entries_mut().push(segment);
+		self.push_data_segment(segment);
+		self
+	}
+
+	/// Push data segment, returning its index (as used by `memory.init` or `array.new_data`)
+	pub fn push_data_segment(&mut self, segment: elements::DataSegment) -> u32 {
+		let entries = self.module.data.entries_mut();
+		entries.push(segment);
+		(entries.len() - 1) as u32
+	}
+
+	/// Add element segment to the builder
+	pub fn with_element_segment(mut self, segment: elements::ElementSegment) -> Self {
+		self.push_element_segment(segment);
 		self
 	}
 
+	/// Push element segment, returning its index (as used by `table.init` or `array.new_elem`)
+	pub fn push_element_segment(&mut self, segment: elements::ElementSegment) -> u32 {
+		let entries = self.module.element.entries_mut();
+		entries.push(segment);
+		(entries.len() - 1) as u32
+	}
+
 	/// Data entry builder
 	pub fn data(self) -> data::DataSegmentBuilder<Self> {
 		data::DataSegmentBuilder::with_callback(self)
@@ -582,6 +615,59 @@ mod tests {
 		assert_eq!(module.data_section().expect("data section to exist").entries().len(), 1);
 	}
 
+	#[test]
+	fn passive_segments() {
+		let mut builder = module()
+			.data()
+				.offset(elements::Instruction::I32Const(16))
+				.value(vec![0u8, 15])
+				.build();
+		let data_index = builder.push_data_segment(
+			super::data::DataSegmentBuilder::new().passive().value(b"hello".to_vec()).build()
+		);
+		let elem_index = builder.push_element_segment(
+			elements::ElementSegment::new(0, None, vec![0, 1], true)
+		);
+		assert_eq!(data_index, 1);
+		assert_eq!(elem_index, 0);
+
+		let module = builder.build();
+		let data = &module.data_section().expect("data section to exist").entries()[1];
+		assert!(data.passive());
+		assert_eq!(data.offset(), &None);
+		assert_eq!(module.elements_section().expect("element section to exist").entries().len(), 1);
+	}
+
+	#[test]
+	fn data_count() {
+		use crate::elements::Instruction::*;
+
+		let mut builder = module()
+			.memory().with_min(1).build()
+			.function()
+				.signature().build()
+				.body()
+					.with_instructions(elements::Instructions::new(vec![
+						I32Const(0), I32Const(0), I32Const(5), MemoryInit(0, 0), End,
+					]))
+					.build()
+				.build();
+		builder.push_data_segment(
+			super::data::DataSegmentBuilder::new().passive().value(b"hello".to_vec()).build()
+		);
+		let module = builder.build();
+		assert!(elements::validate::validate(&module).is_ok());
+
+		let buf = elements::serialize(module.clone()).expect("module to serialize");
+		let reparsed: elements::Module = elements::deserialize_buffer(&buf).expect("module to deserialize");
+		assert!(reparsed.sections().iter().any(|s| *s == elements::Section::DataCount(1)));
+
+		// An explicit data count section is replaced by the derived one.
+		let module = super::from_module(module).with_section(elements::Section::DataCount(7)).build();
+		assert!(elements::serialize(module.clone()).is_ok());
+		assert!(elements::validate::validate(&module).is_ok());
+	}
+
 	#[test]
 	fn reuse_types() {
 		let module = module()
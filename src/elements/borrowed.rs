@@ -0,0 +1,505 @@
+//! Borrowed view of a module that does not copy its contents.
+
+use crate::rust::{str, vec::Vec, borrow::ToOwned};
+use crate::io::{self, Read};
+use super::{
+	deserialize_buffer, Deserialize, Error, Context, Module, VarUint7, VarUint32, Uint32, External,
+	Internal, InitExpr, ImportEntry, ExportEntry, DataSegment, CustomSection,
+};
+use super::module::WASM_MAGIC_NUMBER;
+use super::segment::{FLAG_MEMZERO, FLAG_PASSIVE, FLAG_MEM_NONZERO};
+use super::name_section::{NAME_TYPE_MODULE, NAME_TYPE_FUNCTION};
+
+const IMPORT_SECTION_ID: u8 = 2;
+const EXPORT_SECTION_ID: u8 = 7;
+const DATA_SECTION_ID: u8 = 11;
+
+/// Module parsed without copying, borrowing from the input buffer.
+///
+/// Only the section layout is decoded up front. Entries of sections are
+/// decoded when requested, and the whole module can be decoded into an
+/// owned [`Module`] with [`to_module`](ModuleRef::to_module).
+#[derive(Debug, Clone)]
+pub struct ModuleRef<'a> {
+	bytes: &'a [u8],
+	version: u32,
+	sections: Vec<SectionRef<'a>>,
+}
+
+/// Section of a borrowed module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionRef<'a> {
+	id: u8,
+	offset: usize,
+	payload_offset: usize,
+	payload: &'a [u8],
+}
+
+/// Custom section borrowed from the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CustomSectionRef<'a> {
+	name: &'a str,
+	payload: &'a [u8],
+}
+
+/// Import entry borrowed from the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRef<'a> {
+	module: &'a str,
+	field: &'a str,
+	external: External,
+}
+
+/// Export entry borrowed from the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportRef<'a> {
+	field: &'a str,
+	internal: Internal,
+}
+
+/// Data segment borrowed from the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataSegmentRef<'a> {
+	index: u32,
+	offset: Option<InitExpr>,
+	value: &'a [u8],
+	passive: bool,
+}
+
+impl<'a> ModuleRef<'a> {
+	/// Parse the section layout of the module in the buffer.
+	pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+		let mut reader = SliceReader::new(bytes, 0);
+		if reader.bytes(4).map_err(|_| Error::InvalidMagic)? != WASM_MAGIC_NUMBER {
+			return Err(Error::InvalidMagic);
+		}
+		let version: u32 = Uint32::deserialize(&mut reader)?.into();
+		if version != 1 {
+			return Err(Error::UnsupportedVersion(version));
+		}
+
+		let mut sections = Vec::new();
+		let mut last_section_order = 0;
+		while !reader.is_empty() {
+			let offset = reader.pos;
+			let id: u8 = VarUint7::deserialize(&mut reader)?.into();
+			let located = |error: Error| error.located(Context::Section(id), Some(offset));
+			let order = section_order(id).ok_or_else(|| located(Error::InvalidSectionId(id)))?;
+			if order != 0 {
+				if last_section_order > order {
+					return Err(located(Error::SectionsOutOfOrder));
+				} else if last_section_order == order {
+					return Err(located(Error::DuplicatedSections(order)));
+				}
+				last_section_order = order;
+			}
+			let size: u32 = VarUint32::deserialize(&mut reader).map_err(located)?.into();
+			let payload_offset = reader.pos;
+			let payload = reader.bytes(size as usize).map_err(located)?;
+			sections.push(SectionRef { id, offset, payload_offset, payload });
+		}
+
+		Ok(ModuleRef { bytes, version, sections })
+	}
+
+	/// Version of the module.
+	pub fn version(&self) -> u32 {
+		self.version
+	}
+
+	/// Sections in the order of the input.
+	pub fn sections(&self) -> &[SectionRef<'a>] {
+		&self.sections
+	}
+
+	/// First section with the given id, if any.
+	pub fn section(&self, id: u8) -> Option<&SectionRef<'a>> {
+		self.sections.iter().find(|section| section.id == id)
+	}
+
+	/// Decode the whole module into an owned one.
+	pub fn to_module(&self) -> Result<Module, Error> {
+		deserialize_buffer(self.bytes)
+	}
+
+	/// Custom sections, including the name section.
+	pub fn custom_sections(&self) -> Result<Vec<CustomSectionRef<'a>>, Error> {
+		self.sections.iter()
+			.filter(|section| section.id == 0)
+			.map(|section| section.decode(|reader| Ok(CustomSectionRef {
+				name: reader.str()?,
+				payload: reader.rest(),
+			})))
+			.collect()
+	}
+
+	/// Entries of the import section.
+	pub fn imports(&self) -> Result<Vec<ImportRef<'a>>, Error> {
+		self.entries(IMPORT_SECTION_ID, |reader| Ok(ImportRef {
+			module: reader.str()?,
+			field: reader.str()?,
+			external: External::deserialize(reader)?,
+		}))
+	}
+
+	/// Entries of the export section.
+	pub fn exports(&self) -> Result<Vec<ExportRef<'a>>, Error> {
+		self.entries(EXPORT_SECTION_ID, |reader| Ok(ExportRef {
+			field: reader.str()?,
+			internal: Internal::deserialize(reader)?,
+		}))
+	}
+
+	/// Entries of the data section.
+	pub fn data_segments(&self) -> Result<Vec<DataSegmentRef<'a>>, Error> {
+		self.entries(DATA_SECTION_ID, |reader| {
+			let flags: u32 = VarUint32::deserialize(reader)?.into();
+			let index = match flags {
+				FLAG_MEMZERO | FLAG_PASSIVE => 0,
+				FLAG_MEM_NONZERO => VarUint32::deserialize(reader)?.into(),
+				_ => return Err(Error::InvalidSegmentFlags(flags)),
+			};
+			let offset = if flags == FLAG_PASSIVE {
+				None
+			} else {
+				Some(InitExpr::deserialize(reader)?)
+			};
+			let len: u32 = VarUint32::deserialize(reader)?.into();
+			Ok(DataSegmentRef { index, offset, value: reader.bytes(len as usize)?, passive: flags == FLAG_PASSIVE })
+		})
+	}
+
+	/// Module name from the name section, if any.
+	pub fn module_name(&self) -> Result<Option<&'a str>, Error> {
+		match self.name_subsection(NAME_TYPE_MODULE)? {
+			Some(mut reader) => Ok(Some(reader.str()?)),
+			None => Ok(None),
+		}
+	}
+
+	/// Function names from the name section, by function index.
+	pub fn function_names(&self) -> Result<Vec<(u32, &'a str)>, Error> {
+		let mut reader = match self.name_subsection(NAME_TYPE_FUNCTION)? {
+			Some(reader) => reader,
+			None => return Ok(Vec::new()),
+		};
+		let count: u32 = VarUint32::deserialize(&mut reader)?.into();
+		let mut names = Vec::new();
+		for _ in 0..count {
+			let idx: u32 = VarUint32::deserialize(&mut reader)?.into();
+			names.push((idx, reader.str()?));
+		}
+		Ok(names)
+	}
+
+	/// Payload of the name subsection with the given id.
+	fn name_subsection(&self, id: u8) -> Result<Option<SliceReader<'a>>, Error> {
+		let section = match self.custom_sections()?.into_iter().find(|section| section.name == "name") {
+			Some(section) => section,
+			None => return Ok(None),
+		};
+		let mut reader = SliceReader::new(section.payload, 0);
+		while !reader.is_empty() {
+			let name_type: u8 = VarUint7::deserialize(&mut reader)?.into();
+			let size: u32 = VarUint32::deserialize(&mut reader)?.into();
+			let start = reader.pos;
+			let payload = reader.bytes(size as usize)?;
+			if name_type == id {
+				return Ok(Some(SliceReader::new(payload, start)));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Decode the entries of the section with the given id, if any.
+	fn entries<T>(&self, id: u8, mut entry: impl FnMut(&mut SliceReader<'a>) -> Result<T, Error>)
+		-> Result<Vec<T>, Error>
+	{
+		let section = match self.section(id) {
+			Some(section) => section,
+			None => return Ok(Vec::new()),
+		};
+		section.decode(|reader| {
+			let count: u32 = VarUint32::deserialize(reader)?.into();
+			let mut entries = Vec::new();
+			for idx in 0..count {
+				let start = reader.position();
+				entries.push(entry(reader).map_err(|e| e.located(Context::Entry(idx), start))?);
+			}
+			Ok(entries)
+		})
+	}
+}
+
+impl<'a> SectionRef<'a> {
+	/// Id of the section.
+	pub fn id(&self) -> u8 {
+		self.id
+	}
+
+	/// Offset of the section from the start of the module.
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+
+	/// Payload of the section.
+	pub fn payload(&self) -> &'a [u8] {
+		self.payload
+	}
+
+	/// Decode the whole payload, locating errors in this section.
+	fn decode<T>(&self, f: impl FnOnce(&mut SliceReader<'a>) -> Result<T, Error>) -> Result<T, Error> {
+		let mut reader = SliceReader::new(self.payload, self.payload_offset);
+		let result = f(&mut reader).and_then(|value| if reader.is_empty() {
+			Ok(value)
+		} else {
+			Err(Error::InconsistentLength { expected: self.payload.len(), actual: reader.pos })
+		});
+		result.map_err(|e| e.located(Context::Section(self.id), Some(self.offset)))
+	}
+}
+
+impl<'a> CustomSectionRef<'a> {
+	/// Name of the custom section.
+	pub fn name(&self) -> &'a str {
+		self.name
+	}
+
+	/// Payload of the custom section.
+	pub fn payload(&self) -> &'a [u8] {
+		self.payload
+	}
+}
+
+impl<'a> ImportRef<'a> {
+	/// Module reference of the import entry.
+	pub fn module(&self) -> &'a str {
+		self.module
+	}
+
+	/// Field reference of the import entry.
+	pub fn field(&self) -> &'a str {
+		self.field
+	}
+
+	/// Local binidng of the import entry.
+	pub fn external(&self) -> &External {
+		&self.external
+	}
+}
+
+impl<'a> ExportRef<'a> {
+	/// Public name.
+	pub fn field(&self) -> &'a str {
+		self.field
+	}
+
+	/// Internal reference of the export entry.
+	pub fn internal(&self) -> &Internal {
+		&self.internal
+	}
+}
+
+impl<'a> DataSegmentRef<'a> {
+	/// Linear memory index.
+	pub fn index(&self) -> u32 {
+		self.index
+	}
+
+	/// An i32 initializer expression that computes the offset at which to place the data.
+	///
+	/// `None` for passive segments.
+	pub fn offset(&self) -> &Option<InitExpr> {
+		&self.offset
+	}
+
+	/// Initial value of the data segment.
+	pub fn value(&self) -> &'a [u8] {
+		self.value
+	}
+
+	/// Whether or not this data segment is "passive".
+	pub fn passive(&self) -> bool {
+		self.passive
+	}
+}
+
+impl<'a> From<CustomSectionRef<'a>> for CustomSection {
+	fn from(section: CustomSectionRef<'a>) -> Self {
+		CustomSection::new(section.name.to_owned(), section.payload.to_vec())
+	}
+}
+
+impl<'a> From<ImportRef<'a>> for ImportEntry {
+	fn from(entry: ImportRef<'a>) -> Self {
+		ImportEntry::new(entry.module.to_owned(), entry.field.to_owned(), entry.external)
+	}
+}
+
+impl<'a> From<ExportRef<'a>> for ExportEntry {
+	fn from(entry: ExportRef<'a>) -> Self {
+		ExportEntry::new(entry.field.to_owned(), entry.internal)
+	}
+}
+
+impl<'a> From<DataSegmentRef<'a>> for DataSegment {
+	fn from(segment: DataSegmentRef<'a>) -> Self {
+		DataSegment::new(segment.index, segment.offset, segment.value.to_vec(), segment.passive)
+	}
+}
+
+/// Order of the section with the given id, `None` for unknown ids.
+pub(crate) fn section_order(id: u8) -> Option<u8> {
+	match id {
+		0..=9 => Some(id),
+		// The data count section comes before the code section.
+		10 => Some(0x0b),
+		11 => Some(0x0c),
+		12 => Some(0x0a),
+		_ => None,
+	}
+}
+
+/// Reader over a byte slice that hands out borrowed parts of it.
+pub(crate) struct SliceReader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+	/// Offset of the slice from the start of the module.
+	base: usize,
+}
+
+impl<'a> SliceReader<'a> {
+	pub(crate) fn new(bytes: &'a [u8], base: usize) -> Self {
+		SliceReader { bytes, pos: 0, base }
+	}
+
+	/// Number of bytes read so far.
+	pub(crate) fn consumed(&self) -> usize {
+		self.pos
+	}
+
+	fn is_empty(&self) -> bool {
+		self.pos == self.bytes.len()
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.bytes.len() - self.pos < len {
+			return Err(Error::UnexpectedEof);
+		}
+		let bytes = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(bytes)
+	}
+
+	fn rest(&mut self) -> &'a [u8] {
+		let bytes = &self.bytes[self.pos..];
+		self.pos = self.bytes.len();
+		bytes
+	}
+
+	fn str(&mut self) -> Result<&'a str, Error> {
+		let len: u32 = VarUint32::deserialize(self)?.into();
+		str::from_utf8(self.bytes(len as usize)?).map_err(|_| Error::NonUtf8String)
+	}
+}
+
+impl<'a> io::Read for SliceReader<'a> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+		let bytes = self.bytes(buf.len()).map_err(|_| io::Error::UnexpectedEof)?;
+		buf.copy_from_slice(bytes);
+		Ok(())
+	}
+
+	fn position(&self) -> Option<usize> {
+		Some(self.base + self.pos)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		deserialize_buffer, serialize, Module, Section, ImportSection, ExportSection, DataSection,
+		ImportEntry, ExportEntry, DataSegment, CustomSection, External, Internal, InitExpr,
+		Instruction, MemoryType, Error, Context,
+	};
+	use super::ModuleRef;
+
+	#[test]
+	fn borrowed() {
+		let offset = InitExpr::new(vec![Instruction::I32Const(16), Instruction::End]);
+		let module = Module::new(vec![
+			Section::Import(ImportSection::with_entries(vec![
+				ImportEntry::new("env".into(), "memory".into(), External::Memory(MemoryType::new(1, None, false))),
+			])),
+			Section::Export(ExportSection::with_entries(vec![
+				ExportEntry::new("memory".into(), Internal::Memory(0)),
+			])),
+			Section::Data(DataSection::with_entries(vec![
+				DataSegment::new(0, Some(offset), b"hello".to_vec(), false),
+				DataSegment::new(0, None, b"world".to_vec(), true),
+			])),
+			Section::Custom(CustomSection::new("extra".into(), vec![1, 2, 3])),
+		]);
+		let wasm = serialize(module.clone()).expect("module to serialize");
+
+		let borrowed = ModuleRef::parse(&wasm).expect("module to parse");
+		assert_eq!(borrowed.sections().len(), 4);
+
+		let imports = borrowed.imports().expect("imports to decode");
+		assert_eq!((imports[0].module(), imports[0].field()), ("env", "memory"));
+		let exports = borrowed.exports().expect("exports to decode");
+		assert_eq!(ExportEntry::from(exports[0]), module.export_section().unwrap().entries()[0]);
+
+		let segments = borrowed.data_segments().expect("data segments to decode");
+		assert_eq!(segments[0].value(), b"hello");
+		assert!(segments[1].passive());
+		// The segment value points into the input buffer.
+		let start = wasm.as_ptr() as usize;
+		let value = segments[0].value().as_ptr() as usize;
+		assert!(value > start && value < start + wasm.len());
+		assert_eq!(DataSegment::from(segments[1].clone()), module.data_section().unwrap().entries()[1]);
+
+		let custom = borrowed.custom_sections().expect("custom sections to decode");
+		assert_eq!((custom[0].name(), custom[0].payload()), ("extra", &[1u8, 2, 3][..]));
+		assert_eq!(borrowed.module_name().expect("names to decode"), None);
+
+		assert_eq!(borrowed.to_module().expect("module to decode"), module);
+	}
+
+	#[test]
+	fn names() {
+		let wasm = std::fs::read("./res/cases/v1/with_names.wasm").expect("file to read");
+		let borrowed = ModuleRef::parse(&wasm).expect("module to parse");
+		let names = borrowed.function_names().expect("names to decode");
+		assert_eq!(names[0], (0, "elog"));
+		assert_eq!(
+			names.iter().find(|&&(idx, _)| idx == 11).map(|&(_, name)| name),
+			Some("_ZN48_$LT$pwasm_token_contract..Endpoint$LT$T$GT$$GT$3new17hc3ace6dea0978cd9E"),
+		);
+
+		let owned: Module = deserialize_buffer(&wasm).expect("module to deserialize");
+		assert_eq!(borrowed.to_module().expect("module to decode"), owned);
+	}
+
+	#[test]
+	fn errors() {
+		let module = Module::new(vec![
+			Section::Export(ExportSection::with_entries(vec![
+				ExportEntry::new("a".into(), Internal::Function(0)),
+				ExportEntry::new("b".into(), Internal::Function(1)),
+			])),
+		]);
+		let mut wasm = serialize(module).expect("module to serialize");
+		// Kind of the second export.
+		let last = wasm.len() - 2;
+		wasm[last] = 0x7f;
+
+		let borrowed = ModuleRef::parse(&wasm).expect("module to parse");
+		let err = borrowed.exports().unwrap_err();
+		assert!(matches!(*err.kind(), Error::UnknownInternalKind(0x7f)));
+		assert_eq!(err.context(), &[Context::Section(7), Context::Entry(1)]);
+		assert_eq!(err.offset(), Some(last - 2));
+
+		assert!(matches!(ModuleRef::parse(&wasm[..wasm.len() - 1]).unwrap_err().kind(), Error::UnexpectedEof));
+	}
+}
@@ -0,0 +1,224 @@
+use crate::rust::{collections::BTreeMap, vec::Vec};
+use super::{
+	serialize, Error, TypeSection, Type, RecGroup, ValueType, RefType, HeapType,
+	StorageType, FieldType,
+};
+
+/// Registry of canonical type identifiers under isorecursive type equivalence.
+///
+/// Two type definitions get the same canonical id if their recursion groups
+/// are structurally identical and they are at the same position in the group.
+/// References to types outside of a group are compared by their canonical ids,
+/// so types from different modules can be compared by canonicalizing the
+/// type sections with the same registry.
+#[derive(Debug, Clone, Default)]
+pub struct TypeCanonicalizer {
+	groups: BTreeMap<Vec<u8>, u32>,
+	next: u32,
+}
+
+impl TypeCanonicalizer {
+	/// New empty registry.
+	pub fn new() -> Self {
+		TypeCanonicalizer::default()
+	}
+
+	/// Number of distinct canonical types seen so far.
+	pub fn len(&self) -> usize {
+		self.next as usize
+	}
+
+	/// Has no type been canonicalized yet.
+	pub fn is_empty(&self) -> bool {
+		self.next == 0
+	}
+
+	/// Canonical ids of all types in the section, indexed by type index.
+	///
+	/// Fails if a type refers to a type index past its own recursion group.
+	pub fn canonicalize(&mut self, section: &TypeSection) -> Result<Vec<u32>, Error> {
+		let total = section.types().len() as u32;
+		let mut ids = Vec::with_capacity(total as usize);
+		while (ids.len() as u32) < total {
+			let group = section.rec_group(ids.len() as u32);
+			let base = self.canonicalize_group(section, group, &ids)?;
+			ids.extend(base..base + group.len());
+		}
+		Ok(ids)
+	}
+
+	/// Canonical id of the first type of the group, the rest of the group follows it.
+	fn canonicalize_group(&mut self, section: &TypeSection, group: RecGroup, ids: &[u32])
+		-> Result<u32, Error>
+	{
+		// References inside the group become relative indices, references to
+		// earlier types become their canonical id offset past the group length.
+		let remap = |idx: u32| -> Result<u32, Error> {
+			if group.contains(idx) {
+				Ok(idx - group.start())
+			} else if let Some(&id) = ids.get(idx as usize) {
+				Ok(id + group.len())
+			} else {
+				Err(Error::Other("Type refers to a type defined after its recursion group"))
+			}
+		};
+
+		let mut key = TypeSection::default();
+		let mut types = Vec::with_capacity(group.len() as usize);
+		for (offset, idx) in (group.start()..group.end()).enumerate() {
+			let mut ty = section.types().get(idx as usize)
+				.ok_or(Error::Other("Recursion group does not match the type declarations"))?
+				.clone();
+			remap_type(&mut ty, &remap)?;
+			types.push(ty);
+
+			let mut sub = section.subtype(idx).cloned().unwrap_or_default();
+			for supertype in sub.supertypes_mut() {
+				*supertype = remap(*supertype)?;
+			}
+			key.set_subtype(offset as u32, sub);
+		}
+		key.push_rec_group(types);
+		let key = serialize(key)?;
+
+		let next = &mut self.next;
+		Ok(*self.groups.entry(key).or_insert_with(|| {
+			let base = *next;
+			*next += group.len();
+			base
+		}))
+	}
+}
+
+impl TypeSection {
+	/// Canonical ids of all types in the section under isorecursive type equivalence.
+	///
+	/// Types with the same id are equivalent. See [`TypeCanonicalizer`] to compare
+	/// types across several sections.
+	pub fn canonical_ids(&self) -> Result<Vec<u32>, Error> {
+		TypeCanonicalizer::new().canonicalize(self)
+	}
+}
+
+fn remap_heap_type(ht: &mut HeapType, remap: &impl Fn(u32) -> Result<u32, Error>) -> Result<(), Error> {
+	if let HeapType::Index(idx) = ht {
+		*idx = remap(*idx)?;
+	}
+	Ok(())
+}
+
+fn remap_value_type(vt: &mut ValueType, remap: &impl Fn(u32) -> Result<u32, Error>) -> Result<(), Error> {
+	if let ValueType::Ref(r) = vt {
+		let mut ht = r.heap_type();
+		remap_heap_type(&mut ht, remap)?;
+		*r = RefType::new(r.is_nullable(), ht);
+	}
+	Ok(())
+}
+
+fn remap_field_type(ft: &mut FieldType, remap: &impl Fn(u32) -> Result<u32, Error>) -> Result<(), Error> {
+	if let StorageType::Value(vt) = ft.storage_type_mut() {
+		remap_value_type(vt, remap)?;
+	}
+	Ok(())
+}
+
+fn remap_type(ty: &mut Type, remap: &impl Fn(u32) -> Result<u32, Error>) -> Result<(), Error> {
+	match ty {
+		Type::Function(f) => {
+			for param in f.params_mut() {
+				remap_value_type(param, remap)?;
+			}
+			for result in f.results_mut() {
+				remap_value_type(result, remap)?;
+			}
+		},
+		Type::Struct(s) => {
+			for field in s.fields_mut() {
+				remap_field_type(field, remap)?;
+			}
+		},
+		Type::Array(a) => remap_field_type(a.elem_mut(), remap)?,
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		TypeSection, Type, ValueType, RefType, HeapType, FunctionType, StructType, FieldType,
+		NumType, SubType,
+	};
+	use super::TypeCanonicalizer;
+
+	fn ref_to(idx: u32) -> ValueType {
+		RefType::new(true, HeapType::Index(idx)).into()
+	}
+
+	fn list(next: u32) -> Type {
+		StructType::new(vec![
+			FieldType::new(ValueType::from(NumType::I32), false),
+			FieldType::new(ref_to(next), true),
+		]).into()
+	}
+
+	#[test]
+	fn identical_types() {
+		let func = Type::Function(FunctionType::new(vec![ValueType::from(NumType::I32)], Vec::new()));
+		let mut section = TypeSection::with_types(vec![func.clone(), list(0), func]);
+		// A recursive list type referring to itself.
+		section.push_rec_group(vec![list(3)]);
+		// The same list, but in a recursion group of two.
+		section.push_rec_group(vec![list(4), list(4)]);
+
+		let ids = section.canonical_ids().expect("types to canonicalize");
+		assert_eq!(ids[0], ids[2]);
+		assert_ne!(ids[0], ids[1]);
+		// Type 1 refers to the function type, not itself.
+		assert_ne!(ids[1], ids[3]);
+		assert_ne!(ids[3], ids[4]);
+		assert_ne!(ids[4], ids[5]);
+	}
+
+	#[test]
+	fn subtypes_are_distinct() {
+		let ty = Type::Function(FunctionType::default());
+		let mut section = TypeSection::with_types(vec![ty.clone(), ty.clone(), ty.clone(), ty]);
+		section.set_subtype(1, SubType::new(true, vec![]));
+		section.set_subtype(2, SubType::new(false, vec![]));
+		section.set_subtype(3, SubType::new(false, vec![2]));
+
+		let ids = section.canonical_ids().expect("types to canonicalize");
+		assert_eq!(ids[0], ids[1]);
+		assert_ne!(ids[0], ids[2]);
+		assert_ne!(ids[2], ids[3]);
+	}
+
+	#[test]
+	fn across_sections() {
+		let mut first = TypeSection::default();
+		first.push_rec_group(vec![list(0)]);
+		let mut second = TypeSection::with_types(vec![FunctionType::default().into()]);
+		second.push_rec_group(vec![list(1)]);
+
+		let mut canonicalizer = TypeCanonicalizer::new();
+		let first = canonicalizer.canonicalize(&first).expect("types to canonicalize");
+		let second = canonicalizer.canonicalize(&second).expect("types to canonicalize");
+		assert_eq!(first[0], second[1]);
+		assert_eq!(canonicalizer.len(), 2);
+	}
+
+	#[test]
+	fn forward_reference() {
+		let section = TypeSection::with_types(vec![list(1), list(1)]);
+		assert!(section.canonical_ids().is_err());
+	}
+
+	#[test]
+	fn truncated_rec_group() {
+		let mut section = TypeSection::default();
+		section.push_rec_group(vec![list(0), list(1)]);
+		section.types_mut().pop();
+		assert!(section.canonical_ids().is_err());
+	}
+}
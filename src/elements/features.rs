@@ -0,0 +1,683 @@
+//! Post-MVP proposals and their use by modules.
+
+use crate::rust::{fmt, vec::Vec, collections::BTreeMap};
+use crate::io;
+use super::{
+	Error, Module, Section, TypeSection, Type, ValueType, NumType, RefType, HeapType, BlockType, TableType,
+	MemoryType, GlobalType, External, Internal, Instruction, InitExpr, ElementMode, ElementItems,
+};
+use super::section::BodyDecoding;
+
+/// Post-MVP proposal of the WebAssembly specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+	/// Sign extension operators.
+	SignExt,
+	/// Import and export of mutable globals.
+	MutableGlobal,
+	/// Bulk memory operations and passive segments.
+	BulkMemory,
+	/// Reference types and multiple tables.
+	ReferenceTypes,
+	/// Fixed-width 128-bit SIMD.
+	Simd,
+	/// Threads, atomics and shared memories.
+	Threads,
+	/// Typed function references.
+	FunctionReferences,
+	/// Garbage collection.
+	Gc,
+	/// Multiple results of functions and blocks.
+	MultiValue,
+	/// Multiple memories.
+	MultiMemory,
+	/// Memories with 64-bit addresses.
+	Memory64,
+}
+
+impl Feature {
+	/// Name of the proposal.
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Feature::SignExt => "sign-extension-ops",
+			Feature::MutableGlobal => "mutable-global",
+			Feature::BulkMemory => "bulk-memory",
+			Feature::ReferenceTypes => "reference-types",
+			Feature::Simd => "simd",
+			Feature::Threads => "threads",
+			Feature::FunctionReferences => "function-references",
+			Feature::Gc => "gc",
+			Feature::MultiValue => "multi-value",
+			Feature::MultiMemory => "multi-memory",
+			Feature::Memory64 => "memory64",
+		}
+	}
+}
+
+impl fmt::Display for Feature {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+/// Place in a module where a proposal is used.
+///
+/// Functions, tables, memories and globals are numbered in their index spaces,
+/// which include imports. Other entries are numbered within their section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+	/// Entry of the type section.
+	Type(u32),
+	/// Entry of the import section.
+	Import(u32),
+	/// Table definition.
+	Table(u32),
+	/// Memory definition.
+	Memory(u32),
+	/// Global definition, including its initializer.
+	Global(u32),
+	/// Entry of the export section.
+	Export(u32),
+	/// Element segment, including its offset.
+	ElementSegment(u32),
+	/// Data segment, including its offset.
+	DataSegment(u32),
+	/// The data count section.
+	DataCount,
+	/// Local declarations of a function body.
+	Locals(u32),
+	/// Instruction of a function body.
+	Instruction {
+		/// Function index.
+		func: u32,
+		/// Index of the instruction in the function body.
+		instruction: u32,
+	},
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Location::Type(idx) => write!(f, "type {}", idx),
+			Location::Import(idx) => write!(f, "import {}", idx),
+			Location::Table(idx) => write!(f, "table {}", idx),
+			Location::Memory(idx) => write!(f, "memory {}", idx),
+			Location::Global(idx) => write!(f, "global {}", idx),
+			Location::Export(idx) => write!(f, "export {}", idx),
+			Location::ElementSegment(idx) => write!(f, "element segment {}", idx),
+			Location::DataSegment(idx) => write!(f, "data segment {}", idx),
+			Location::DataCount => write!(f, "data count section"),
+			Location::Locals(func) => write!(f, "locals of function {}", func),
+			Location::Instruction { func, instruction } => {
+				write!(f, "function {}, instruction {}", func, instruction)
+			},
+		}
+	}
+}
+
+/// Set of enabled proposals.
+///
+/// The default set enables all proposals supported by the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+	/// Sign extension operators.
+	pub sign_ext: bool,
+	/// Import and export of mutable globals.
+	pub mutable_global: bool,
+	/// Bulk memory operations and passive segments.
+	pub bulk_memory: bool,
+	/// Reference types and multiple tables.
+	pub reference_types: bool,
+	/// Fixed-width 128-bit SIMD.
+	pub simd: bool,
+	/// Threads, atomics and shared memories.
+	pub threads: bool,
+	/// Typed function references.
+	pub function_references: bool,
+	/// Garbage collection.
+	pub gc: bool,
+	/// Multiple results of functions and blocks.
+	pub multi_value: bool,
+	/// Multiple memories.
+	pub multi_memory: bool,
+	/// Memories with 64-bit addresses.
+	pub memory64: bool,
+}
+
+impl Default for Features {
+	fn default() -> Self {
+		Features::all()
+	}
+}
+
+impl Features {
+	/// Only the WebAssembly MVP, all proposals disabled.
+	pub fn mvp() -> Self {
+		Features {
+			sign_ext: false,
+			mutable_global: false,
+			bulk_memory: false,
+			reference_types: false,
+			simd: false,
+			threads: false,
+			function_references: false,
+			gc: false,
+			multi_value: false,
+			multi_memory: false,
+			memory64: false,
+		}
+	}
+
+	/// All proposals enabled.
+	pub fn all() -> Self {
+		Features {
+			sign_ext: true,
+			mutable_global: true,
+			bulk_memory: true,
+			reference_types: true,
+			simd: true,
+			threads: true,
+			function_references: true,
+			gc: true,
+			multi_value: true,
+			multi_memory: true,
+			memory64: true,
+		}
+	}
+
+	/// Is the proposal enabled.
+	pub fn is_enabled(&self, feature: Feature) -> bool {
+		match feature {
+			Feature::SignExt => self.sign_ext,
+			Feature::MutableGlobal => self.mutable_global,
+			Feature::BulkMemory => self.bulk_memory,
+			Feature::ReferenceTypes => self.reference_types,
+			Feature::Simd => self.simd,
+			Feature::Threads => self.threads,
+			Feature::FunctionReferences => self.function_references,
+			Feature::Gc => self.gc,
+			Feature::MultiValue => self.multi_value,
+			Feature::MultiMemory => self.multi_memory,
+			Feature::Memory64 => self.memory64,
+		}
+	}
+
+	/// Check that the module only uses enabled proposals.
+	pub fn check(&self, module: &Module) -> Result<(), Error> {
+		let mut scanner = Scanner::new(|feature, _| self.require(feature));
+		for section in module.sections() {
+			scanner.section(section)?;
+		}
+		Ok(())
+	}
+
+	fn require(&self, feature: Feature) -> Result<(), Error> {
+		if self.is_enabled(feature) {
+			Ok(())
+		} else {
+			Err(Error::DisabledFeature(feature))
+		}
+	}
+}
+
+/// Deserialize a module from a buffer, rejecting proposals that are not enabled.
+///
+/// Sections are checked as soon as they are decoded.
+pub fn deserialize_with_features(contents: &[u8], features: &Features) -> Result<Module, Error> {
+	let mut reader = io::Cursor::new(contents);
+	let mut scanner = Scanner::new(|feature, _| features.require(feature));
+	let module = Module::deserialize_sections(&mut reader, BodyDecoding::Eager, |section| scanner.section(section))?;
+	if reader.position() != contents.len() {
+		return Err(io::Error::TrailingData.into())
+	}
+	Ok(module)
+}
+
+impl Module {
+	/// Post-MVP proposals used by the module.
+	///
+	/// Each proposal is mapped to the first place in the module where it is used.
+	/// Scanning stops at a malformed lazily decoded function body.
+	pub fn used_features(&self) -> BTreeMap<Feature, Location> {
+		let mut used = BTreeMap::new();
+		let mut scanner = Scanner::new(|feature, location| {
+			used.entry(feature).or_insert(location);
+			Ok(())
+		});
+		for section in self.sections() {
+			// The callback never fails, only malformed function bodies do.
+			if scanner.section(section).is_err() {
+				break;
+			}
+		}
+		used
+	}
+}
+
+/// Walks module sections and reports every use of a post-MVP proposal.
+struct Scanner<F> {
+	sink: F,
+	/// Location of the entry being scanned.
+	at: Location,
+	funcs: u32,
+	tables: usize,
+	memories: u32,
+	/// Mutability of globals seen so far.
+	globals: Vec<bool>,
+}
+
+impl<F: FnMut(Feature, Location) -> Result<(), Error>> Scanner<F> {
+	fn new(sink: F) -> Self {
+		Scanner {
+			sink,
+			at: Location::Type(0),
+			funcs: 0,
+			tables: 0,
+			memories: 0,
+			globals: Vec::new(),
+		}
+	}
+
+	fn report(&mut self, feature: Feature) -> Result<(), Error> {
+		(self.sink)(feature, self.at)
+	}
+
+	fn section(&mut self, section: &Section) -> Result<(), Error> {
+		match *section {
+			Section::Type(ref types) => self.types(types)?,
+			Section::Import(ref imports) => {
+				for (idx, entry) in imports.entries().iter().enumerate() {
+					self.at = Location::Import(idx as u32);
+					match *entry.external() {
+						External::Function(_) => self.funcs += 1,
+						External::Table(ref table) => self.table(table)?,
+						External::Memory(ref memory) => self.memory(memory)?,
+						External::Global(ref global) => {
+							if global.is_mutable() {
+								self.report(Feature::MutableGlobal)?;
+							}
+							self.global(global)?;
+						},
+					}
+				}
+			},
+			Section::Table(ref tables) => {
+				for table in tables.entries() {
+					self.at = Location::Table(self.tables as u32);
+					self.table(table)?;
+				}
+			},
+			Section::Memory(ref memories) => {
+				for memory in memories.entries() {
+					self.at = Location::Memory(self.memories);
+					self.memory(memory)?;
+				}
+			},
+			Section::Global(ref globals) => {
+				for entry in globals.entries() {
+					self.at = Location::Global(self.globals.len() as u32);
+					self.global(entry.global_type())?;
+					self.init_expr(entry.init_expr())?;
+				}
+			},
+			Section::Export(ref exports) => {
+				for (idx, entry) in exports.entries().iter().enumerate() {
+					self.at = Location::Export(idx as u32);
+					match *entry.internal() {
+						Internal::Global(idx) if self.globals.get(idx as usize) == Some(&true) => {
+							self.report(Feature::MutableGlobal)?;
+						},
+						Internal::Table(idx) if idx != 0 => self.report(Feature::ReferenceTypes)?,
+						Internal::Memory(idx) if idx != 0 => self.report(Feature::MultiMemory)?,
+						_ => {},
+					}
+				}
+			},
+			Section::Element(ref elements) => {
+				for (idx, segment) in elements.entries().iter().enumerate() {
+					self.at = Location::ElementSegment(idx as u32);
+					match segment.mode() {
+						ElementMode::Passive => self.report(Feature::BulkMemory)?,
+						ElementMode::Declarative => self.report(Feature::ReferenceTypes)?,
+						ElementMode::Active if segment.index() != 0 => self.report(Feature::ReferenceTypes)?,
+						ElementMode::Active => {},
+					}
+					if segment.elem_type() != RefType::FUNCREF {
+						self.ref_type(segment.elem_type())?;
+					}
+					if let ElementItems::Expressions(ref exprs) = *segment.items() {
+						self.report(Feature::BulkMemory)?;
+						for expr in exprs {
+							self.init_expr(expr)?;
+						}
+					}
+					if let Some(ref offset) = *segment.offset() {
+						self.init_expr(offset)?;
+					}
+				}
+			},
+			Section::DataCount(_) => {
+				self.at = Location::DataCount;
+				self.report(Feature::BulkMemory)?;
+			},
+			Section::Code(ref code) => {
+				for (idx, body) in code.bodies().iter().enumerate() {
+					let func = self.funcs + idx as u32;
+					self.at = Location::Locals(func);
+					for local in body.try_locals()? {
+						self.value_type(local.value_type())?;
+					}
+					for (instruction, op) in body.try_code()?.elements().iter().enumerate() {
+						self.at = Location::Instruction { func, instruction: instruction as u32 };
+						self.instruction(op)?;
+					}
+				}
+			},
+			Section::Data(ref data) => {
+				for (idx, segment) in data.entries().iter().enumerate() {
+					self.at = Location::DataSegment(idx as u32);
+					if segment.passive() {
+						self.report(Feature::BulkMemory)?;
+					} else if segment.index() != 0 {
+						self.report(Feature::MultiMemory)?;
+					}
+					if let Some(ref offset) = *segment.offset() {
+						self.init_expr(offset)?;
+					}
+				}
+			},
+			_ => {},
+		}
+		Ok(())
+	}
+
+	fn types(&mut self, types: &TypeSection) -> Result<(), Error> {
+		if let Some(group) = types.rec_groups().first() {
+			self.at = Location::Type(group.start());
+			self.report(Feature::Gc)?;
+		}
+		for (idx, ty) in types.types().iter().enumerate() {
+			self.at = Location::Type(idx as u32);
+			if types.subtype(idx as u32).is_some() {
+				self.report(Feature::Gc)?;
+			}
+			match *ty {
+				Type::Function(ref f) => {
+					for &param in f.params() {
+						self.value_type(param)?;
+					}
+					if f.results().len() > 1 {
+						self.report(Feature::MultiValue)?;
+					}
+					for &result in f.results() {
+						self.value_type(result)?;
+					}
+				},
+				Type::Struct(_) | Type::Array(_) => self.report(Feature::Gc)?,
+			}
+		}
+		Ok(())
+	}
+
+	fn table(&mut self, table: &TableType) -> Result<(), Error> {
+		self.tables += 1;
+		if self.tables > 1 {
+			self.report(Feature::ReferenceTypes)?;
+		}
+		if table.elem_type() != RefType::FUNCREF {
+			self.ref_type(table.elem_type())?;
+		}
+		Ok(())
+	}
+
+	fn memory(&mut self, memory: &MemoryType) -> Result<(), Error> {
+		self.memories += 1;
+		if self.memories > 1 {
+			self.report(Feature::MultiMemory)?;
+		}
+		if memory.limits().shared() {
+			self.report(Feature::Threads)?;
+		}
+		if memory.index_type() == NumType::I64 {
+			self.report(Feature::Memory64)?;
+		}
+		Ok(())
+	}
+
+	fn global(&mut self, global: &GlobalType) -> Result<(), Error> {
+		self.globals.push(global.is_mutable());
+		self.value_type(global.content_type())
+	}
+
+	fn init_expr(&mut self, expr: &InitExpr) -> Result<(), Error> {
+		for instruction in expr.code() {
+			self.instruction(instruction)?;
+		}
+		Ok(())
+	}
+
+	fn value_type(&mut self, ty: ValueType) -> Result<(), Error> {
+		match ty {
+			ValueType::Num(_) => Ok(()),
+			ValueType::V128 => self.report(Feature::Simd),
+			ValueType::Ref(r) => self.ref_type(r),
+		}
+	}
+
+	fn ref_type(&mut self, ty: RefType) -> Result<(), Error> {
+		match (ty.is_nullable(), ty.heap_type()) {
+			(true, HeapType::Func) | (true, HeapType::Extern) => self.report(Feature::ReferenceTypes),
+			(false, HeapType::Func) | (false, HeapType::Extern) | (_, HeapType::Index(_)) => {
+				self.report(Feature::FunctionReferences)
+			},
+			_ => self.report(Feature::Gc),
+		}
+	}
+
+	fn heap_type(&mut self, ty: HeapType) -> Result<(), Error> {
+		self.ref_type(RefType::new(true, ty))
+	}
+
+	fn instruction(&mut self, instruction: &Instruction) -> Result<(), Error> {
+		use self::Instruction::*;
+
+		let memory = match *instruction {
+			CurrentMemory(mem) | GrowMemory(mem) | MemoryInit(_, mem) | MemoryFill(mem) => mem,
+			MemoryCopy(dst, src) => dst.max(src),
+			_ => instruction.memarg().map_or(0, |memarg| memarg.memory),
+		};
+		if memory != 0 {
+			self.report(Feature::MultiMemory)?;
+		}
+
+		let feature = match *instruction {
+			Block(BlockType::Value(ty)) | Loop(BlockType::Value(ty)) | If(BlockType::Value(ty)) => {
+				return self.value_type(ty);
+			},
+			Block(BlockType::TypeIndex(_)) | Loop(BlockType::TypeIndex(_)) | If(BlockType::TypeIndex(_)) => {
+				Feature::MultiValue
+			},
+			CallIndirect(_, table) if table != 0 => Feature::ReferenceTypes,
+			RefNull(ty) => return self.heap_type(ty),
+			RefIsNull | RefFunc(_) | GetTable(_) | SetTable(_) | TableGrow(_) | TableSize(_) | TableFill(_) => {
+				Feature::ReferenceTypes
+			},
+			TableInit(_, table) | TableCopy(table, _) | TableCopy(_, table) if table != 0 => Feature::ReferenceTypes,
+			CallRef(_) | ReturnCallRef(_) | RefAsNonNull | BrOnNull(_) | BrOnNonNull(_) => {
+				Feature::FunctionReferences
+			},
+			RefTest(ty) | RefCast(ty) => {
+				self.ref_type(ty)?;
+				Feature::Gc
+			},
+			I32Extend8S | I32Extend16S | I64Extend8S | I64Extend16S | I64Extend32S => Feature::SignExt,
+			MemoryInit(..) | MemoryDrop(_) | MemoryCopy(..) | MemoryFill(_) | TableInit(..) | TableDrop(_) |
+			TableCopy(..) => Feature::BulkMemory,
+			AtomicWake(_) | I32AtomicWait(_) | I64AtomicWait(_) | I32AtomicLoad(_) | I64AtomicLoad(_) |
+			I32AtomicLoad8u(_) | I32AtomicLoad16u(_) | I64AtomicLoad8u(_) | I64AtomicLoad16u(_) |
+			I64AtomicLoad32u(_) | I32AtomicStore(_) | I64AtomicStore(_) | I32AtomicStore8u(_) |
+			I32AtomicStore16u(_) | I64AtomicStore8u(_) | I64AtomicStore16u(_) | I64AtomicStore32u(_) |
+			I32AtomicRmwAdd(_) | I64AtomicRmwAdd(_) | I32AtomicRmwAdd8u(_) | I32AtomicRmwAdd16u(_) |
+			I64AtomicRmwAdd8u(_) | I64AtomicRmwAdd16u(_) | I64AtomicRmwAdd32u(_) | I32AtomicRmwSub(_) |
+			I64AtomicRmwSub(_) | I32AtomicRmwSub8u(_) | I32AtomicRmwSub16u(_) | I64AtomicRmwSub8u(_) |
+			I64AtomicRmwSub16u(_) | I64AtomicRmwSub32u(_) | I32AtomicRmwAnd(_) | I64AtomicRmwAnd(_) |
+			I32AtomicRmwAnd8u(_) | I32AtomicRmwAnd16u(_) | I64AtomicRmwAnd8u(_) | I64AtomicRmwAnd16u(_) |
+			I64AtomicRmwAnd32u(_) | I32AtomicRmwOr(_) | I64AtomicRmwOr(_) | I32AtomicRmwOr8u(_) |
+			I32AtomicRmwOr16u(_) | I64AtomicRmwOr8u(_) | I64AtomicRmwOr16u(_) | I64AtomicRmwOr32u(_) |
+			I32AtomicRmwXor(_) | I64AtomicRmwXor(_) | I32AtomicRmwXor8u(_) | I32AtomicRmwXor16u(_) |
+			I64AtomicRmwXor8u(_) | I64AtomicRmwXor16u(_) | I64AtomicRmwXor32u(_) | I32AtomicRmwXchg(_) |
+			I64AtomicRmwXchg(_) | I32AtomicRmwXchg8u(_) | I32AtomicRmwXchg16u(_) | I64AtomicRmwXchg8u(_) |
+			I64AtomicRmwXchg16u(_) | I64AtomicRmwXchg32u(_) | I32AtomicRmwCmpxchg(_) |
+			I64AtomicRmwCmpxchg(_) | I32AtomicRmwCmpxchg8u(_) | I32AtomicRmwCmpxchg16u(_) |
+			I64AtomicRmwCmpxchg8u(_) | I64AtomicRmwCmpxchg16u(_) | I64AtomicRmwCmpxchg32u(_) => Feature::Threads,
+			V128Const(_) | V128Load(_) | V128Store(_) | I8x16Splat | I16x8Splat | I32x4Splat | I64x2Splat |
+			F32x4Splat | F64x2Splat | I8x16ExtractLaneS(_) | I8x16ExtractLaneU(_) | I16x8ExtractLaneS(_) |
+			I16x8ExtractLaneU(_) | I32x4ExtractLane(_) | I64x2ExtractLane(_) | F32x4ExtractLane(_) |
+			F64x2ExtractLane(_) | I8x16ReplaceLane(_) | I16x8ReplaceLane(_) | I32x4ReplaceLane(_) |
+			I64x2ReplaceLane(_) | F32x4ReplaceLane(_) | F64x2ReplaceLane(_) | V8x16Shuffle(_) | I8x16Add |
+			I16x8Add | I32x4Add | I64x2Add | I8x16Sub | I16x8Sub | I32x4Sub | I64x2Sub | I8x16Mul | I16x8Mul |
+			I32x4Mul | I8x16Neg | I16x8Neg | I32x4Neg | I64x2Neg | I8x16AddSaturateS | I8x16AddSaturateU |
+			I16x8AddSaturateS | I16x8AddSaturateU | I8x16SubSaturateS | I8x16SubSaturateU |
+			I16x8SubSaturateS | I16x8SubSaturateU | I8x16Shl | I16x8Shl | I32x4Shl | I64x2Shl | I8x16ShrS |
+			I8x16ShrU | I16x8ShrS | I16x8ShrU | I32x4ShrS | I32x4ShrU | I64x2ShrS | I64x2ShrU | V128And |
+			V128Or | V128Xor | V128Not | V128Bitselect | I8x16AnyTrue | I16x8AnyTrue | I32x4AnyTrue |
+			I64x2AnyTrue | I8x16AllTrue | I16x8AllTrue | I32x4AllTrue | I64x2AllTrue | I8x16Eq | I16x8Eq |
+			I32x4Eq | F32x4Eq | F64x2Eq | I8x16Ne | I16x8Ne | I32x4Ne | F32x4Ne | F64x2Ne | I8x16LtS |
+			I8x16LtU | I16x8LtS | I16x8LtU | I32x4LtS | I32x4LtU | F32x4Lt | F64x2Lt | I8x16LeS | I8x16LeU |
+			I16x8LeS | I16x8LeU | I32x4LeS | I32x4LeU | F32x4Le | F64x2Le | I8x16GtS | I8x16GtU | I16x8GtS |
+			I16x8GtU | I32x4GtS | I32x4GtU | F32x4Gt | F64x2Gt | I8x16GeS | I8x16GeU | I16x8GeS | I16x8GeU |
+			I32x4GeS | I32x4GeU | F32x4Ge | F64x2Ge | F32x4Neg | F64x2Neg | F32x4Abs | F64x2Abs | F32x4Min |
+			F64x2Min | F32x4Max | F64x2Max | F32x4Add | F64x2Add | F32x4Sub | F64x2Sub | F32x4Div | F64x2Div |
+			F32x4Mul | F64x2Mul | F32x4Sqrt | F64x2Sqrt | F32x4ConvertSI32x4 | F32x4ConvertUI32x4 |
+			F64x2ConvertSI64x2 | F64x2ConvertUI64x2 | I32x4TruncSF32x4Sat | I32x4TruncUF32x4Sat |
+			I64x2TruncSF64x2Sat | I64x2TruncUF64x2Sat => Feature::Simd,
+			StructNew(_) | StructNewDefault(_) | StructGet(..) | StructGetS(..) | StructGetU(..) |
+			StructSet(..) | ArrayNew(_) | ArrayNewDefault(_) | ArrayNewFixed(..) | ArrayGet(_) |
+			ArrayGetS(_) | ArrayGetU(_) | ArraySet(_) | ArrayLen | ArrayNewData(..) | ArrayNewElem(..) |
+			ArrayFill(_) | ArrayCopy(..) | ArrayInitData(..) | ArrayInitElem(..) | RefEq |
+			BrOnCast(_) | BrOnCastFail(_) | AnyConvertExtern | ExternConvertAny | RefI31 | I31GetS |
+			I31GetU => Feature::Gc,
+			_ => return Ok(()),
+		};
+		self.report(feature)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		serialize, Module, Section, TypeSection, FunctionSection, CodeSection, DataSection, FunctionType,
+		Func, FuncBody, Instructions, Instruction, DataSegment, NumType, ValueType, Error,
+		ImportSection, ImportEntry, External, MemoryType, BlockType, ElementSection, ElementSegment,
+		ElementMode, ElementItems, RefType, MemorySection,
+	};
+	use super::{Feature, Features, Location, deserialize_with_features};
+
+	fn module(code: Vec<Instruction>, mut sections: Vec<Section>) -> Vec<u8> {
+		let func_type = FunctionType::new(vec![ValueType::from(NumType::I32)], Vec::new());
+		let mut module_sections = vec![
+			Section::Type(TypeSection::with_types(vec![func_type.into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::new(Vec::new(), Instructions::new(code))])),
+		];
+		module_sections.append(&mut sections);
+		module_sections.sort_by_key(Section::order);
+		serialize(Module::new(module_sections)).expect("module to serialize")
+	}
+
+	fn disabled(result: Result<Module, Error>) -> Option<Feature> {
+		match result {
+			Err(Error::DisabledFeature(feature)) => Some(feature),
+			Err(e) => panic!("Unexpected error {:?}", e),
+			Ok(_) => None,
+		}
+	}
+
+	#[test]
+	fn sign_extension() {
+		let mvp_sign_ext = Features { sign_ext: true, ..Features::mvp() };
+		let code = vec![Instruction::GetLocal(0), Instruction::I32Extend8S, Instruction::Drop, Instruction::End];
+		let wasm = module(code, vec![]);
+
+		assert_eq!(disabled(deserialize_with_features(&wasm, &Features::mvp())), Some(Feature::SignExt));
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), None);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &Features::default())), None);
+	}
+
+	#[test]
+	fn proposals() {
+		let mvp_sign_ext = Features { sign_ext: true, ..Features::mvp() };
+
+		let code = vec![Instruction::V128Const(Box::new([0; 16])), Instruction::Drop, Instruction::End];
+		let wasm = module(code, vec![]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::Simd));
+
+		let data = DataSegment::new(0, None, vec![1, 2, 3], true);
+		let wasm = module(vec![Instruction::End], vec![Section::Data(DataSection::with_entries(vec![data]))]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::BulkMemory));
+
+		let code = vec![Instruction::GetLocal(0), Instruction::Block(BlockType::TypeIndex(0)), Instruction::End, Instruction::End];
+		let wasm = module(code, vec![]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::MultiValue));
+
+		let code = vec![Instruction::CurrentMemory(1), Instruction::Drop, Instruction::End];
+		let wasm = module(code, vec![]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::MultiMemory));
+
+		let memory = Section::Memory(MemorySection::with_entries(vec![MemoryType::new64(1, None, false)]));
+		let wasm = module(vec![Instruction::End], vec![memory]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::Memory64));
+
+		let declarative = ElementSegment::with_items(
+			ElementMode::Declarative, 0, None, RefType::FUNCREF, ElementItems::Functions(vec![0]),
+		);
+		let wasm = module(vec![Instruction::End], vec![Section::Element(ElementSection::with_entries(vec![declarative]))]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::ReferenceTypes));
+
+		let code = vec![Instruction::TableSize(0), Instruction::Drop, Instruction::End];
+		let wasm = module(code, vec![]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &mvp_sign_ext)), Some(Feature::ReferenceTypes));
+
+		let code = vec![
+			Instruction::I32Const(0), Instruction::I32Const(0), Instruction::I32Const(0),
+			Instruction::TableCopy(0, 1), Instruction::End,
+		];
+		let bulk_memory = Features { bulk_memory: true, ..mvp_sign_ext };
+		let wasm = module(code, vec![]);
+		assert_eq!(disabled(deserialize_with_features(&wasm, &bulk_memory)), Some(Feature::ReferenceTypes));
+
+		let code = vec![Instruction::I32Const(0), Instruction::I32Const(0), Instruction::MemoryFill(0), Instruction::End];
+		let module: Module = super::super::deserialize_buffer(&module(code, vec![])).expect("module to deserialize");
+		assert!(matches!(mvp_sign_ext.check(&module), Err(Error::DisabledFeature(Feature::BulkMemory))));
+		assert!(Features::all().check(&module).is_ok());
+	}
+
+	#[test]
+	fn used_features() {
+		let func_type = FunctionType::new(vec![ValueType::from(NumType::I32)], Vec::new());
+		let imports = vec![
+			ImportEntry::new("env".into(), "f".into(), External::Function(0)),
+			ImportEntry::new("env".into(), "mem".into(), External::Memory(MemoryType::new(1, Some(1), true))),
+		];
+		let code = vec![
+			Instruction::GetLocal(0),
+			Instruction::I32Extend8S,
+			Instruction::I32Extend16S,
+			Instruction::Drop,
+			Instruction::End,
+		];
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![func_type.into()])),
+			Section::Import(ImportSection::with_entries(imports)),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::new(Vec::new(), Instructions::new(code))])),
+		]);
+
+		let used = module.used_features();
+		assert_eq!(used.len(), 2);
+		assert_eq!(used[&Feature::Threads], Location::Import(1));
+		assert_eq!(used[&Feature::SignExt], Location::Instruction { func: 1, instruction: 1 });
+		assert_eq!(used[&Feature::SignExt].to_string(), "function 1, instruction 1");
+		assert!(Module::default().used_features().is_empty());
+	}
+}
@@ -1,9 +1,13 @@
-use crate::rust::vec::Vec;
+use crate::rust::{ops::Range, vec::Vec};
+#[cfg(feature="std")]
+use crate::rust::sync::OnceLock as Lazy;
+#[cfg(not(feature="std"))]
+use crate::rust::cell::OnceCell as Lazy;
 use super::{
 	Deserialize, Error, ValueType, VarUint32, CountedList, Instructions,
-	Serialize, CountedWriter, CountedListWriter,
+	Serialize, CountedWriter, CountedListWriter, FuncOffsets, Module, Dialect,
 };
-use crate::{io, elements::section::SectionReader};
+use crate::{io, elements::section::{SectionReader, BodyDecoding}};
 
 /// Function signature (type reference)
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -81,8 +85,19 @@ impl Serialize for Local {
 }
 
 /// Function body definition.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Bodies decoded by [`deserialize_lazy`] keep their encoding and decode it on first access.
+#[derive(Debug, Clone)]
 pub struct FuncBody {
+	/// Encoding of the body without its size prefix, until the body is changed.
+	raw: Option<Vec<u8>>,
+	code: Lazy<Result<Code, Error>>,
+	offsets: Option<FuncOffsets>,
+}
+
+/// Decoded part of a function body.
+#[derive(Debug, Clone, PartialEq)]
+struct Code {
 	locals: Vec<Local>,
 	instructions: Instructions,
 }
@@ -90,27 +105,137 @@ pub struct FuncBody {
 impl FuncBody {
 	/// New function body with given `locals` and `instructions`.
 	pub fn new(locals: Vec<Local>, instructions: Instructions) -> Self {
-		FuncBody { locals: locals, instructions: instructions }
+		FuncBody {
+			raw: None,
+			code: Lazy::from(Ok(Code { locals: locals, instructions: instructions })),
+			offsets: None,
+		}
 	}
 
 	/// List of individual instructions.
 	pub fn empty() -> Self {
-		FuncBody { locals: Vec::new(), instructions: Instructions::empty() }
+		FuncBody::new(Vec::new(), Instructions::empty())
+	}
+
+	/// New function body from its encoding without the size prefix.
+	///
+	/// The body is decoded on first access.
+	pub fn from_raw(raw: Vec<u8>) -> Self {
+		FuncBody { raw: Some(raw), code: Lazy::new(), offsets: None }
 	}
 
+	/// Encoding of the body without the size prefix, if it was not changed since decoding.
+	///
+	/// Only kept by [`deserialize_lazy`] and [`FuncBody::from_raw`].
+	pub fn raw(&self) -> Option<&[u8]> { self.raw.as_deref() }
+
+	/// Whether the locals and instructions of the body have been decoded.
+	pub fn is_decoded(&self) -> bool { self.code.get().is_some() }
+
 	/// Locals declared in function body.
-	pub fn locals(&self) -> &[Local] { &self.locals }
+	///
+	/// Panics if a lazily decoded body is malformed, see [`FuncBody::try_locals`].
+	pub fn locals(&self) -> &[Local] { &self.decoded().locals }
 
 	/// Instruction list of the function body. Minimal instruction list
 	///
 	/// is just `&[Instruction::End]`
-	pub fn code(&self) -> &Instructions { &self.instructions }
+	///
+	/// Panics if a lazily decoded body is malformed, see [`FuncBody::try_code`].
+	pub fn code(&self) -> &Instructions { &self.decoded().instructions }
+
+	/// Locals declared in function body, decoding them if needed.
+	pub fn try_locals(&self) -> Result<&[Local], Error> {
+		self.try_decode().map(|code| &code.locals[..])
+	}
+
+	/// Instruction list of the function body, decoding it if needed.
+	pub fn try_code(&self) -> Result<&Instructions, Error> {
+		self.try_decode().map(|code| &code.instructions)
+	}
 
 	/// Locals declared in function body (mutable).
-	pub fn locals_mut(&mut self) -> &mut Vec<Local> { &mut self.locals }
+	///
+	/// The body is re-encoded from its locals and instructions from now on.
+	pub fn locals_mut(&mut self) -> &mut Vec<Local> { &mut self.decoded_mut().locals }
 
 	/// Instruction list of the function body (mutable).
-	pub fn code_mut(&mut self) -> &mut Instructions { &mut self.instructions }
+	///
+	/// The body is re-encoded from its locals and instructions from now on.
+	pub fn code_mut(&mut self) -> &mut Instructions { &mut self.decoded_mut().instructions }
+
+	/// Byte ranges of the body and its instructions in the decoded input.
+	///
+	/// Only recorded by [`deserialize_with_offsets`](super::deserialize_with_offsets).
+	pub fn offsets(&self) -> Option<&FuncOffsets> { self.offsets.as_ref() }
+
+	/// Byte ranges of the body and its instructions (mutable).
+	///
+	/// Ranges are not updated when instructions are changed.
+	pub fn offsets_mut(&mut self) -> &mut Option<FuncOffsets> { &mut self.offsets }
+
+	fn try_decode(&self) -> Result<&Code, Error> {
+		self.code
+			.get_or_init(|| {
+				let raw = self.raw.as_deref().expect("bodies without encoding are decoded; qed");
+				let mut reader = io::Cursor::new(raw);
+				let code = read_code(&mut reader, None)?;
+				if reader.position() != raw.len() {
+					return Err(Error::InconsistentLength { expected: raw.len(), actual: reader.position() });
+				}
+				Ok(code)
+			})
+			.as_ref()
+			.map_err(Clone::clone)
+	}
+
+	fn decoded(&self) -> &Code {
+		match self.try_decode() {
+			Ok(code) => code,
+			Err(e) => panic!("malformed function body: {}", e),
+		}
+	}
+
+	fn decoded_mut(&mut self) -> &mut Code {
+		self.decoded();
+		self.raw = None;
+		match self.code.get_mut() {
+			Some(Ok(code)) => code,
+			_ => unreachable!("body is decoded above; qed"),
+		}
+	}
+
+	/// Read a body, keeping its encoding to decode on first access.
+	pub(crate) fn deserialize_lazy<R: io::Read>(reader: &mut R) -> Result<Self, Error> {
+		let size: usize = u32::from(VarUint32::deserialize(reader)?) as usize;
+		let raw = buffered_read!(16384, size, reader);
+		Ok(FuncBody::from_raw(raw))
+	}
+}
+
+impl PartialEq for FuncBody {
+	fn eq(&self, other: &Self) -> bool {
+		// Byte offsets are decoding metadata and don't take part in equality.
+		match (self.try_decode(), other.try_decode()) {
+			(Ok(code), Ok(other_code)) => code == other_code,
+			_ => self.raw == other.raw,
+		}
+	}
+}
+
+/// Read the locals and instructions of a body, pushing the byte ranges of the instructions to `ranges`.
+fn read_code<R: io::Read>(reader: &mut R, ranges: Option<&mut Vec<Range<usize>>>) -> Result<Code, Error> {
+	let locals: Vec<Local> = CountedList::<Local>::deserialize(reader)?.into_inner();
+
+	// The specification obliges us to count the total number of local variables while
+	// decoding the binary format.
+	locals
+		.iter()
+		.try_fold(0u32, |acc, &Local { count, .. }| acc.checked_add(count))
+		.ok_or_else(|| Error::TooManyLocals)?;
+
+	let instructions = Instructions::deserialize_ranges(reader, Dialect::Standard, ranges)?;
+	Ok(Code { locals: locals, instructions: instructions })
 }
 
 impl Deserialize for FuncBody {
@@ -118,18 +243,23 @@ impl Deserialize for FuncBody {
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
 		let mut body_reader = SectionReader::new(reader)?;
-		let locals: Vec<Local> = CountedList::<Local>::deserialize(&mut body_reader)?.into_inner();
-
-		// The specification obliges us to count the total number of local variables while
-		// decoding the binary format.
-		locals
-			.iter()
-			.try_fold(0u32, |acc, &Local { count, .. }| acc.checked_add(count))
-			.ok_or_else(|| Error::TooManyLocals)?;
+		let code = read_code(&mut body_reader, None)?;
+		body_reader.close()?;
+		Ok(FuncBody { raw: None, code: Lazy::from(Ok(code)), offsets: None })
+	}
+}
 
-		let instructions = Instructions::deserialize(&mut body_reader)?;
+impl FuncBody {
+	/// Read a body, recording the byte ranges of the body and its instructions.
+	pub(crate) fn deserialize_with_offsets<R: io::Read>(reader: &mut R) -> Result<Self, Error> {
+		let start = reader.position();
+		let mut body_reader = SectionReader::new(reader)?;
+		let mut instructions = Vec::new();
+		let code = read_code(&mut body_reader, Some(&mut instructions))?;
 		body_reader.close()?;
-		Ok(FuncBody { locals: locals, instructions: instructions })
+		let offsets = start.zip(reader.position())
+			.map(|(start, end)| FuncOffsets::new(start..end, instructions));
+		Ok(FuncBody { raw: None, code: Lazy::from(Ok(code)), offsets })
 	}
 }
 
@@ -137,20 +267,38 @@ impl Serialize for FuncBody {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
+		if let Some(raw) = self.raw {
+			VarUint32::from(raw.len()).serialize(writer)?;
+			writer.write(&raw)?;
+			return Ok(());
+		}
+
+		let code = self.code.into_inner().expect("bodies without encoding are decoded; qed")?;
 		let mut counted_writer = CountedWriter::new(writer);
 
-		let data = self.locals;
+		let data = code.locals;
 		let counted_list = CountedListWriter::<Local, _>(
 			data.len(),
 			data.into_iter().map(Into::into),
 		);
 		counted_list.serialize(&mut counted_writer)?;
 
-		let code = self.instructions;
-		code.serialize(&mut counted_writer)?;
+		code.instructions.serialize(&mut counted_writer)?;
 
 		counted_writer.done()?;
 
 		Ok(())
 	}
 }
+
+/// Deserialize a module from a buffer, keeping function bodies encoded until first accessed.
+///
+/// Bodies that are never changed are serialized back verbatim.
+pub fn deserialize_lazy(contents: &[u8]) -> Result<Module, Error> {
+	let mut reader = io::Cursor::new(contents);
+	let module = Module::deserialize_sections(&mut reader, BodyDecoding::Lazy, |_| Ok(()))?;
+	if reader.position() != contents.len() {
+		return Err(io::Error::TrailingData.into())
+	}
+	Ok(module)
+}
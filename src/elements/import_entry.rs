@@ -1,12 +1,13 @@
 use crate::rust::string::String;
 use crate::io;
 use super::{
-	Deserialize, Serialize, Error, VarUint7, VarInt7, VarUint32, VarUint1, Uint8,
-	ValueType, RefType
+	Deserialize, Serialize, Error, VarUint7, VarInt7, VarUint32, VarUint64, VarUint1, Uint8,
+	ValueType, RefType, NumType,
 };
 
 const FLAG_HAS_MAX: u8 = 0x01;
 const FLAG_SHARED: u8 = 0x02;
+const FLAG_INDEX64: u8 = 0x04;
 
 /// Global definition struct
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -64,8 +65,13 @@ pub struct TableType {
 impl TableType {
 	/// New table definition
 	pub fn new(min: u32, max: Option<u32>) -> Self {
+		TableType::with_elem_type(RefType::FUNCREF, min, max)
+	}
+
+	/// New table definition with the given element type
+	pub fn with_elem_type(elem_type: RefType, min: u32, max: Option<u32>) -> Self {
 		TableType {
-			elem_type: RefType::AnyFunc,
+			elem_type,
 			limits: ResizableLimits::new(min, max),
 		}
 	}
@@ -75,6 +81,14 @@ impl TableType {
 
 	/// Table element type
 	pub fn elem_type(&self) -> RefType { self.elem_type }
+
+	/// Table with decoded limits, which must have 32-bit indices.
+	pub(crate) fn with_limits(elem_type: RefType, limits: ResizableLimits) -> Result<Self, Error> {
+		if limits.index64 {
+			return Err(Error::Other("Tables with 64-bit indices are not supported"));
+		}
+		Ok(TableType { elem_type, limits })
+	}
 }
 
 impl Deserialize for TableType {
@@ -82,11 +96,7 @@ impl Deserialize for TableType {
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
 		let elem_type = RefType::deserialize(reader)?;
-		let limits = ResizableLimits::deserialize(reader)?;
-		Ok(TableType {
-			elem_type: elem_type,
-			limits: limits,
-		})
+		TableType::with_limits(elem_type, ResizableLimits::deserialize(reader)?)
 	}
 }
 
@@ -102,26 +112,41 @@ impl Serialize for TableType {
 /// Memory and table limits.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct ResizableLimits {
-	initial: u32,
-	maximum: Option<u32>,
+	initial: u64,
+	maximum: Option<u64>,
 	shared: bool,
+	index64: bool,
 }
 
 impl ResizableLimits {
 	/// New memory limits definition.
 	pub fn new(min: u32, max: Option<u32>) -> Self {
+		ResizableLimits {
+			initial: min.into(),
+			maximum: max.map(Into::into),
+			shared: false,
+			index64: false,
+		}
+	}
+	/// New limits of a memory with 64-bit addresses.
+	pub fn new64(min: u64, max: Option<u64>) -> Self {
 		ResizableLimits {
 			initial: min,
 			maximum: max,
 			shared: false,
+			index64: true,
 		}
 	}
 	/// Initial size.
-	pub fn initial(&self) -> u32 { self.initial }
+	pub fn initial(&self) -> u64 { self.initial }
 	/// Maximum size.
-	pub fn maximum(&self) -> Option<u32> { self.maximum }
+	pub fn maximum(&self) -> Option<u64> { self.maximum }
 	/// Whether or not this is a shared array buffer.
 	pub fn shared(&self) -> bool { self.shared }
+	/// Type of addresses, `i64` for 64-bit memories.
+	pub fn index_type(&self) -> NumType {
+		if self.index64 { NumType::I64 } else { NumType::I32 }
+	}
 }
 
 impl Deserialize for ResizableLimits {
@@ -129,23 +154,32 @@ impl Deserialize for ResizableLimits {
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
 		let flags: u8 = Uint8::deserialize(reader)?.into();
-		match flags {
+		match flags & !FLAG_INDEX64 {
 			0x00 | 0x01 | 0x03 => {},
 			_ => return Err(Error::InvalidLimitsFlags(flags)),
 		}
 
-		let initial = VarUint32::deserialize(reader)?;
+		let index64 = flags & FLAG_INDEX64 != 0;
+		let read = |reader: &mut R| -> Result<u64, Error> {
+			if index64 {
+				Ok(VarUint64::deserialize(reader)?.into())
+			} else {
+				Ok(u32::from(VarUint32::deserialize(reader)?).into())
+			}
+		};
+		let initial = read(reader)?;
 		let maximum = if flags & FLAG_HAS_MAX != 0 {
-			Some(VarUint32::deserialize(reader)?.into())
+			Some(read(reader)?)
 		} else {
 			None
 		};
 		let shared = flags & FLAG_SHARED != 0;
 
 		Ok(ResizableLimits {
-			initial: initial.into(),
-			maximum: maximum,
+			initial,
+			maximum,
 			shared,
+			index64,
 		})
 	}
 }
@@ -161,10 +195,22 @@ impl Serialize for ResizableLimits {
 		if self.shared {
 			flags |= FLAG_SHARED;
 		}
+		if self.index64 {
+			flags |= FLAG_INDEX64;
+		}
 		Uint8::from(flags).serialize(writer)?;
-		VarUint32::from(self.initial).serialize(writer)?;
+		let write = |writer: &mut W, value: u64| -> Result<(), Error> {
+			if self.index64 {
+				VarUint64::from(value).serialize(writer)
+			} else if value <= u64::from(u32::MAX) {
+				VarUint32::from(value as u32).serialize(writer)
+			} else {
+				Err(Error::Other("Limits of 32-bit memory out of range"))
+			}
+		};
+		write(writer, self.initial)?;
 		if let Some(max) = self.maximum {
-			VarUint32::from(max).serialize(writer)?;
+			write(writer, max)?;
 		}
 		Ok(())
 	}
@@ -182,10 +228,22 @@ impl MemoryType {
 		MemoryType(r)
 	}
 
+	/// New memory definition with 64-bit addresses.
+	pub fn new64(min: u64, max: Option<u64>, shared: bool) -> Self {
+		let mut r = ResizableLimits::new64(min, max);
+		r.shared = shared;
+		MemoryType(r)
+	}
+
 	/// Limits of the memory entry.
 	pub fn limits(&self) -> &ResizableLimits {
 		&self.0
 	}
+
+	/// Type of addresses, `i64` for 64-bit memories.
+	pub fn index_type(&self) -> NumType {
+		self.0.index_type()
+	}
 }
 
 impl Deserialize for MemoryType {
@@ -328,3 +386,25 @@ impl Serialize for ImportEntry {
 		self.external.serialize(writer)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::super::{deserialize_buffer, serialize, NumType};
+	use super::{MemoryType, TableType};
+
+	#[test]
+	fn memory64() {
+		let memory = MemoryType::new64(1, Some(1 << 40), false);
+		let buf = serialize(memory).expect("memory to serialize");
+		assert_eq!(buf, vec![0x05, 0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20]);
+
+		let decoded: MemoryType = deserialize_buffer(&buf).expect("memory to deserialize");
+		assert_eq!(decoded, memory);
+		assert_eq!(decoded.index_type(), NumType::I64);
+		assert_eq!(decoded.limits().maximum(), Some(1 << 40));
+		assert_eq!(MemoryType::new(1, None, false).index_type(), NumType::I32);
+
+		assert!(deserialize_buffer::<TableType>(&[0x70, 0x04, 0x01]).is_err());
+		assert!(deserialize_buffer::<MemoryType>(&[0x06, 0x01]).is_err());
+	}
+}
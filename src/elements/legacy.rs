@@ -0,0 +1,218 @@
+//! Conversion of modules from the legacy prototype GC encoding.
+
+use crate::rust::{string::String, vec::Vec};
+use crate::io::{self, Read};
+use super::{
+	Deserialize, Serialize, Error, Module, Section, VarUint7, VarUint32, VarUint1,
+	TypeSection, ImportSection, TableSection, GlobalSection, CodeSection, ImportEntry,
+	External, TableType, MemoryType, GlobalType, GlobalEntry, ResizableLimits, FuncBody,
+	Local, Instructions, InitExpr, ValueType, RefType,
+	Dialect, deserialize_buffer,
+};
+use super::types::read_list;
+
+/// Rewrite a module binary from the legacy prototype GC encoding to the standard one.
+///
+/// Only sections that can contain type definitions are re-encoded, all other
+/// sections are copied verbatim.
+pub fn upgrade(contents: &[u8]) -> Result<Vec<u8>, Error> {
+	// Magic number and version are the same in both encodings.
+	if contents.len() < 8 {
+		return Err(Error::UnexpectedEof);
+	}
+	let mut result = Vec::with_capacity(contents.len());
+	result.extend_from_slice(&contents[..8]);
+
+	let mut start = 8;
+	while start < contents.len() {
+		let mut reader = io::Cursor::new(&contents[start..]);
+		let id: u8 = VarUint7::deserialize(&mut reader)?.into();
+		let size_start = start + reader.position();
+		let size: usize = VarUint32::deserialize(&mut reader)?.into();
+		let payload_start = start + reader.position();
+		let end = payload_start.checked_add(size)
+			.filter(|&end| end <= contents.len())
+			.ok_or(Error::UnexpectedEof)?;
+		let payload = &contents[payload_start..end];
+
+		let section = match id {
+			1 => {
+				let mut section_reader = io::Cursor::new(&contents[size_start..end]);
+				Some(Section::Type(TypeSection::deserialize_dialect(&mut section_reader, Dialect::Legacy)?))
+			},
+			2 => Some(Section::Import(ImportSection::with_entries(read_payload(payload, read_import)?))),
+			4 => Some(Section::Table(TableSection::with_entries(read_payload(payload, read_table_type)?))),
+			6 => Some(Section::Global(GlobalSection::with_entries(read_payload(payload, read_global)?))),
+			10 => Some(Section::Code(CodeSection::with_bodies(read_payload(payload, read_func_body)?))),
+			_ => None,
+		};
+
+		match section {
+			Some(section) => section.serialize(&mut result)?,
+			None => result.extend_from_slice(&contents[start..end]),
+		}
+		start = end;
+	}
+
+	Ok(result)
+}
+
+/// Deserialize a module from a buffer in the legacy prototype GC encoding.
+///
+/// The module is converted with [`upgrade`] and serializes in the standard encoding.
+pub fn deserialize_legacy_buffer(contents: &[u8]) -> Result<Module, Error> {
+	deserialize_buffer(&upgrade(contents)?)
+}
+
+/// Read a counted list of entries taking up the whole `payload`.
+fn read_payload<'a, T>(
+	payload: &'a [u8],
+	read: impl FnMut(&mut io::Cursor<&'a [u8]>) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+	let mut reader = io::Cursor::new(payload);
+	let entries = read_list(&mut reader, read)?;
+	if reader.position() != payload.len() {
+		return Err(Error::InconsistentLength { expected: payload.len(), actual: reader.position() });
+	}
+	Ok(entries)
+}
+
+fn read_table_type<R: io::Read>(reader: &mut R) -> Result<TableType, Error> {
+	let elem_type = RefType::deserialize_dialect(reader, Dialect::Legacy)?;
+	TableType::with_limits(elem_type, ResizableLimits::deserialize(reader)?)
+}
+
+fn read_global_type<R: io::Read>(reader: &mut R) -> Result<GlobalType, Error> {
+	let content_type = ValueType::deserialize_dialect(reader, Dialect::Legacy)?;
+	let is_mutable: bool = VarUint1::deserialize(reader)?.into();
+	Ok(GlobalType::new(content_type, is_mutable))
+}
+
+fn read_import<R: io::Read>(reader: &mut R) -> Result<ImportEntry, Error> {
+	let module_str = String::deserialize(reader)?;
+	let field_str = String::deserialize(reader)?;
+	let kind = VarUint7::deserialize(reader)?;
+	let external = match kind.into() {
+		0x00 => External::Function(VarUint32::deserialize(reader)?.into()),
+		0x01 => External::Table(read_table_type(reader)?),
+		0x02 => External::Memory(MemoryType::deserialize(reader)?),
+		0x03 => External::Global(read_global_type(reader)?),
+		_ => return Err(Error::UnknownExternalKind(kind.into())),
+	};
+	Ok(ImportEntry::new(module_str, field_str, external))
+}
+
+fn read_global<R: io::Read>(reader: &mut R) -> Result<GlobalEntry, Error> {
+	let global_type = read_global_type(reader)?;
+	Ok(GlobalEntry::new(global_type, InitExpr::deserialize_dialect(reader, Dialect::Legacy)?))
+}
+
+fn read_func_body(reader: &mut io::Cursor<&[u8]>) -> Result<FuncBody, Error> {
+	let size: usize = VarUint32::deserialize(reader)?.into();
+	let body = buffered_read!(65536, size, reader);
+
+	let mut body_reader = io::Cursor::new(&body[..]);
+	let locals = read_list(&mut body_reader, |r| {
+		let count: u32 = VarUint32::deserialize(r)?.into();
+		Ok(Local::new(count, ValueType::deserialize_dialect(r, Dialect::Legacy)?))
+	})?;
+
+	let instructions = Instructions::deserialize_dialect(&mut body_reader, Dialect::Legacy)?;
+	if body_reader.position() != size {
+		return Err(Error::InconsistentLength { expected: size, actual: body_reader.position() });
+	}
+
+	Ok(FuncBody::new(locals, instructions))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		deserialize_buffer, serialize, Module, Type, FieldType, StorageType, RefType, HeapType,
+		ValueType, BlockType, Instruction,
+	};
+	use super::{upgrade, deserialize_legacy_buffer};
+
+	fn legacy_module() -> Vec<u8> {
+		vec![
+			0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+			// type section: (struct (field (mut i8)) (field (ref 0))), (func (param (ref 0)))
+			0x01, 0x0d, 0x02,
+			0x5f, 0x02, 0x01, 0x68, 0x00, 0x6e, 0x00,
+			0x60, 0x01, 0x6e, 0x00, 0x00,
+			// function section
+			0x03, 0x02, 0x01, 0x01,
+			// table section: anyref table
+			0x04, 0x04, 0x01, 0x6f, 0x00, 0x01,
+			// code section: (local (ref 0)) (block (result (ref 0)) end) end
+			0x0a, 0x0a, 0x01, 0x08, 0x01, 0x01, 0x6e, 0x00, 0x02, 0x6e, 0x0b, 0x0b,
+		]
+	}
+
+	#[test]
+	fn legacy_module_upgrade() {
+		let module = deserialize_legacy_buffer(&legacy_module()).expect("legacy module to deserialize");
+		let ref0 = RefType::new(true, HeapType::Index(0));
+
+		let types = module.type_section().expect("type section to exist").types();
+		match types[0] {
+			Type::Struct(ref s) => assert_eq!(s.fields(), &[
+				FieldType::new(StorageType::PackedI8, true),
+				FieldType::new(ValueType::from(ref0), false),
+			][..]),
+			_ => panic!("First type should be a struct type"),
+		}
+		match types[1] {
+			Type::Function(ref f) => assert_eq!(f.params(), &[ValueType::from(ref0)][..]),
+			_ => panic!("Second type should be a function type"),
+		}
+
+		let table = &module.table_section().expect("table section to exist").entries()[0];
+		assert_eq!(table.elem_type(), RefType::EXTERNREF);
+
+		let body = &module.code_section().expect("code section to exist").bodies()[0];
+		assert_eq!(body.locals()[0].value_type(), ValueType::from(ref0));
+		assert_eq!(body.code().elements()[0], Instruction::Block(BlockType::Value(ref0.into())));
+
+		let standard = serialize(module.clone()).expect("module to serialize");
+		assert_eq!(upgrade(&legacy_module()).expect("legacy module to upgrade"), standard);
+		let reparsed: Module = deserialize_buffer(&standard).expect("standard module to deserialize");
+		assert_eq!(reparsed.type_section(), module.type_section());
+	}
+
+	#[test]
+	fn legacy_module_not_standard() {
+		assert!(deserialize_buffer::<Module>(&legacy_module()).is_err());
+	}
+
+	#[test]
+	fn legacy_ref_null() {
+		let wasm = vec![
+			0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+			// type section: (func)
+			0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+			// function section
+			0x03, 0x02, 0x01, 0x00,
+			// global section: (global (ref 0) (ref.null))
+			0x06, 0x06, 0x01, 0x6e, 0x00, 0x00, 0xd0, 0x0b,
+			// code section: (ref.null) (drop) end
+			0x0a, 0x06, 0x01, 0x04, 0x00, 0xd0, 0x1a, 0x0b,
+		];
+		let module = deserialize_legacy_buffer(&wasm).expect("legacy module to deserialize");
+		let ref_null = Instruction::RefNull(HeapType::Index(0));
+
+		let global = &module.global_section().expect("global section to exist").entries()[0];
+		assert_eq!(global.init_expr().code(), &[ref_null.clone(), Instruction::End][..]);
+
+		let body = &module.code_section().expect("code section to exist").bodies()[0];
+		assert_eq!(body.code().elements(), &[ref_null, Instruction::Drop, Instruction::End][..]);
+	}
+
+	#[test]
+	fn legacy_body_size_out_of_range() {
+		let mut wasm = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+		// code section with one body claiming 4 GiB
+		wasm.extend_from_slice(&[0x0a, 0x06, 0x01, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+		assert!(upgrade(&wasm).is_err());
+	}
+}
@@ -1,6 +1,6 @@
 //! Elements of the WebAssembly binary format.
 
-use crate::rust::{fmt, vec::Vec, format, string::String};
+use crate::rust::{fmt, vec::Vec, boxed::Box, string::String};
 use crate::io;
 
 macro_rules! buffered_read {
@@ -33,6 +33,14 @@ mod segment;
 mod index_map;
 mod name_section;
 mod reloc_section;
+mod type_context;
+mod canonical;
+mod features;
+mod offsets;
+mod borrowed;
+mod stream;
+pub mod legacy;
+pub mod validate;
 
 pub use self::module::{Module, peek_size, ImportCountType};
 pub use self::section::{
@@ -47,11 +55,20 @@ pub use self::primitives::{
 	VarUint32, VarUint7, Uint8, VarUint1, VarInt7, Uint32, VarInt32, VarInt64,
 	Uint64, VarUint64, CountedList, CountedWriter, CountedListWriter,
 };
-pub use self::types::{Type, ValueType, BlockType, FunctionType, NumType, RefType};
-pub use self::ops::{Instruction, Instructions, InitExpr, opcodes, MemArg, BrTableData};
-pub use self::func::{Func, FuncBody, Local};
-pub use self::segment::{ElementSegment, DataSegment};
+pub use self::types::{
+	Type, ValueType, BlockType, FunctionType, NumType, RefType, HeapType, Dialect, StructType,
+	ArrayType, FieldType, StorageType, SubType, RecGroup,
+};
+pub use self::ops::{Instruction, Instructions, InitExpr, opcodes, MemArg, BrTableData, BrOnCastData};
+pub use self::func::{Func, FuncBody, Local, deserialize_lazy};
+pub use self::segment::{ElementSegment, ElementMode, ElementItems, DataSegment};
 pub use self::index_map::IndexMap;
+pub use self::type_context::{TypeContext, Subtype};
+pub use self::canonical::TypeCanonicalizer;
+pub use self::features::{Feature, Features, Location, deserialize_with_features};
+pub use self::offsets::{FuncOffsets, deserialize_with_offsets};
+pub use self::borrowed::{ModuleRef, SectionRef, CustomSectionRef, ImportRef, ExportRef, DataSegmentRef};
+pub use self::stream::{Parser, Event, Chunk};
 pub use self::name_section::{
 	NameMap, NameSection, ModuleNameSection, FunctionNameSection,
 	LocalNameSection,
@@ -101,6 +118,8 @@ pub enum Error {
 	UnknownValueType(i8),
 	/// Invalid/unknown table element type declaration.
 	UnknownTableElementType(i8),
+	/// Invalid/unknown heap type declaration.
+	UnknownHeapType(i64),
 	/// Non-utf8 string.
 	NonUtf8String,
 	/// Unknown external kind code.
@@ -111,6 +130,8 @@ pub enum Error {
 	UnknownOpcode(u8),
 	/// Unknown SIMD opcode encountered.
 	UnknownSimdOpcode(u32),
+	/// Unknown GC opcode encountered.
+	UnknownGcOpcode(u32),
 	/// Invalid VarUint1 value.
 	InvalidVarUint1(u8),
 	/// Invalid VarInt32 value.
@@ -145,6 +166,103 @@ pub enum Error {
 	InvalidSegmentFlags(u32),
 	/// Sum of counts of locals is greater than 2^32.
 	TooManyLocals,
+	/// Module uses a proposal that is not enabled.
+	DisabledFeature(Feature),
+	/// Data left after the end of the input.
+	TrailingData,
+	/// Error of the underlying reader or writer.
+	#[cfg(feature = "std")]
+	Io(crate::rust::sync::Arc<::std::io::Error>),
+	/// Error with the location in the input where it happened.
+	Located {
+		/// Offset of the innermost part being decoded, from the start of the input.
+		offset: Option<usize>,
+		/// Parts being decoded, outermost first.
+		context: Vec<Context>,
+		/// The error itself.
+		error: Box<Error>,
+	},
+}
+
+impl Error {
+	/// The error without its location.
+	pub fn kind(&self) -> &Error {
+		match *self {
+			Error::Located { ref error, .. } => error,
+			ref error => error,
+		}
+	}
+
+	/// Offset in the input where the error happened, if known.
+	pub fn offset(&self) -> Option<usize> {
+		match *self {
+			Error::Located { offset, .. } => offset,
+			_ => None,
+		}
+	}
+
+	/// Parts of the module being decoded when the error happened, outermost first.
+	pub fn context(&self) -> &[Context] {
+		match *self {
+			Error::Located { ref context, .. } => context,
+			_ => &[],
+		}
+	}
+
+	/// Add an outer part being decoded, starting at `offset`.
+	///
+	/// The offset of the innermost part is kept.
+	pub(crate) fn located(self, part: Context, offset: Option<usize>) -> Error {
+		match self {
+			Error::Located { offset: inner, mut context, error } => {
+				context.insert(0, part);
+				Error::Located { offset: inner.or(offset), context, error }
+			},
+			error => Error::Located { offset, context: vec![part], error: Box::new(error) },
+		}
+	}
+}
+
+/// Part of a module being decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+	/// Section with the given id.
+	Section(u8),
+	/// Entry of a section.
+	Entry(u32),
+	/// Function body with the given index in the code section.
+	FuncBody(u32),
+	/// Instruction with the given index in the function body.
+	Instruction(u32),
+}
+
+impl fmt::Display for Context {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Context::Section(id) => {
+				let name = match id {
+					0 => "custom",
+					1 => "type",
+					2 => "import",
+					3 => "function",
+					4 => "table",
+					5 => "memory",
+					6 => "global",
+					7 => "export",
+					8 => "start",
+					9 => "element",
+					10 => "code",
+					11 => "data",
+					12 => "data count",
+					_ => return write!(f, "section {}", id),
+				};
+				write!(f, "{} section", name)
+			},
+			Context::Entry(idx) => write!(f, "entry {}", idx),
+			Context::FuncBody(idx) => write!(f, "function {}", idx),
+			Context::Instruction(idx) => write!(f, "instruction {}", idx),
+		}
+	}
 }
 
 impl fmt::Display for Error {
@@ -160,11 +278,13 @@ impl fmt::Display for Error {
 			Error::HeapOther(ref msg) => write!(f, "{}", msg),
 			Error::UnknownValueType(ty) => write!(f, "Invalid or unknown value type {}", ty),
 			Error::UnknownTableElementType(ty) => write!(f, "Unknown table element type {}", ty),
+			Error::UnknownHeapType(ty) => write!(f, "Invalid or unknown heap type {}", ty),
 			Error::NonUtf8String => write!(f, "Non-UTF-8 string"),
 			Error::UnknownExternalKind(kind) => write!(f, "Unknown external kind {}", kind),
 			Error::UnknownInternalKind(kind) => write!(f, "Unknown internal kind {}", kind),
 			Error::UnknownOpcode(opcode) => write!(f, "Unknown opcode {}", opcode),
 			Error::UnknownSimdOpcode(opcode) => write!(f, "Unknown SIMD opcode {}", opcode),
+			Error::UnknownGcOpcode(opcode) => write!(f, "Unknown GC opcode {}", opcode),
 			Error::InvalidVarUint1(val) => write!(f, "Not an unsigned 1-bit integer: {}", val),
 			Error::InvalidVarInt7(val) => write!(f, "Not a signed 7-bit integer: {}", val),
 			Error::InvalidVarInt32 => write!(f, "Not a signed 32-bit integer"),
@@ -182,6 +302,22 @@ impl fmt::Display for Error {
 			Error::InconsistentCode =>  write!(f, "Number of function body entries and signatures does not match"),
 			Error::InvalidSegmentFlags(n) =>  write!(f, "Invalid segment flags: {}", n),
 			Error::TooManyLocals => write!(f, "Too many locals"),
+			Error::DisabledFeature(feature) => write!(f, "Proposal {} is not enabled", feature),
+			Error::TrailingData => write!(f, "Trailing data"),
+			#[cfg(feature = "std")]
+			Error::Io(ref err) => write!(f, "I/O error: {}", err),
+			Error::Located { offset, ref context, ref error } => {
+				for (idx, part) in context.iter().enumerate() {
+					if idx > 0 {
+						write!(f, " > ")?;
+					}
+					write!(f, "{}", part)?;
+				}
+				if let Some(offset) = offset {
+					write!(f, " at offset {:#x}", offset)?;
+				}
+				write!(f, ": {}", error)
+			},
 		}
 	}
 }
@@ -198,11 +334,13 @@ impl ::std::error::Error for Error {
 			Error::HeapOther(ref msg) => &msg[..],
 			Error::UnknownValueType(_) => "Invalid or unknown value type",
 			Error::UnknownTableElementType(_) => "Unknown table element type",
+			Error::UnknownHeapType(_) => "Invalid or unknown heap type",
 			Error::NonUtf8String => "Non-UTF-8 string",
 			Error::UnknownExternalKind(_) => "Unknown external kind",
 			Error::UnknownInternalKind(_) => "Unknown internal kind",
 			Error::UnknownOpcode(_) => "Unknown opcode",
 			Error::UnknownSimdOpcode(_) => "Unknown SIMD opcode",
+			Error::UnknownGcOpcode(_) => "Unknown GC opcode",
 			Error::InvalidVarUint1(_) => "Not an unsigned 1-bit integer",
 			Error::InvalidVarInt32 => "Not a signed 32-bit integer",
 			Error::InvalidVarInt7(_) => "Not a signed 7-bit integer",
@@ -220,13 +358,40 @@ impl ::std::error::Error for Error {
 			Error::InconsistentCode =>  "Number of function body entries and signatures does not match",
 			Error::InvalidSegmentFlags(_) =>  "Invalid segment flags",
 			Error::TooManyLocals => "Too many locals",
+			Error::DisabledFeature(_) => "Proposal is not enabled",
+			Error::TrailingData => "Trailing data",
+			Error::Io(_) => "I/O error",
+			Error::Located { .. } => "Located decode error",
+		}
+	}
+
+	fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+		match *self {
+			Error::Io(ref err) => Some(&**err),
+			Error::Located { ref error, .. } => Some(&**error),
+			_ => None,
 		}
 	}
 }
 
 impl From<io::Error> for Error {
 	fn from(err: io::Error) -> Self {
-		Error::HeapOther(format!("I/O Error: {:?}", err))
+		match err {
+			io::Error::TrailingData => Error::TrailingData,
+			io::Error::UnexpectedEof => Error::UnexpectedEof,
+			io::Error::InvalidData => Error::Other("Invalid data"),
+			#[cfg(feature = "std")]
+			io::Error::IoError(ref err) if err.kind() == ::std::io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+			#[cfg(feature = "std")]
+			io::Error::IoError(err) => Error::Io(err),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<::std::io::Error> for Error {
+	fn from(err: ::std::io::Error) -> Self {
+		Error::Io(crate::rust::sync::Arc::new(err))
 	}
 }
 
@@ -272,8 +437,7 @@ pub fn serialize<T: Serialize>(val: T) -> Result<Vec<u8>, T::Error> {
 /// Deserialize module from the file.
 #[cfg(feature = "std")]
 pub fn deserialize_file<P: AsRef<::std::path::Path>>(p: P) -> Result<Module, Error> {
-	let mut f = ::std::fs::File::open(p)
-		.map_err(|e| Error::HeapOther(format!("Can't read from the file: {:?}", e)))?;
+	let mut f = ::std::fs::File::open(p)?;
 
 	Module::deserialize(&mut f)
 }
@@ -281,10 +445,7 @@ pub fn deserialize_file<P: AsRef<::std::path::Path>>(p: P) -> Result<Module, Err
 /// Serialize module to the file
 #[cfg(feature = "std")]
 pub fn serialize_to_file<P: AsRef<::std::path::Path>>(p: P, module: Module) -> Result<(), Error> {
-	let mut io = ::std::fs::File::create(p)
-		.map_err(|e|
-			Error::HeapOther(format!("Can't create the file: {:?}", e))
-		)?;
+	let mut io = ::std::fs::File::create(p)?;
 
 	module.serialize(&mut io)?;
 	Ok(())
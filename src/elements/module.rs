@@ -1,23 +1,24 @@
-use crate::rust::{vec::Vec, borrow::ToOwned, string::String, cmp};
-use crate::io;
+use crate::rust::{vec::Vec, borrow::ToOwned, string::String, cmp, ops::Range};
+use crate::io::{self, Read};
 
-use super::{Deserialize, Serialize, Error, Uint32, External};
+use super::{Deserialize, Serialize, Error, Context, Uint32, External};
 use super::section::{
 	Section, CodeSection, TypeSection, ImportSection, ExportSection, FunctionSection,
 	GlobalSection, TableSection, ElementSection, DataSection, MemorySection,
-	CustomSection,
+	CustomSection, BodyDecoding,
 };
 use super::name_section::NameSection;
 use super::reloc_section::RelocSection;
 
-const WASM_MAGIC_NUMBER: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
+pub(crate) const WASM_MAGIC_NUMBER: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
 
 /// WebAssembly module
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Module {
 	magic: u32,
 	version: u32,
 	sections: Vec<Section>,
+	section_offsets: Option<Vec<Range<usize>>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -39,10 +40,18 @@ impl Default for Module {
 			magic: u32::from_le_bytes(WASM_MAGIC_NUMBER),
 			version: 1,
 			sections: Vec::with_capacity(16),
+			section_offsets: None,
 		}
 	}
 }
 
+// Byte offsets are decoding metadata and don't take part in equality.
+impl PartialEq for Module {
+	fn eq(&self, other: &Self) -> bool {
+		self.magic == other.magic && self.version == other.version && self.sections == other.sections
+	}
+}
+
 impl Module {
 	/// New module with sections
 	pub fn new(sections: Vec<Section>) -> Self {
@@ -73,6 +82,20 @@ impl Module {
 		&mut self.sections
 	}
 
+	/// Byte ranges of the sections in the decoded input, including section headers.
+	///
+	/// Only recorded by [`deserialize_with_offsets`](super::deserialize_with_offsets).
+	pub fn section_offsets(&self) -> Option<&[Range<usize>]> {
+		self.section_offsets.as_ref().map(|offsets| &offsets[..])
+	}
+
+	/// Byte ranges of the sections in the decoded input (mutable).
+	///
+	/// Ranges are not updated when sections are changed.
+	pub fn section_offsets_mut(&mut self) -> &mut Option<Vec<Range<usize>>> {
+		&mut self.section_offsets
+	}
+
 	/// Code section reference, if any.
 	pub fn code_section(&self) -> Option<&CodeSection> {
 		for section in self.sections() {
@@ -399,7 +422,7 @@ impl Module {
 							Err(e) => { parse_errors.push((i, e)); continue; }
 						};
 						if rdr.position() != custom.payload().len() {
-							parse_errors.push((i, io::Error::InvalidData.into()));
+							parse_errors.push((i, Error::TrailingData));
 							continue;
 						}
 						Some(Section::Reloc(reloc_section))
@@ -466,7 +489,23 @@ impl Deserialize for Module {
 	type Error = super::Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		Module::deserialize_sections(reader, BodyDecoding::Eager, |_| Ok(()))
+	}
+}
+
+impl Module {
+	/// Deserialize a module, passing every section to `check` as soon as it is decoded.
+	///
+	/// Function bodies are decoded as given by `bodies`, section offsets are only
+	/// recorded along with the body offsets.
+	pub(crate) fn deserialize_sections<R: io::Read>(
+		reader: &mut R,
+		bodies: BodyDecoding,
+		mut check: impl FnMut(&Section) -> Result<(), Error>,
+	) -> Result<Self, Error> {
+		let reader = &mut CountingReader { inner: reader, position: 0 };
 		let mut sections = Vec::new();
+		let mut offsets = Vec::new();
 
 		let mut magic = [0u8; 4];
 		reader.read(&mut magic)?;
@@ -483,19 +522,27 @@ impl Deserialize for Module {
 		let mut last_section_order = 0;
 
 		loop {
-			match Section::deserialize(reader) {
+			let start = reader.position;
+			match Section::deserialize_section(reader, bodies) {
 				Err(Error::UnexpectedEof) => { break; },
 				Err(e) => { return Err(e) },
 				Ok(section) => {
 					if section.order() != 0 {
+						let located = |error: Error| {
+							error.located(Context::Section(section.id()), Some(start))
+						};
 						if last_section_order > section.order() {
-							return Err(Error::SectionsOutOfOrder);
+							return Err(located(Error::SectionsOutOfOrder));
 						} else if last_section_order == section.order() {
-							return Err(Error::DuplicatedSections(last_section_order));
+							return Err(located(Error::DuplicatedSections(last_section_order)));
 						}
 						last_section_order = section.order();
 					}
+					check(&section)?;
 					sections.push(section);
+					if bodies == BodyDecoding::Offsets {
+						offsets.push(start..reader.position);
+					}
 				}
 			}
 		}
@@ -504,6 +551,7 @@ impl Deserialize for Module {
 			magic: u32::from_le_bytes(magic),
 			version: version,
 			sections: sections,
+			section_offsets: if bodies == BodyDecoding::Offsets { Some(offsets) } else { None },
 		};
 
 		if module.code_section().map(|cs| cs.bodies().len()).unwrap_or(0) !=
@@ -529,6 +577,24 @@ impl Serialize for Module {
 	}
 }
 
+/// Reader that counts the bytes read from the start of a module.
+struct CountingReader<'a, R> {
+	inner: &'a mut R,
+	position: usize,
+}
+
+impl<'a, R: io::Read> io::Read for CountingReader<'a, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+		self.inner.read(buf)?;
+		self.position += buf.len();
+		Ok(())
+	}
+
+	fn position(&self) -> Option<usize> {
+		Some(self.position)
+	}
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct PeekSection<'a> {
 	cursor: usize,
@@ -591,7 +657,12 @@ pub fn peek_size(source: &[u8]) -> usize {
 
 #[cfg(test)]
 mod integration_tests {
-	use super::super::{deserialize_file, serialize, deserialize_buffer, Section};
+	use super::super::{
+		deserialize_file, serialize, deserialize_buffer, deserialize_with_offsets, Section, Error,
+		Context, TypeSection, FunctionSection, CodeSection, FunctionType, Func, FuncBody,
+		Instructions, Instruction, deserialize_lazy, Features,
+	};
+	use super::super::validate::{validate, ValidationError};
 	use super::Module;
 
 	#[test]
@@ -698,13 +769,13 @@ mod integration_tests {
 
 	#[test]
 	fn store() {
-		use super::super::Instruction::*;
+		use super::super::{Instruction::*, MemArg};
 
 		let module = deserialize_file("./res/cases/v1/offset.wasm").expect("Should be deserialized");
 		let func = &module.code_section().expect("Code section to exist").bodies()[0];
 
 		assert_eq!(func.code().elements().len(), 5);
-		assert_eq!(I64Store(0, 32), func.code().elements()[2]);
+		assert_eq!(I64Store(MemArg::new(0, 32)), func.code().elements()[2]);
 	}
 
 	#[test]
@@ -793,6 +864,93 @@ mod integration_tests {
 			.expect("Maybe shouldn't be deserialized");
 	}
 
+	#[test]
+	fn located_errors() {
+		let body = |code| FuncBody::new(Vec::new(), Instructions::new(code));
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![FunctionType::default().into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0), Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![
+				body(vec![Instruction::End]),
+				body(vec![Instruction::I32Const(1), Instruction::Drop, Instruction::Nop, Instruction::End]),
+			])),
+		]);
+		let mut wasm = serialize(module).expect("serialization to succeed");
+		let module = deserialize_with_offsets(&wasm).expect("deserialization to succeed");
+		let nop = module.code_section().unwrap().bodies()[1].offsets().unwrap().instructions()[2].start;
+		wasm[nop] = 0xff;
+
+		let err = deserialize_buffer::<Module>(&wasm).unwrap_err();
+		assert!(matches!(*err.kind(), Error::UnknownOpcode(0xff)));
+		assert_eq!(err.offset(), Some(nop));
+		assert_eq!(err.context(), &[Context::Section(10), Context::FuncBody(1), Context::Instruction(2)]);
+		assert_eq!(
+			err.to_string(),
+			format!("code section > function 1 > instruction 2 at offset {:#x}: Unknown opcode 255", nop),
+		);
+
+		let err = deserialize_file("./res/cases/v1/missing.wasm").unwrap_err();
+		assert!(matches!(err, Error::Io(_)));
+		assert!(::std::error::Error::source(&err).is_some());
+	}
+
+	#[test]
+	fn lazy_bodies() {
+		// `i32.const 0` with a padded immediate, `drop` and `end`.
+		let raw = vec![0x00, 0x41, 0x80, 0x00, 0x1a, 0x0b];
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![FunctionType::default().into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0), Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::from_raw(raw.clone()), FuncBody::empty()])),
+		]);
+		let wasm = serialize(module).expect("serialization to succeed");
+
+		let module = deserialize_lazy(&wasm).expect("deserialization to succeed");
+		let bodies = module.code_section().unwrap().bodies();
+		assert!(!bodies[0].is_decoded());
+		assert_eq!(bodies[0].raw(), Some(&raw[..]));
+		assert_eq!(serialize(module.clone()).expect("serialization to succeed"), wasm);
+
+		let eager: Module = deserialize_buffer(&wasm).expect("deserialization to succeed");
+		assert_eq!(module, eager);
+		assert!(module.code_section().unwrap().bodies()[0].is_decoded());
+		assert!(serialize(eager).expect("serialization to succeed").len() < wasm.len());
+
+		let mut module = module;
+		module.code_section_mut().unwrap().bodies_mut()[0].code_mut().elements_mut().insert(0, Instruction::Nop);
+		assert!(module.code_section().unwrap().bodies()[0].raw().is_none());
+		let module: Module = deserialize_buffer(&serialize(module).expect("serialization to succeed"))
+			.expect("deserialization to succeed");
+		assert_eq!(module.code_section().unwrap().bodies()[0].code().elements()[..2], [Instruction::Nop, Instruction::I32Const(0)]);
+
+		let body = FuncBody::from_raw(vec![0x00, 0xff, 0x0b]);
+		assert!(matches!(body.try_code().unwrap_err().kind(), Error::UnknownOpcode(0xff)));
+		assert!(matches!(
+			FuncBody::from_raw(vec![0x00, 0x0b, 0x0b]).try_locals(),
+			Err(Error::InconsistentLength { .. })
+		));
+	}
+
+	#[test]
+	fn lazy_malformed_body() {
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![FunctionType::default().into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::from_raw(vec![0x00, 0xff, 0x0b])])),
+		]);
+		let wasm = serialize(module).expect("serialization to succeed");
+		let module = deserialize_lazy(&wasm).expect("deserialization to succeed");
+
+		match validate(&module) {
+			Err(ValidationError::Function { func: 0, error, .. }) => {
+				assert!(matches!(*error, ValidationError::MalformedBody(_)));
+			},
+			other => panic!("Expected malformed body, got {:?}", other),
+		}
+		assert!(module.used_features().is_empty());
+		assert!(matches!(Features::all().check(&module).unwrap_err().kind(), Error::UnknownOpcode(0xff)));
+	}
+
 
 	#[test]
 	fn memory_space() {
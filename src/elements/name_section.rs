@@ -4,8 +4,8 @@ use crate::io;
 use super::{Deserialize, Error, Module, Serialize, VarUint32, VarUint7, Type};
 use super::index_map::IndexMap;
 
-const NAME_TYPE_MODULE: u8 = 0;
-const NAME_TYPE_FUNCTION: u8 = 1;
+pub(crate) const NAME_TYPE_MODULE: u8 = 0;
+pub(crate) const NAME_TYPE_FUNCTION: u8 = 1;
 const NAME_TYPE_LOCAL: u8 = 2;
 
 /// Debug name information.
@@ -203,10 +203,10 @@ impl LocalNameSection {
 					.unwrap_or(0))
 			.unwrap_or(0);
 
-		let max_locals = module
-			.code_section()
-			.map(|cs| cs.bodies().iter().map(|f| f.locals().len()).max().unwrap_or(0))
-			.unwrap_or(0);
+		let mut max_locals = 0;
+		for body in module.code_section().map(|cs| cs.bodies()).unwrap_or(&[]) {
+			max_locals = max_locals.max(body.try_locals()?.len());
+		}
 
 		let max_space = max_signature_args + max_locals;
 
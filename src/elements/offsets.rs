@@ -0,0 +1,99 @@
+//! Byte offsets of decoded sections, function bodies and instructions.
+
+use crate::rust::{ops::Range, vec::Vec};
+use crate::io;
+use super::{Error, Module};
+use super::section::BodyDecoding;
+
+/// Byte ranges of a function body and its instructions in the decoded input.
+///
+/// Ranges are relative to the start of the module.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FuncOffsets {
+	body: Range<usize>,
+	instructions: Vec<Range<usize>>,
+}
+
+impl FuncOffsets {
+	pub(crate) fn new(body: Range<usize>, instructions: Vec<Range<usize>>) -> Self {
+		FuncOffsets { body, instructions }
+	}
+
+	/// Byte range of the function body, including its size prefix.
+	pub fn body(&self) -> Range<usize> {
+		self.body.clone()
+	}
+
+	/// Byte ranges of the instructions, in the order of the body instructions.
+	pub fn instructions(&self) -> &[Range<usize>] {
+		&self.instructions
+	}
+
+	/// Index of the instruction that contains the given byte offset, if any.
+	pub fn instruction_at(&self, offset: usize) -> Option<usize> {
+		let idx = self.instructions.partition_point(|range| range.end <= offset);
+		match self.instructions.get(idx) {
+			Some(range) if range.start <= offset => Some(idx),
+			_ => None,
+		}
+	}
+}
+
+/// Deserialize a module from a buffer, recording byte offsets of its sections,
+/// function bodies and instructions.
+///
+/// See [`Module::section_offsets`] and [`FuncBody::offsets`](super::FuncBody::offsets).
+pub fn deserialize_with_offsets(contents: &[u8]) -> Result<Module, Error> {
+	let mut reader = io::Cursor::new(contents);
+	let module = Module::deserialize_sections(&mut reader, BodyDecoding::Offsets, |_| Ok(()))?;
+	if reader.position() != contents.len() {
+		return Err(io::Error::TrailingData.into())
+	}
+	Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		serialize, Module, Section, TypeSection, FunctionSection, CodeSection, FunctionType, Func,
+		FuncBody, Instructions, Instruction, deserialize_buffer,
+	};
+	use super::deserialize_with_offsets;
+
+	#[test]
+	fn offsets() {
+		let code = vec![Instruction::I32Const(1000), Instruction::Drop, Instruction::End];
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![FunctionType::default().into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::new(Vec::new(), Instructions::new(code.clone()))])),
+		]);
+		let wasm = serialize(module).expect("module to serialize");
+
+		let module = deserialize_with_offsets(&wasm).expect("module to deserialize");
+		let sections = module.section_offsets().expect("section offsets");
+		assert_eq!(sections.len(), 3);
+		assert_eq!(sections[0].start, 8);
+		assert_eq!(sections[2].end, wasm.len());
+		assert_eq!(wasm[sections[2].start], 10);
+
+		let offsets = module.code_section().unwrap().bodies()[0].offsets().expect("body offsets");
+		assert_eq!(offsets.body().end, wasm.len());
+		// Size prefix and an empty list of locals.
+		assert_eq!(offsets.instructions()[0].start, offsets.body().start + 2);
+		// i32.const with a two byte immediate.
+		assert_eq!(offsets.instructions()[0].len(), 3);
+		assert_eq!(&wasm[offsets.instructions()[1].clone()], &[0x1a]);
+		assert_eq!(offsets.instructions()[2].end, wasm.len());
+		assert_eq!(offsets.instruction_at(offsets.body().start + 3), Some(0));
+		assert_eq!(offsets.instruction_at(wasm.len() - 1), Some(2));
+		assert_eq!(offsets.instruction_at(wasm.len()), None);
+		assert_eq!(offsets.instruction_at(0), None);
+
+		let plain: Module = deserialize_buffer(&wasm).expect("module to deserialize");
+		assert!(plain.section_offsets().is_none());
+		assert!(plain.code_section().unwrap().bodies()[0].offsets().is_none());
+		assert_eq!(plain, module);
+		assert_eq!(plain.code_section().unwrap().bodies()[0], FuncBody::new(Vec::new(), Instructions::new(code)));
+	}
+}
@@ -1,10 +1,10 @@
-use crate::rust::{fmt, vec::Vec, boxed::Box};
+use crate::rust::{fmt, ops::Range, vec::Vec, boxed::Box};
 use crate::io;
 use super::{
-	Serialize, Deserialize, Error,
-	Uint8, VarUint32, CountedList, BlockType,
+	Serialize, Deserialize, Error, Context,
+	Uint8, VarUint32, VarUint64, CountedList, BlockType, HeapType, RefType,
 	Uint32, Uint64, CountedListWriter,
-	VarInt32, VarInt64,
+	VarInt32, VarInt64, Dialect,
 };
 
 /// List of instructions (usually inside a block section).
@@ -29,15 +29,28 @@ impl Instructions {
 	pub fn elements_mut(&mut self) -> &mut Vec<Instruction> { &mut self.0 }
 }
 
-impl Deserialize for Instructions {
-	type Error = Error;
+impl Instructions {
+	/// Read a function body expression encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		Instructions::deserialize_ranges(reader, dialect, None)
+	}
 
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+	/// Read a function body expression, pushing the byte range of every instruction to `ranges`.
+	pub(crate) fn deserialize_ranges<R: io::Read>(
+		reader: &mut R,
+		dialect: Dialect,
+		mut ranges: Option<&mut Vec<Range<usize>>>,
+	) -> Result<Self, Error> {
 		let mut instructions = Vec::new();
 		let mut block_count = 1usize;
 
 		loop {
-			let instruction = Instruction::deserialize(reader)?;
+			let start = reader.position();
+			let instruction = Instruction::deserialize_dialect(reader, dialect)
+				.map_err(|e| e.located(Context::Instruction(instructions.len() as u32), start))?;
+			if let (Some(ranges), Some(start), Some(end)) = (ranges.as_mut(), start, reader.position()) {
+				ranges.push(start..end);
+			}
 			if instruction.is_terminal() {
 				block_count -= 1;
 			} else if instruction.is_block() {
@@ -54,6 +67,14 @@ impl Deserialize for Instructions {
 	}
 }
 
+impl Deserialize for Instructions {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		Instructions::deserialize_dialect(reader, Dialect::Standard)
+	}
+}
+
 /// Initialization expression.
 #[derive(Debug, Clone, PartialEq)]
 pub struct InitExpr(Vec<Instruction>);
@@ -82,14 +103,13 @@ impl InitExpr {
 	}
 }
 
-impl Deserialize for InitExpr {
-	type Error = Error;
-
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+impl InitExpr {
+	/// Read an initialization expression encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
 		let mut instructions = Vec::new();
 
 		loop {
-			let instruction = Instruction::deserialize(reader)?;
+			let instruction = Instruction::deserialize_dialect(reader, dialect)?;
 			let is_terminal = instruction.is_terminal();
 			instructions.push(instruction);
 			if is_terminal {
@@ -101,6 +121,14 @@ impl Deserialize for InitExpr {
 	}
 }
 
+impl Deserialize for InitExpr {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		InitExpr::deserialize_dialect(reader, Dialect::Standard)
+	}
+}
+
 /// Instruction.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[allow(missing_docs)]
@@ -118,10 +146,18 @@ pub enum Instruction {
 	Return,
 
 	Call(u32),
-	CallIndirect(u32, u8),
+	/// Type and table.
+	CallIndirect(u32, u32),
+	CallRef(u32),
+	ReturnCallRef(u32),
 
-	RefNull,
+	RefNull(HeapType),
 	RefIsNull,
+	RefFunc(u32),
+	RefEq,
+	RefAsNonNull,
+	BrOnNull(u32),
+	BrOnNonNull(u32),
 
 	Drop,
 	Select,
@@ -135,33 +171,32 @@ pub enum Instruction {
 	SetTable(u32),
 
 	// All store/load instructions operate with 'memory immediates'
-	// which represented here as (flag, offset) tuple
-	I32Load(u32, u32),
-	I64Load(u32, u32),
-	F32Load(u32, u32),
-	F64Load(u32, u32),
-	I32Load8S(u32, u32),
-	I32Load8U(u32, u32),
-	I32Load16S(u32, u32),
-	I32Load16U(u32, u32),
-	I64Load8S(u32, u32),
-	I64Load8U(u32, u32),
-	I64Load16S(u32, u32),
-	I64Load16U(u32, u32),
-	I64Load32S(u32, u32),
-	I64Load32U(u32, u32),
-	I32Store(u32, u32),
-	I64Store(u32, u32),
-	F32Store(u32, u32),
-	F64Store(u32, u32),
-	I32Store8(u32, u32),
-	I32Store16(u32, u32),
-	I64Store8(u32, u32),
-	I64Store16(u32, u32),
-	I64Store32(u32, u32),
-
-	CurrentMemory(u8),
-	GrowMemory(u8),
+	I32Load(MemArg),
+	I64Load(MemArg),
+	F32Load(MemArg),
+	F64Load(MemArg),
+	I32Load8S(MemArg),
+	I32Load8U(MemArg),
+	I32Load16S(MemArg),
+	I32Load16U(MemArg),
+	I64Load8S(MemArg),
+	I64Load8U(MemArg),
+	I64Load16S(MemArg),
+	I64Load16U(MemArg),
+	I64Load32S(MemArg),
+	I64Load32U(MemArg),
+	I32Store(MemArg),
+	I64Store(MemArg),
+	F32Store(MemArg),
+	F64Store(MemArg),
+	I32Store8(MemArg),
+	I32Store16(MemArg),
+	I64Store8(MemArg),
+	I64Store16(MemArg),
+	I64Store32(MemArg),
+
+	CurrentMemory(u32),
+	GrowMemory(u32),
 
 	I32Const(i32),
 	I64Const(i64),
@@ -533,20 +568,69 @@ pub enum Instruction {
 	I64x2TruncUF64x2Sat,
 
 	// https://github.com/WebAssembly/bulk-memory-operations
-	MemoryInit(u32),
+	/// Data segment and memory.
+	MemoryInit(u32, u32),
 	MemoryDrop(u32),
-	MemoryCopy,
-	MemoryFill,
-	TableInit(u32),
+	/// Destination and source memories.
+	MemoryCopy(u32, u32),
+	MemoryFill(u32),
+	/// Element segment and table.
+	TableInit(u32, u32),
 	TableDrop(u32),
-	TableCopy,
+	/// Destination and source tables.
+	TableCopy(u32, u32),
+	TableGrow(u32),
+	TableSize(u32),
+	TableFill(u32),
+
+	// https://github.com/WebAssembly/gc
+	StructNew(u32),
+	StructNewDefault(u32),
+	StructGet(u32, u32),
+	StructGetS(u32, u32),
+	StructGetU(u32, u32),
+	StructSet(u32, u32),
+	ArrayNew(u32),
+	ArrayNewDefault(u32),
+	ArrayNewFixed(u32, u32),
+	ArrayGet(u32),
+	ArrayGetS(u32),
+	ArrayGetU(u32),
+	ArraySet(u32),
+	ArrayLen,
+	ArrayNewData(u32, u32),
+	ArrayNewElem(u32, u32),
+	ArrayFill(u32),
+	ArrayCopy(u32, u32),
+	ArrayInitData(u32, u32),
+	ArrayInitElem(u32, u32),
+	RefTest(RefType),
+	RefCast(RefType),
+	BrOnCast(Box<BrOnCastData>),
+	BrOnCastFail(Box<BrOnCastData>),
+	AnyConvertExtern,
+	ExternConvertAny,
+	RefI31,
+	I31GetS,
+	I31GetU,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-#[allow(missing_docs)]
+/// Immediates of a memory access.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct MemArg {
+	/// Alignment of the access, as a power of two.
 	pub align: u8,
-	pub offset: u32,
+	/// Offset added to the address operand.
+	pub offset: u64,
+	/// Index of the accessed memory.
+	pub memory: u32,
+}
+
+impl MemArg {
+	/// Access to the first memory with the given alignment and offset.
+	pub fn new(align: u8, offset: u64) -> Self {
+		MemArg { align, offset, memory: 0 }
+	}
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -556,6 +640,17 @@ pub struct BrTableData {
 	pub default: u32,
 }
 
+/// Immediates of the `br_on_cast` and `br_on_cast_fail` instructions.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BrOnCastData {
+	/// Label to branch to.
+	pub label: u32,
+	/// Type of the operand.
+	pub from: RefType,
+	/// Type to cast the operand to.
+	pub to: RefType,
+}
+
 impl Instruction {
 	/// Is this instruction starts the new block (which should end with terminal instruction).
 	pub fn is_block(&self) -> bool {
@@ -574,6 +669,47 @@ impl Instruction {
 			_ => false,
 		}
 	}
+
+	/// Immediates of a memory access instruction.
+	pub fn memarg(&self) -> Option<&MemArg> {
+		use self::Instruction::*;
+
+		match *self {
+			I32Load(ref memarg) | I64Load(ref memarg) | F32Load(ref memarg) | F64Load(ref memarg) |
+			I32Load8S(ref memarg) | I32Load8U(ref memarg) | I32Load16S(ref memarg) |
+			I32Load16U(ref memarg) | I64Load8S(ref memarg) | I64Load8U(ref memarg) |
+			I64Load16S(ref memarg) | I64Load16U(ref memarg) | I64Load32S(ref memarg) |
+			I64Load32U(ref memarg) | I32Store(ref memarg) | I64Store(ref memarg) | F32Store(ref memarg) |
+			F64Store(ref memarg) | I32Store8(ref memarg) | I32Store16(ref memarg) | I64Store8(ref memarg) |
+			I64Store16(ref memarg) | I64Store32(ref memarg) | AtomicWake(ref memarg) |
+			I32AtomicWait(ref memarg) | I64AtomicWait(ref memarg) | I32AtomicLoad(ref memarg) |
+			I64AtomicLoad(ref memarg) | I32AtomicLoad8u(ref memarg) | I32AtomicLoad16u(ref memarg) |
+			I64AtomicLoad8u(ref memarg) | I64AtomicLoad16u(ref memarg) | I64AtomicLoad32u(ref memarg) |
+			I32AtomicStore(ref memarg) | I64AtomicStore(ref memarg) | I32AtomicStore8u(ref memarg) |
+			I32AtomicStore16u(ref memarg) | I64AtomicStore8u(ref memarg) | I64AtomicStore16u(ref memarg) |
+			I64AtomicStore32u(ref memarg) | I32AtomicRmwAdd(ref memarg) | I64AtomicRmwAdd(ref memarg) |
+			I32AtomicRmwAdd8u(ref memarg) | I32AtomicRmwAdd16u(ref memarg) | I64AtomicRmwAdd8u(ref memarg) |
+			I64AtomicRmwAdd16u(ref memarg) | I64AtomicRmwAdd32u(ref memarg) | I32AtomicRmwSub(ref memarg) |
+			I64AtomicRmwSub(ref memarg) | I32AtomicRmwSub8u(ref memarg) | I32AtomicRmwSub16u(ref memarg) |
+			I64AtomicRmwSub8u(ref memarg) | I64AtomicRmwSub16u(ref memarg) |
+			I64AtomicRmwSub32u(ref memarg) | I32AtomicRmwAnd(ref memarg) | I64AtomicRmwAnd(ref memarg) |
+			I32AtomicRmwAnd8u(ref memarg) | I32AtomicRmwAnd16u(ref memarg) | I64AtomicRmwAnd8u(ref memarg) |
+			I64AtomicRmwAnd16u(ref memarg) | I64AtomicRmwAnd32u(ref memarg) | I32AtomicRmwOr(ref memarg) |
+			I64AtomicRmwOr(ref memarg) | I32AtomicRmwOr8u(ref memarg) | I32AtomicRmwOr16u(ref memarg) |
+			I64AtomicRmwOr8u(ref memarg) | I64AtomicRmwOr16u(ref memarg) | I64AtomicRmwOr32u(ref memarg) |
+			I32AtomicRmwXor(ref memarg) | I64AtomicRmwXor(ref memarg) | I32AtomicRmwXor8u(ref memarg) |
+			I32AtomicRmwXor16u(ref memarg) | I64AtomicRmwXor8u(ref memarg) |
+			I64AtomicRmwXor16u(ref memarg) | I64AtomicRmwXor32u(ref memarg) | I32AtomicRmwXchg(ref memarg) |
+			I64AtomicRmwXchg(ref memarg) | I32AtomicRmwXchg8u(ref memarg) |
+			I32AtomicRmwXchg16u(ref memarg) | I64AtomicRmwXchg8u(ref memarg) |
+			I64AtomicRmwXchg16u(ref memarg) | I64AtomicRmwXchg32u(ref memarg) |
+			I32AtomicRmwCmpxchg(ref memarg) | I64AtomicRmwCmpxchg(ref memarg) |
+			I32AtomicRmwCmpxchg8u(ref memarg) | I32AtomicRmwCmpxchg16u(ref memarg) |
+			I64AtomicRmwCmpxchg8u(ref memarg) | I64AtomicRmwCmpxchg16u(ref memarg) |
+			I64AtomicRmwCmpxchg32u(ref memarg) | V128Load(ref memarg) | V128Store(ref memarg) => Some(memarg),
+			_ => None,
+		}
+	}
 }
 
 #[allow(missing_docs)]
@@ -591,8 +727,15 @@ pub mod opcodes {
 	pub const RETURN: u8 = 0x0f;
 	pub const CALL: u8 = 0x10;
 	pub const CALLINDIRECT: u8 = 0x11;
+	pub const CALLREF: u8 = 0x14;
+	pub const RETURNCALLREF: u8 = 0x15;
 	pub const REFNULL: u8 = 0xD0;
 	pub const REFISNULL: u8 = 0xD1;
+	pub const REFFUNC: u8 = 0xD2;
+	pub const REFEQ: u8 = 0xD3;
+	pub const REFASNONNULL: u8 = 0xD4;
+	pub const BRONNULL: u8 = 0xD5;
+	pub const BRONNONNULL: u8 = 0xD6;
 	pub const DROP: u8 = 0x1a;
 	pub const SELECT: u8 = 0x1b;
 	pub const GETLOCAL: u8 = 0x20;
@@ -1014,12 +1157,48 @@ pub mod opcodes {
 	pub const TABLE_INIT: u8 = 0x0c;
 	pub const TABLE_DROP: u8 = 0x0d;
 	pub const TABLE_COPY: u8 = 0x0e;
+	pub const TABLE_GROW: u8 = 0x0f;
+	pub const TABLE_SIZE: u8 = 0x10;
+	pub const TABLE_FILL: u8 = 0x11;
+
+	// https://github.com/WebAssembly/gc/blob/master/proposals/gc/MVP.md
+	pub const GC_PREFIX: u8 = 0xfb;
+	pub const STRUCT_NEW: u32 = 0x00;
+	pub const STRUCT_NEW_DEFAULT: u32 = 0x01;
+	pub const STRUCT_GET: u32 = 0x02;
+	pub const STRUCT_GET_S: u32 = 0x03;
+	pub const STRUCT_GET_U: u32 = 0x04;
+	pub const STRUCT_SET: u32 = 0x05;
+	pub const ARRAY_NEW: u32 = 0x06;
+	pub const ARRAY_NEW_DEFAULT: u32 = 0x07;
+	pub const ARRAY_NEW_FIXED: u32 = 0x08;
+	pub const ARRAY_NEW_DATA: u32 = 0x09;
+	pub const ARRAY_NEW_ELEM: u32 = 0x0a;
+	pub const ARRAY_GET: u32 = 0x0b;
+	pub const ARRAY_GET_S: u32 = 0x0c;
+	pub const ARRAY_GET_U: u32 = 0x0d;
+	pub const ARRAY_SET: u32 = 0x0e;
+	pub const ARRAY_LEN: u32 = 0x0f;
+	pub const ARRAY_FILL: u32 = 0x10;
+	pub const ARRAY_COPY: u32 = 0x11;
+	pub const ARRAY_INIT_DATA: u32 = 0x12;
+	pub const ARRAY_INIT_ELEM: u32 = 0x13;
+	pub const REF_TEST: u32 = 0x14;
+	pub const REF_TEST_NULL: u32 = 0x15;
+	pub const REF_CAST: u32 = 0x16;
+	pub const REF_CAST_NULL: u32 = 0x17;
+	pub const BR_ON_CAST: u32 = 0x18;
+	pub const BR_ON_CAST_FAIL: u32 = 0x19;
+	pub const ANY_CONVERT_EXTERN: u32 = 0x1a;
+	pub const EXTERN_CONVERT_ANY: u32 = 0x1b;
+	pub const REF_I31: u32 = 0x1c;
+	pub const I31_GET_S: u32 = 0x1d;
+	pub const I31_GET_U: u32 = 0x1e;
 }
 
-impl Deserialize for Instruction {
-	type Error = Error;
-
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+impl Instruction {
+	/// Read an instruction encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
 		use self::Instruction::*;
 		use self::opcodes::*;
 
@@ -1029,9 +1208,9 @@ impl Deserialize for Instruction {
 			match val {
 				UNREACHABLE => Unreachable,
 				NOP => Nop,
-				BLOCK => Block(BlockType::deserialize(reader)?),
-				LOOP => Loop(BlockType::deserialize(reader)?),
-				IF => If(BlockType::deserialize(reader)?),
+				BLOCK => Block(BlockType::deserialize_dialect(reader, dialect)?),
+				LOOP => Loop(BlockType::deserialize_dialect(reader, dialect)?),
+				IF => If(BlockType::deserialize_dialect(reader, dialect)?),
 				ELSE => Else,
 				END => End,
 
@@ -1051,19 +1230,23 @@ impl Deserialize for Instruction {
 				},
 				RETURN => Return,
 				CALL => Call(VarUint32::deserialize(reader)?.into()),
-				CALLINDIRECT => {
-					let signature: u32 = VarUint32::deserialize(reader)?.into();
-					let table_ref: u8 = Uint8::deserialize(reader)?.into();
-					if table_ref != 0 { return Err(Error::InvalidTableReference(table_ref)); }
-
-					CallIndirect(
-						signature,
-						table_ref,
-					)
-				},
+				CALLINDIRECT => CallIndirect(
+					VarUint32::deserialize(reader)?.into(),
+					VarUint32::deserialize(reader)?.into(),
+				),
+
+				CALLREF => CallRef(VarUint32::deserialize(reader)?.into()),
+				RETURNCALLREF => ReturnCallRef(VarUint32::deserialize(reader)?.into()),
 
-				REFNULL => RefNull,
+				// The prototype `ref.null` has no immediate and produces the legacy `ref` type.
+				REFNULL if dialect == Dialect::Legacy => RefNull(HeapType::Index(0)),
+				REFNULL => RefNull(HeapType::deserialize(reader)?),
 				REFISNULL => RefIsNull,
+				REFFUNC => RefFunc(VarUint32::deserialize(reader)?.into()),
+				REFEQ => RefEq,
+				REFASNONNULL => RefAsNonNull,
+				BRONNULL => BrOnNull(VarUint32::deserialize(reader)?.into()),
+				BRONNONNULL => BrOnNonNull(VarUint32::deserialize(reader)?.into()),
 
 				DROP => Drop,
 				SELECT => Select,
@@ -1076,109 +1259,32 @@ impl Deserialize for Instruction {
 				GETTABLE => GetTable(VarUint32::deserialize(reader)?.into()),
 				SETTABLE => SetTable(VarUint32::deserialize(reader)?.into()),
 
-				I32LOAD => I32Load(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD => I64Load(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				F32LOAD => F32Load(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				F64LOAD => F64Load(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32LOAD8S => I32Load8S(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32LOAD8U => I32Load8U(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32LOAD16S => I32Load16S(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32LOAD16U => I32Load16U(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD8S => I64Load8S(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD8U => I64Load8U(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD16S => I64Load16S(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD16U => I64Load16U(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD32S => I64Load32S(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64LOAD32U => I64Load32U(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32STORE => I32Store(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64STORE => I64Store(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				F32STORE => F32Store(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				F64STORE => F64Store(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32STORE8 => I32Store8(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I32STORE16 => I32Store16(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64STORE8 => I64Store8(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64STORE16 => I64Store16(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-				I64STORE32 => I64Store32(
-					VarUint32::deserialize(reader)?.into(),
-					VarUint32::deserialize(reader)?.into()),
-
-
-				CURRENTMEMORY => {
-					let mem_ref: u8 = Uint8::deserialize(reader)?.into();
-					if mem_ref != 0 { return Err(Error::InvalidMemoryReference(mem_ref)); }
-					CurrentMemory(mem_ref)
-				},
-				GROWMEMORY => {
-					let mem_ref: u8 = Uint8::deserialize(reader)?.into();
-					if mem_ref != 0 { return Err(Error::InvalidMemoryReference(mem_ref)); }
-					GrowMemory(mem_ref)
-				}
+				I32LOAD => I32Load(MemArg::deserialize(reader)?),
+				I64LOAD => I64Load(MemArg::deserialize(reader)?),
+				F32LOAD => F32Load(MemArg::deserialize(reader)?),
+				F64LOAD => F64Load(MemArg::deserialize(reader)?),
+				I32LOAD8S => I32Load8S(MemArg::deserialize(reader)?),
+				I32LOAD8U => I32Load8U(MemArg::deserialize(reader)?),
+				I32LOAD16S => I32Load16S(MemArg::deserialize(reader)?),
+				I32LOAD16U => I32Load16U(MemArg::deserialize(reader)?),
+				I64LOAD8S => I64Load8S(MemArg::deserialize(reader)?),
+				I64LOAD8U => I64Load8U(MemArg::deserialize(reader)?),
+				I64LOAD16S => I64Load16S(MemArg::deserialize(reader)?),
+				I64LOAD16U => I64Load16U(MemArg::deserialize(reader)?),
+				I64LOAD32S => I64Load32S(MemArg::deserialize(reader)?),
+				I64LOAD32U => I64Load32U(MemArg::deserialize(reader)?),
+				I32STORE => I32Store(MemArg::deserialize(reader)?),
+				I64STORE => I64Store(MemArg::deserialize(reader)?),
+				F32STORE => F32Store(MemArg::deserialize(reader)?),
+				F64STORE => F64Store(MemArg::deserialize(reader)?),
+				I32STORE8 => I32Store8(MemArg::deserialize(reader)?),
+				I32STORE16 => I32Store16(MemArg::deserialize(reader)?),
+				I64STORE8 => I64Store8(MemArg::deserialize(reader)?),
+				I64STORE16 => I64Store16(MemArg::deserialize(reader)?),
+				I64STORE32 => I64Store32(MemArg::deserialize(reader)?),
+
+				CURRENTMEMORY => CurrentMemory(VarUint32::deserialize(reader)?.into()),
+				GROWMEMORY => GrowMemory(VarUint32::deserialize(reader)?.into()),
 
 				I32CONST => I32Const(VarInt32::deserialize(reader)?.into()),
 				I64CONST => I64Const(VarInt64::deserialize(reader)?.into()),
@@ -1325,12 +1431,22 @@ impl Deserialize for Instruction {
 
 				BULK_PREFIX => return deserialize_bulk(reader),
 
+				GC_PREFIX => return deserialize_gc(reader),
+
 				_ => { return Err(Error::UnknownOpcode(val)); }
 			}
 		)
 	}
 }
 
+impl Deserialize for Instruction {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		Instruction::deserialize_dialect(reader, Dialect::Standard)
+	}
+}
+
 fn deserialize_atomic<R: io::Read>(reader: &mut R) -> Result<Instruction, Error> {
 	use self::Instruction::*;
 	use self::opcodes::*;
@@ -1586,51 +1702,117 @@ fn deserialize_bulk<R: io::Read>(reader: &mut R) -> Result<Instruction, Error> {
 
 	let val: u8 = Uint8::deserialize(reader)?.into();
 	Ok(match val {
-		MEMORY_INIT => {
-			if u8::from(Uint8::deserialize(reader)?) != 0 {
-				return Err(Error::UnknownOpcode(val))
-			}
-			MemoryInit(VarUint32::deserialize(reader)?.into())
-		}
+		MEMORY_INIT => MemoryInit(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into(),
+		),
 		MEMORY_DROP => MemoryDrop(VarUint32::deserialize(reader)?.into()),
-		MEMORY_FILL => {
-			if u8::from(Uint8::deserialize(reader)?) != 0 {
-				return Err(Error::UnknownOpcode(val))
-			}
-			MemoryFill
-		}
-		MEMORY_COPY => {
-			if u8::from(Uint8::deserialize(reader)?) != 0 {
-				return Err(Error::UnknownOpcode(val))
-			}
-			MemoryCopy
-		}
-
-		TABLE_INIT => {
-			if u8::from(Uint8::deserialize(reader)?) != 0 {
-				return Err(Error::UnknownOpcode(val))
-			}
-			TableInit(VarUint32::deserialize(reader)?.into())
-		}
+		MEMORY_FILL => MemoryFill(VarUint32::deserialize(reader)?.into()),
+		MEMORY_COPY => MemoryCopy(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into(),
+		),
+
+		TABLE_INIT => TableInit(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into(),
+		),
 		TABLE_DROP => TableDrop(VarUint32::deserialize(reader)?.into()),
-		TABLE_COPY => {
-			if u8::from(Uint8::deserialize(reader)?) != 0 {
-				return Err(Error::UnknownOpcode(val))
-			}
-			TableCopy
-		}
+		TABLE_COPY => TableCopy(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into(),
+		),
+		TABLE_GROW => TableGrow(VarUint32::deserialize(reader)?.into()),
+		TABLE_SIZE => TableSize(VarUint32::deserialize(reader)?.into()),
+		TABLE_FILL => TableFill(VarUint32::deserialize(reader)?.into()),
 
 		_ => return Err(Error::UnknownOpcode(val)),
 	})
 }
 
+fn deserialize_gc<R: io::Read>(reader: &mut R) -> Result<Instruction, Error> {
+	use self::Instruction::*;
+	use self::opcodes::*;
+
+	let val = VarUint32::deserialize(reader)?.into();
+	Ok(match val {
+		STRUCT_NEW => StructNew(VarUint32::deserialize(reader)?.into()),
+		STRUCT_NEW_DEFAULT => StructNewDefault(VarUint32::deserialize(reader)?.into()),
+		STRUCT_GET => StructGet(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		STRUCT_GET_S => StructGetS(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		STRUCT_GET_U => StructGetU(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		STRUCT_SET => StructSet(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+
+		ARRAY_NEW => ArrayNew(VarUint32::deserialize(reader)?.into()),
+		ARRAY_NEW_DEFAULT => ArrayNewDefault(VarUint32::deserialize(reader)?.into()),
+		ARRAY_NEW_FIXED => ArrayNewFixed(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		ARRAY_GET => ArrayGet(VarUint32::deserialize(reader)?.into()),
+		ARRAY_GET_S => ArrayGetS(VarUint32::deserialize(reader)?.into()),
+		ARRAY_GET_U => ArrayGetU(VarUint32::deserialize(reader)?.into()),
+		ARRAY_SET => ArraySet(VarUint32::deserialize(reader)?.into()),
+		ARRAY_LEN => ArrayLen,
+		ARRAY_NEW_DATA => ArrayNewData(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		ARRAY_NEW_ELEM => ArrayNewElem(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		ARRAY_FILL => ArrayFill(VarUint32::deserialize(reader)?.into()),
+		ARRAY_COPY => ArrayCopy(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		ARRAY_INIT_DATA => ArrayInitData(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		ARRAY_INIT_ELEM => ArrayInitElem(
+			VarUint32::deserialize(reader)?.into(),
+			VarUint32::deserialize(reader)?.into()),
+		REF_TEST => RefTest(RefType::new(false, HeapType::deserialize(reader)?)),
+		REF_TEST_NULL => RefTest(RefType::new(true, HeapType::deserialize(reader)?)),
+		REF_CAST => RefCast(RefType::new(false, HeapType::deserialize(reader)?)),
+		REF_CAST_NULL => RefCast(RefType::new(true, HeapType::deserialize(reader)?)),
+		BR_ON_CAST => BrOnCast(Box::new(BrOnCastData::deserialize(reader)?)),
+		BR_ON_CAST_FAIL => BrOnCastFail(Box::new(BrOnCastData::deserialize(reader)?)),
+		ANY_CONVERT_EXTERN => AnyConvertExtern,
+		EXTERN_CONVERT_ANY => ExternConvertAny,
+		REF_I31 => RefI31,
+		I31_GET_S => I31GetS,
+		I31_GET_U => I31GetU,
+
+		_ => return Err(Error::UnknownGcOpcode(val)),
+	})
+}
+
+/// Alignment flag that signals an explicit memory index.
+const MEMARG_MEMORY_FLAG: u32 = 0x40;
+
 impl Deserialize for MemArg {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let align = Uint8::deserialize(reader)?;
-		let offset = VarUint32::deserialize(reader)?;
-		Ok(MemArg { align: align.into(), offset: offset.into() })
+		let flags: u32 = VarUint32::deserialize(reader)?.into();
+		// Bit 6 of the alignment flags signals an explicit memory index.
+		let memory = if flags & MEMARG_MEMORY_FLAG != 0 {
+			VarUint32::deserialize(reader)?.into()
+		} else {
+			0
+		};
+		let align = flags & !MEMARG_MEMORY_FLAG;
+		if align >= MEMARG_MEMORY_FLAG {
+			return Err(Error::Other("Invalid memory access alignment"));
+		}
+		let offset = VarUint64::deserialize(reader)?;
+		Ok(MemArg { align: align as u8, offset: offset.into(), memory })
 	}
 }
 
@@ -1670,6 +1852,57 @@ macro_rules! bulk {
 	});
 }
 
+const CAST_FROM_NULLABLE: u8 = 0x01;
+const CAST_TO_NULLABLE: u8 = 0x02;
+
+impl Deserialize for BrOnCastData {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		let flags: u8 = Uint8::deserialize(reader)?.into();
+		if flags & !(CAST_FROM_NULLABLE | CAST_TO_NULLABLE) != 0 {
+			return Err(Error::Other("Invalid br_on_cast flags"));
+		}
+		let label = VarUint32::deserialize(reader)?.into();
+		let from = HeapType::deserialize(reader)?;
+		let to = HeapType::deserialize(reader)?;
+		Ok(BrOnCastData {
+			label,
+			from: RefType::new(flags & CAST_FROM_NULLABLE != 0, from),
+			to: RefType::new(flags & CAST_TO_NULLABLE != 0, to),
+		})
+	}
+}
+
+impl Serialize for BrOnCastData {
+	type Error = Error;
+
+	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
+		let mut flags = 0;
+		if self.from.is_nullable() {
+			flags |= CAST_FROM_NULLABLE;
+		}
+		if self.to.is_nullable() {
+			flags |= CAST_TO_NULLABLE;
+		}
+		Uint8::from(flags).serialize(writer)?;
+		VarUint32::from(self.label).serialize(writer)?;
+		self.from.heap_type().serialize(writer)?;
+		self.to.heap_type().serialize(writer)
+	}
+}
+
+macro_rules! gc {
+	($writer: expr, $byte: expr) => ({
+		$writer.write(&[GC_PREFIX])?;
+		VarUint32::from($byte).serialize($writer)?;
+	});
+	($writer: expr, $byte: expr, $remaining:expr) => ({
+		gc!($writer, $byte);
+		$remaining;
+	});
+}
+
 impl Serialize for Instruction {
 	type Error = Error;
 
@@ -1709,12 +1942,31 @@ impl Serialize for Instruction {
 			Call(index) => op!(writer, CALL, {
 				VarUint32::from(index).serialize(writer)?;
 			}),
-			CallIndirect(index, reserved) => op!(writer, CALLINDIRECT, {
+			CallIndirect(index, table) => op!(writer, CALLINDIRECT, {
+				VarUint32::from(index).serialize(writer)?;
+				VarUint32::from(table).serialize(writer)?;
+			}),
+			CallRef(index) => op!(writer, CALLREF, {
 				VarUint32::from(index).serialize(writer)?;
-				Uint8::from(reserved).serialize(writer)?;
 			}),
-			RefNull => op!(writer, REFNULL),
+			ReturnCallRef(index) => op!(writer, RETURNCALLREF, {
+				VarUint32::from(index).serialize(writer)?;
+			}),
+			RefNull(heap_type) => op!(writer, REFNULL, {
+				heap_type.serialize(writer)?;
+			}),
 			RefIsNull => op!(writer, REFISNULL),
+			RefFunc(index) => op!(writer, REFFUNC, {
+				VarUint32::from(index).serialize(writer)?;
+			}),
+			RefEq => op!(writer, REFEQ),
+			RefAsNonNull => op!(writer, REFASNONNULL),
+			BrOnNull(idx) => op!(writer, BRONNULL, {
+				VarUint32::from(idx).serialize(writer)?;
+			}),
+			BrOnNonNull(idx) => op!(writer, BRONNONNULL, {
+				VarUint32::from(idx).serialize(writer)?;
+			}),
 			Drop => op!(writer, DROP),
 			Select => op!(writer, SELECT),
 			GetLocal(index) => op!(writer, GETLOCAL, {
@@ -1738,103 +1990,34 @@ impl Serialize for Instruction {
 			SetTable(index) => op!(writer, SETTABLE, {
 				VarUint32::from(index).serialize(writer)?;
 			}),
-			I32Load(flags, offset) => op!(writer, I32LOAD, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load(flags, offset) => op!(writer, I64LOAD, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			F32Load(flags, offset) => op!(writer, F32LOAD, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			F64Load(flags, offset) => op!(writer, F64LOAD, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Load8S(flags, offset) => op!(writer, I32LOAD8S, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Load8U(flags, offset) => op!(writer, I32LOAD8U, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Load16S(flags, offset) => op!(writer, I32LOAD16S, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Load16U(flags, offset) => op!(writer, I32LOAD16U, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load8S(flags, offset) => op!(writer, I64LOAD8S, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load8U(flags, offset) => op!(writer, I64LOAD8U, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load16S(flags, offset) => op!(writer, I64LOAD16S, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load16U(flags, offset) => op!(writer, I64LOAD16U, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load32S(flags, offset) => op!(writer, I64LOAD32S, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Load32U(flags, offset) => op!(writer, I64LOAD32U, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Store(flags, offset) => op!(writer, I32STORE, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Store(flags, offset) => op!(writer, I64STORE, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
+			I32Load(memarg) => op!(writer, I32LOAD, { memarg.serialize(writer)?; }),
+			I64Load(memarg) => op!(writer, I64LOAD, { memarg.serialize(writer)?; }),
+			F32Load(memarg) => op!(writer, F32LOAD, { memarg.serialize(writer)?; }),
+			F64Load(memarg) => op!(writer, F64LOAD, { memarg.serialize(writer)?; }),
+			I32Load8S(memarg) => op!(writer, I32LOAD8S, { memarg.serialize(writer)?; }),
+			I32Load8U(memarg) => op!(writer, I32LOAD8U, { memarg.serialize(writer)?; }),
+			I32Load16S(memarg) => op!(writer, I32LOAD16S, { memarg.serialize(writer)?; }),
+			I32Load16U(memarg) => op!(writer, I32LOAD16U, { memarg.serialize(writer)?; }),
+			I64Load8S(memarg) => op!(writer, I64LOAD8S, { memarg.serialize(writer)?; }),
+			I64Load8U(memarg) => op!(writer, I64LOAD8U, { memarg.serialize(writer)?; }),
+			I64Load16S(memarg) => op!(writer, I64LOAD16S, { memarg.serialize(writer)?; }),
+			I64Load16U(memarg) => op!(writer, I64LOAD16U, { memarg.serialize(writer)?; }),
+			I64Load32S(memarg) => op!(writer, I64LOAD32S, { memarg.serialize(writer)?; }),
+			I64Load32U(memarg) => op!(writer, I64LOAD32U, { memarg.serialize(writer)?; }),
+			I32Store(memarg) => op!(writer, I32STORE, { memarg.serialize(writer)?; }),
+			I64Store(memarg) => op!(writer, I64STORE, { memarg.serialize(writer)?; }),
+			F32Store(memarg) => op!(writer, F32STORE, { memarg.serialize(writer)?; }),
+			F64Store(memarg) => op!(writer, F64STORE, { memarg.serialize(writer)?; }),
+			I32Store8(memarg) => op!(writer, I32STORE8, { memarg.serialize(writer)?; }),
+			I32Store16(memarg) => op!(writer, I32STORE16, { memarg.serialize(writer)?; }),
+			I64Store8(memarg) => op!(writer, I64STORE8, { memarg.serialize(writer)?; }),
+			I64Store16(memarg) => op!(writer, I64STORE16, { memarg.serialize(writer)?; }),
+			I64Store32(memarg) => op!(writer, I64STORE32, { memarg.serialize(writer)?; }),
+			CurrentMemory(mem) => op!(writer, CURRENTMEMORY, {
+				VarUint32::from(mem).serialize(writer)?;
 			}),
-			F32Store(flags, offset) => op!(writer, F32STORE, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			F64Store(flags, offset) => op!(writer, F64STORE, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Store8(flags, offset) => op!(writer, I32STORE8, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I32Store16(flags, offset) => op!(writer, I32STORE16, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Store8(flags, offset) => op!(writer, I64STORE8, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Store16(flags, offset) => op!(writer, I64STORE16, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			I64Store32(flags, offset) => op!(writer, I64STORE32, {
-				VarUint32::from(flags).serialize(writer)?;
-				VarUint32::from(offset).serialize(writer)?;
-			}),
-			CurrentMemory(flag) => op!(writer, CURRENTMEMORY, {
-				Uint8::from(flag).serialize(writer)?;
-			}),
-			GrowMemory(flag) => op!(writer, GROWMEMORY, {
-				Uint8::from(flag).serialize(writer)?;
+			GrowMemory(mem) => op!(writer, GROWMEMORY, {
+				VarUint32::from(mem).serialize(writer)?;
 			}),
 			I32Const(def) => op!(writer, I32CONST, {
 				VarInt32::from(def).serialize(writer)?;
@@ -2212,19 +2395,94 @@ impl Serialize for Instruction {
 			I64x2TruncSF64x2Sat => simd!(writer, opcodes::I64X2_TRUNC_S_F64X2_SAT, ()),
 			I64x2TruncUF64x2Sat => simd!(writer, opcodes::I64X2_TRUNC_U_F64X2_SAT, ()),
 
-			MemoryInit(seg) => bulk!(writer, MEMORY_INIT, {
-				Uint8::from(0).serialize(writer)?;
+			MemoryInit(seg, mem) => bulk!(writer, MEMORY_INIT, {
 				VarUint32::from(seg).serialize(writer)?;
+				VarUint32::from(mem).serialize(writer)?;
 			}),
 			MemoryDrop(seg) => bulk!(writer, MEMORY_DROP, VarUint32::from(seg).serialize(writer)?),
-			MemoryFill => bulk!(writer, MEMORY_FILL, Uint8::from(0).serialize(writer)?),
-			MemoryCopy => bulk!(writer, MEMORY_COPY, Uint8::from(0).serialize(writer)?),
-			TableInit(seg) => bulk!(writer, TABLE_INIT, {
-				Uint8::from(0).serialize(writer)?;
+			MemoryFill(mem) => bulk!(writer, MEMORY_FILL, VarUint32::from(mem).serialize(writer)?),
+			MemoryCopy(dst, src) => bulk!(writer, MEMORY_COPY, {
+				VarUint32::from(dst).serialize(writer)?;
+				VarUint32::from(src).serialize(writer)?;
+			}),
+			TableInit(seg, table) => bulk!(writer, TABLE_INIT, {
 				VarUint32::from(seg).serialize(writer)?;
+				VarUint32::from(table).serialize(writer)?;
 			}),
 			TableDrop(seg) => bulk!(writer, TABLE_DROP, VarUint32::from(seg).serialize(writer)?),
-			TableCopy => bulk!(writer, TABLE_COPY, Uint8::from(0).serialize(writer)?),
+			TableCopy(dst, src) => bulk!(writer, TABLE_COPY, {
+				VarUint32::from(dst).serialize(writer)?;
+				VarUint32::from(src).serialize(writer)?;
+			}),
+			TableGrow(table) => bulk!(writer, TABLE_GROW, VarUint32::from(table).serialize(writer)?),
+			TableSize(table) => bulk!(writer, TABLE_SIZE, VarUint32::from(table).serialize(writer)?),
+			TableFill(table) => bulk!(writer, TABLE_FILL, VarUint32::from(table).serialize(writer)?),
+
+			StructNew(ty) => gc!(writer, STRUCT_NEW, VarUint32::from(ty).serialize(writer)?),
+			StructNewDefault(ty) => gc!(writer, STRUCT_NEW_DEFAULT, VarUint32::from(ty).serialize(writer)?),
+			StructGet(ty, field) => gc!(writer, STRUCT_GET, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(field).serialize(writer)?;
+			}),
+			StructGetS(ty, field) => gc!(writer, STRUCT_GET_S, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(field).serialize(writer)?;
+			}),
+			StructGetU(ty, field) => gc!(writer, STRUCT_GET_U, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(field).serialize(writer)?;
+			}),
+			StructSet(ty, field) => gc!(writer, STRUCT_SET, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(field).serialize(writer)?;
+			}),
+			ArrayNew(ty) => gc!(writer, ARRAY_NEW, VarUint32::from(ty).serialize(writer)?),
+			ArrayNewDefault(ty) => gc!(writer, ARRAY_NEW_DEFAULT, VarUint32::from(ty).serialize(writer)?),
+			ArrayNewFixed(ty, len) => gc!(writer, ARRAY_NEW_FIXED, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(len).serialize(writer)?;
+			}),
+			ArrayGet(ty) => gc!(writer, ARRAY_GET, VarUint32::from(ty).serialize(writer)?),
+			ArrayGetS(ty) => gc!(writer, ARRAY_GET_S, VarUint32::from(ty).serialize(writer)?),
+			ArrayGetU(ty) => gc!(writer, ARRAY_GET_U, VarUint32::from(ty).serialize(writer)?),
+			ArraySet(ty) => gc!(writer, ARRAY_SET, VarUint32::from(ty).serialize(writer)?),
+			ArrayLen => gc!(writer, ARRAY_LEN),
+			ArrayNewData(ty, data) => gc!(writer, ARRAY_NEW_DATA, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(data).serialize(writer)?;
+			}),
+			ArrayNewElem(ty, elem) => gc!(writer, ARRAY_NEW_ELEM, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(elem).serialize(writer)?;
+			}),
+			ArrayFill(ty) => gc!(writer, ARRAY_FILL, VarUint32::from(ty).serialize(writer)?),
+			ArrayCopy(dst, src) => gc!(writer, ARRAY_COPY, {
+				VarUint32::from(dst).serialize(writer)?;
+				VarUint32::from(src).serialize(writer)?;
+			}),
+			ArrayInitData(ty, data) => gc!(writer, ARRAY_INIT_DATA, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(data).serialize(writer)?;
+			}),
+			ArrayInitElem(ty, elem) => gc!(writer, ARRAY_INIT_ELEM, {
+				VarUint32::from(ty).serialize(writer)?;
+				VarUint32::from(elem).serialize(writer)?;
+			}),
+			RefTest(ty) => {
+				gc!(writer, if ty.is_nullable() { REF_TEST_NULL } else { REF_TEST });
+				ty.heap_type().serialize(writer)?;
+			},
+			RefCast(ty) => {
+				gc!(writer, if ty.is_nullable() { REF_CAST_NULL } else { REF_CAST });
+				ty.heap_type().serialize(writer)?;
+			},
+			BrOnCast(data) => gc!(writer, BR_ON_CAST, data.serialize(writer)?),
+			BrOnCastFail(data) => gc!(writer, BR_ON_CAST_FAIL, data.serialize(writer)?),
+			AnyConvertExtern => gc!(writer, ANY_CONVERT_EXTERN),
+			ExternConvertAny => gc!(writer, EXTERN_CONVERT_ANY),
+			RefI31 => gc!(writer, REF_I31),
+			I31GetS => gc!(writer, I31_GET_S),
+			I31GetU => gc!(writer, I31_GET_U),
 		}
 
 		Ok(())
@@ -2235,8 +2493,16 @@ impl Serialize for MemArg {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		Uint8::from(self.align).serialize(writer)?;
-		VarUint32::from(self.offset).serialize(writer)?;
+		if u32::from(self.align) >= MEMARG_MEMORY_FLAG {
+			return Err(Error::Other("Invalid memory access alignment"));
+		}
+		if self.memory != 0 {
+			VarUint32::from(u32::from(self.align) | MEMARG_MEMORY_FLAG).serialize(writer)?;
+			VarUint32::from(self.memory).serialize(writer)?;
+		} else {
+			VarUint32::from(u32::from(self.align)).serialize(writer)?;
+		}
+		VarUint64::from(self.offset).serialize(writer)?;
 		Ok(())
 	}
 }
@@ -2253,6 +2519,18 @@ macro_rules! fmt_op {
 	});
 }
 
+/// Write a memory access with its non-default immediates.
+fn fmt_memarg(f: &mut fmt::Formatter, mnemonic: &str, memarg: &MemArg) -> fmt::Result {
+	write!(f, "{}", mnemonic)?;
+	if memarg.memory != 0 {
+		write!(f, " {}", memarg.memory)?;
+	}
+	if memarg.offset != 0 {
+		write!(f, " offset={}", memarg.offset)?;
+	}
+	Ok(())
+}
+
 impl fmt::Display for Instruction {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		use self::Instruction::*;
@@ -2263,10 +2541,13 @@ impl fmt::Display for Instruction {
 			Nop => fmt_op!(f, "nop"),
 			Block(BlockType::NoResult) => fmt_op!(f, "block"),
 			Block(BlockType::Value(value_type)) => fmt_op!(f, "block", value_type),
+			Block(BlockType::TypeIndex(idx)) => write!(f, "block (type {})", idx),
 			Loop(BlockType::NoResult) => fmt_op!(f, "loop"),
 			Loop(BlockType::Value(value_type)) => fmt_op!(f, "loop", value_type),
+			Loop(BlockType::TypeIndex(idx)) => write!(f, "loop (type {})", idx),
 			If(BlockType::NoResult) => fmt_op!(f, "if"),
 			If(BlockType::Value(value_type)) => fmt_op!(f, "if", value_type),
+			If(BlockType::TypeIndex(idx)) => write!(f, "if (type {})", idx),
 			Else => fmt_op!(f, "else"),
 			End => fmt_op!(f, "end"),
 			Br(idx) => fmt_op!(f, "br",  idx),
@@ -2275,8 +2556,15 @@ impl fmt::Display for Instruction {
 			Return => fmt_op!(f, "return"),
 			Call(index) => fmt_op!(f, "call", index),
 			CallIndirect(index, _) =>  fmt_op!(f, "call_indirect", index),
-			RefNull => fmt_op!(f, "ref.null"),
+			CallRef(index) => fmt_op!(f, "call_ref", index),
+			ReturnCallRef(index) => fmt_op!(f, "return_call_ref", index),
+			RefNull(heap_type) => fmt_op!(f, "ref.null", heap_type),
 			RefIsNull => fmt_op!(f, "ref.isnull"),
+			RefFunc(index) => fmt_op!(f, "ref.func", index),
+			RefEq => fmt_op!(f, "ref.eq"),
+			RefAsNonNull => fmt_op!(f, "ref.as_non_null"),
+			BrOnNull(idx) => fmt_op!(f, "br_on_null", idx),
+			BrOnNonNull(idx) => fmt_op!(f, "br_on_non_null", idx),
 			Drop => fmt_op!(f, "drop"),
 			Select => fmt_op!(f, "select"),
 			GetLocal(index) => fmt_op!(f, "get_local", index),
@@ -2287,77 +2575,33 @@ impl fmt::Display for Instruction {
 			GetTable(index) => fmt_op!(f, "get_table", index),
 			SetTable(index) => fmt_op!(f, "set_table", index),
 
-			I32Load(_, 0) => write!(f, "i32.load"),
-			I32Load(_, offset) => write!(f, "i32.load offset={}", offset),
-
-			I64Load(_, 0) => write!(f, "i64.load"),
-			I64Load(_, offset) => write!(f, "i64.load offset={}", offset),
-
-			F32Load(_, 0) => write!(f, "f32.load"),
-			F32Load(_, offset) => write!(f, "f32.load offset={}", offset),
-
-			F64Load(_, 0) => write!(f, "f64.load"),
-			F64Load(_, offset) => write!(f, "f64.load offset={}", offset),
-
-			I32Load8S(_, 0) => write!(f, "i32.load8_s"),
-			I32Load8S(_, offset) => write!(f, "i32.load8_s offset={}", offset),
-
-			I32Load8U(_, 0) => write!(f, "i32.load8_u"),
-			I32Load8U(_, offset) => write!(f, "i32.load8_u offset={}", offset),
-
-			I32Load16S(_, 0) => write!(f, "i32.load16_s"),
-			I32Load16S(_, offset) => write!(f, "i32.load16_s offset={}", offset),
-
-			I32Load16U(_, 0) => write!(f, "i32.load16_u"),
-			I32Load16U(_, offset) => write!(f, "i32.load16_u offset={}", offset),
-
-			I64Load8S(_, 0) => write!(f, "i64.load8_s"),
-			I64Load8S(_, offset) => write!(f, "i64.load8_s offset={}", offset),
-
-			I64Load8U(_, 0) => write!(f, "i64.load8_u"),
-			I64Load8U(_, offset) => write!(f, "i64.load8_u offset={}", offset),
-
-			I64Load16S(_, 0) => write!(f, "i64.load16_s"),
-			I64Load16S(_, offset) => write!(f, "i64.load16_s offset={}", offset),
-
-			I64Load16U(_, 0) => write!(f, "i64.load16_u"),
-			I64Load16U(_, offset) => write!(f, "i64.load16_u offset={}", offset),
-
-			I64Load32S(_, 0) => write!(f, "i64.load32_s"),
-			I64Load32S(_, offset) => write!(f, "i64.load32_s offset={}", offset),
-
-			I64Load32U(_, 0) => write!(f, "i64.load32_u"),
-			I64Load32U(_, offset) => write!(f, "i64.load32_u offset={}", offset),
-
-			I32Store(_, 0) => write!(f, "i32.store"),
-			I32Store(_, offset) => write!(f, "i32.store offset={}", offset),
-
-			I64Store(_, 0) => write!(f, "i64.store"),
-			I64Store(_, offset) => write!(f, "i64.store offset={}", offset),
-
-			F32Store(_, 0) => write!(f, "f32.store"),
-			F32Store(_, offset) => write!(f, "f32.store offset={}", offset),
-
-			F64Store(_, 0) => write!(f, "f64.store"),
-			F64Store(_, offset) => write!(f, "f64.store offset={}", offset),
-
-			I32Store8(_, 0) => write!(f, "i32.store8"),
-			I32Store8(_, offset) => write!(f, "i32.store8 offset={}", offset),
-
-			I32Store16(_, 0) => write!(f, "i32.store16"),
-			I32Store16(_, offset) => write!(f, "i32.store16 offset={}", offset),
-
-			I64Store8(_, 0) => write!(f, "i64.store8"),
-			I64Store8(_, offset) => write!(f, "i64.store8 offset={}", offset),
-
-			I64Store16(_, 0) => write!(f, "i64.store16"),
-			I64Store16(_, offset) => write!(f, "i64.store16 offset={}", offset),
-
-			I64Store32(_, 0) => write!(f, "i64.store32"),
-			I64Store32(_, offset) => write!(f, "i64.store32 offset={}", offset),
-
-			CurrentMemory(_) => fmt_op!(f, "current_memory"),
-			GrowMemory(_) => fmt_op!(f, "grow_memory"),
+			I32Load(ref memarg) => fmt_memarg(f, "i32.load", memarg),
+			I64Load(ref memarg) => fmt_memarg(f, "i64.load", memarg),
+			F32Load(ref memarg) => fmt_memarg(f, "f32.load", memarg),
+			F64Load(ref memarg) => fmt_memarg(f, "f64.load", memarg),
+			I32Load8S(ref memarg) => fmt_memarg(f, "i32.load8_s", memarg),
+			I32Load8U(ref memarg) => fmt_memarg(f, "i32.load8_u", memarg),
+			I32Load16S(ref memarg) => fmt_memarg(f, "i32.load16_s", memarg),
+			I32Load16U(ref memarg) => fmt_memarg(f, "i32.load16_u", memarg),
+			I64Load8S(ref memarg) => fmt_memarg(f, "i64.load8_s", memarg),
+			I64Load8U(ref memarg) => fmt_memarg(f, "i64.load8_u", memarg),
+			I64Load16S(ref memarg) => fmt_memarg(f, "i64.load16_s", memarg),
+			I64Load16U(ref memarg) => fmt_memarg(f, "i64.load16_u", memarg),
+			I64Load32S(ref memarg) => fmt_memarg(f, "i64.load32_s", memarg),
+			I64Load32U(ref memarg) => fmt_memarg(f, "i64.load32_u", memarg),
+			I32Store(ref memarg) => fmt_memarg(f, "i32.store", memarg),
+			I64Store(ref memarg) => fmt_memarg(f, "i64.store", memarg),
+			F32Store(ref memarg) => fmt_memarg(f, "f32.store", memarg),
+			F64Store(ref memarg) => fmt_memarg(f, "f64.store", memarg),
+			I32Store8(ref memarg) => fmt_memarg(f, "i32.store8", memarg),
+			I32Store16(ref memarg) => fmt_memarg(f, "i32.store16", memarg),
+			I64Store8(ref memarg) => fmt_memarg(f, "i64.store8", memarg),
+			I64Store16(ref memarg) => fmt_memarg(f, "i64.store16", memarg),
+			I64Store32(ref memarg) => fmt_memarg(f, "i64.store32", memarg),
+			CurrentMemory(0) => fmt_op!(f, "current_memory"),
+			CurrentMemory(mem) => fmt_op!(f, "current_memory", mem),
+			GrowMemory(0) => fmt_op!(f, "grow_memory"),
+			GrowMemory(mem) => fmt_op!(f, "grow_memory", mem),
 
 			I32Const(def) => fmt_op!(f, "i32.const", def),
 			I64Const(def) => fmt_op!(f, "i64.const", def),
@@ -2734,13 +2978,48 @@ impl fmt::Display for Instruction {
 			I64x2TruncSF64x2Sat => write!(f, "i64x2.trunc_s/f64x2:sat"),
 			I64x2TruncUF64x2Sat => write!(f, "i64x2.trunc_u/f64x2:sat"),
 
-			MemoryInit(_) => write!(f, "memory.init"),
+			MemoryInit(..) => write!(f, "memory.init"),
 			MemoryDrop(_) => write!(f, "memory.drop"),
-			MemoryFill => write!(f, "memory.fill"),
-			MemoryCopy => write!(f, "memory.copy"),
-			TableInit(_) => write!(f, "table.init"),
+			MemoryFill(_) => write!(f, "memory.fill"),
+			MemoryCopy(..) => write!(f, "memory.copy"),
+			TableInit(..) => write!(f, "table.init"),
 			TableDrop(_) => write!(f, "table.drop"),
-			TableCopy => write!(f, "table.copy"),
+			TableCopy(..) => write!(f, "table.copy"),
+			TableGrow(table) => fmt_op!(f, "table.grow", table),
+			TableSize(table) => fmt_op!(f, "table.size", table),
+			TableFill(table) => fmt_op!(f, "table.fill", table),
+
+			StructNew(ty) => fmt_op!(f, "struct.new", ty),
+			StructNewDefault(ty) => fmt_op!(f, "struct.new_default", ty),
+			StructGet(ty, field) => fmt_op!(f, "struct.get", ty, field),
+			StructGetS(ty, field) => fmt_op!(f, "struct.get_s", ty, field),
+			StructGetU(ty, field) => fmt_op!(f, "struct.get_u", ty, field),
+			StructSet(ty, field) => fmt_op!(f, "struct.set", ty, field),
+			ArrayNew(ty) => fmt_op!(f, "array.new", ty),
+			ArrayNewDefault(ty) => fmt_op!(f, "array.new_default", ty),
+			ArrayNewFixed(ty, len) => fmt_op!(f, "array.new_fixed", ty, len),
+			ArrayGet(ty) => fmt_op!(f, "array.get", ty),
+			ArrayGetS(ty) => fmt_op!(f, "array.get_s", ty),
+			ArrayGetU(ty) => fmt_op!(f, "array.get_u", ty),
+			ArraySet(ty) => fmt_op!(f, "array.set", ty),
+			ArrayLen => fmt_op!(f, "array.len"),
+			ArrayNewData(ty, data) => fmt_op!(f, "array.new_data", ty, data),
+			ArrayNewElem(ty, elem) => fmt_op!(f, "array.new_elem", ty, elem),
+			ArrayFill(ty) => fmt_op!(f, "array.fill", ty),
+			ArrayCopy(dst, src) => fmt_op!(f, "array.copy", dst, src),
+			ArrayInitData(ty, data) => fmt_op!(f, "array.init_data", ty, data),
+			ArrayInitElem(ty, elem) => fmt_op!(f, "array.init_elem", ty, elem),
+			RefTest(ty) => fmt_op!(f, "ref.test", ty),
+			RefCast(ty) => fmt_op!(f, "ref.cast", ty),
+			BrOnCast(ref data) => write!(f, "br_on_cast {} {} {}", data.label, data.from, data.to),
+			BrOnCastFail(ref data) => {
+				write!(f, "br_on_cast_fail {} {} {}", data.label, data.from, data.to)
+			},
+			AnyConvertExtern => fmt_op!(f, "any.convert_extern"),
+			ExternConvertAny => fmt_op!(f, "extern.convert_any"),
+			RefI31 => fmt_op!(f, "ref.i31"),
+			I31GetS => fmt_op!(f, "i31.get_s"),
+			I31GetU => fmt_op!(f, "i31.get_u"),
 		}
 	}
 }
@@ -2794,11 +3073,139 @@ fn display() {
 	let instruction = Instruction::GetLocal(0);
 	assert_eq!("get_local 0", format!("{}", instruction));
 
-	let instruction = Instruction::F64Store(0, 24);
+	let instruction = Instruction::F64Store(MemArg::new(0, 24));
 	assert_eq!("f64.store offset=24", format!("{}", instruction));
 
-	let instruction = Instruction::I64Store(0, 0);
+	let instruction = Instruction::I64Store(MemArg::new(0, 0));
 	assert_eq!("i64.store", format!("{}", instruction));
+
+	let instruction = Instruction::StructGet(3, 1);
+	assert_eq!("struct.get 3 1", format!("{}", instruction));
+}
+
+#[test]
+fn gc_struct_array() {
+	use self::Instruction::*;
+
+	let code = vec![
+		StructNew(0), StructNewDefault(1), StructGet(0, 1), StructGetS(0, 2),
+		StructGetU(0, 3), StructSet(0, 130), ArrayNew(2), ArrayNewDefault(2),
+		ArrayNewFixed(2, 4), ArrayGet(2), ArrayGetS(3), ArrayGetU(3), ArraySet(2),
+		ArrayLen, ArrayNewData(2, 0), ArrayNewElem(5, 1), ArrayFill(2), ArrayCopy(2, 3),
+		ArrayInitData(2, 1), ArrayInitElem(5, 0), End,
+	];
+	let buf = super::serialize(Instructions::new(code.clone())).expect("gc instructions to serialize");
+	assert_eq!(&buf[..3], &[0xfb, 0x00, 0x00]);
+
+	let decoded = super::deserialize_buffer::<Instructions>(&buf).expect("gc instructions to deserialize");
+	assert_eq!(decoded.elements(), &code[..]);
+}
+
+#[test]
+fn typed_function_references() {
+	use self::Instruction::*;
+	use super::HeapType;
+
+	let code = vec![
+		RefNull(HeapType::Func), RefNull(HeapType::Index(3)), RefFunc(7), RefAsNonNull,
+		BrOnNull(0), BrOnNonNull(1), CallRef(3), ReturnCallRef(3), RefEq, RefIsNull, End,
+	];
+	let buf = super::serialize(Instructions::new(code.clone())).expect("instructions to serialize");
+	assert_eq!(&buf[..4], &[0xd0, 0x70, 0xd0, 0x03]);
+
+	let decoded = super::deserialize_buffer::<Instructions>(&buf).expect("instructions to deserialize");
+	assert_eq!(decoded.elements(), &code[..]);
+
+	assert_eq!(format!("{}", RefNull(HeapType::Extern)), "ref.null extern");
+	assert_eq!(format!("{}", RefNull(HeapType::Index(2))), "ref.null 2");
+	assert_eq!(format!("{}", BrOnNonNull(1)), "br_on_non_null 1");
+}
+
+#[test]
+fn multi_memory() {
+	use self::Instruction::*;
+
+	let memarg = MemArg { align: 2, offset: 16, memory: 1 };
+	let code = vec![
+		I32Load(memarg), I32Store(MemArg::new(2, 0)), I32AtomicLoad(memarg), CurrentMemory(1), GrowMemory(0),
+		MemoryInit(3, 1), MemoryCopy(1, 0), MemoryFill(2), End,
+	];
+	let buf = super::serialize(Instructions::new(code.clone())).expect("instructions to serialize");
+	assert_eq!(&buf[..7], &[0x28, 0x42, 0x01, 0x10, 0x36, 0x02, 0x00]);
+	assert_eq!(
+		&buf[buf.len() - 16..],
+		&[0x3f, 0x01, 0x40, 0x00, 0xfc, 0x08, 0x03, 0x01, 0xfc, 0x0a, 0x01, 0x00, 0xfc, 0x0b, 0x02, 0x0b],
+	);
+
+	let decoded = super::deserialize_buffer::<Instructions>(&buf).expect("instructions to deserialize");
+	assert_eq!(decoded.elements(), &code[..]);
+	assert_eq!(decoded.elements()[0].memarg(), Some(&memarg));
+	assert_eq!(decoded.elements()[3].memarg(), None);
+
+	assert_eq!(format!("{}", I32Load(memarg)), "i32.load 1 offset=16");
+	assert_eq!(format!("{}", CurrentMemory(1)), "current_memory 1");
+
+	// Alignment that overlaps the memory index flag.
+	assert!(super::serialize(I32Load(MemArg::new(0x40, 0))).is_err());
+	assert!(super::deserialize_buffer::<Instruction>(&[0x28, 0x80, 0x01, 0x00]).is_err());
+}
+
+#[test]
+fn memory64_offset() {
+	let load = Instruction::I64Load(MemArg::new(3, 1 << 40));
+	let buf = super::serialize(load.clone()).expect("instruction to serialize");
+	assert_eq!(buf, vec![0x29, 0x03, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20]);
+	assert_eq!(super::deserialize_buffer::<Instruction>(&buf).expect("instruction to deserialize"), load);
+	assert_eq!(format!("{}", load), "i64.load offset=1099511627776");
+}
+
+#[test]
+fn multi_table() {
+	use self::Instruction::*;
+
+	let code = vec![
+		CallIndirect(2, 1), TableInit(3, 1), TableCopy(1, 0), TableGrow(1), TableSize(2), TableFill(0), End,
+	];
+	let buf = super::serialize(Instructions::new(code.clone())).expect("instructions to serialize");
+	assert_eq!(
+		buf,
+		vec![
+			0x11, 0x02, 0x01, 0xfc, 0x0c, 0x03, 0x01, 0xfc, 0x0e, 0x01, 0x00, 0xfc, 0x0f, 0x01,
+			0xfc, 0x10, 0x02, 0xfc, 0x11, 0x00, 0x0b,
+		],
+	);
+
+	let decoded = super::deserialize_buffer::<Instructions>(&buf).expect("instructions to deserialize");
+	assert_eq!(decoded.elements(), &code[..]);
+	assert_eq!(format!("{}", TableSize(2)), "table.size 2");
+}
+
+#[test]
+fn gc_casts() {
+	use self::Instruction::*;
+	use super::HeapType;
+
+	let cast = BrOnCastData {
+		label: 1,
+		from: RefType::ANYREF,
+		to: RefType::new(false, HeapType::Index(4)),
+	};
+	let code = vec![
+		RefTest(RefType::new(false, HeapType::I31)), RefTest(RefType::STRUCTREF),
+		RefCast(RefType::new(false, HeapType::Index(4))), RefCast(RefType::new(true, HeapType::Index(4))),
+		BrOnCast(Box::new(cast.clone())), BrOnCastFail(Box::new(cast.clone())),
+		AnyConvertExtern, ExternConvertAny, RefI31, I31GetS, I31GetU, End,
+	];
+	let buf = super::serialize(Instructions::new(code.clone())).expect("gc instructions to serialize");
+	assert_eq!(&buf[..3], &[0xfb, 0x14, 0x6c]);
+
+	let decoded = super::deserialize_buffer::<Instructions>(&buf).expect("gc instructions to deserialize");
+	assert_eq!(decoded.elements(), &code[..]);
+
+	let br_on_cast = super::serialize(BrOnCast(Box::new(cast.clone()))).expect("br_on_cast to serialize");
+	assert_eq!(br_on_cast, vec![0xfb, 0x18, 0x01, 0x01, 0x6e, 0x04]);
+	assert_eq!(format!("{}", BrOnCast(Box::new(cast))), "br_on_cast 1 anyref (ref 4)");
+	assert_eq!(format!("{}", RefCast(RefType::new(true, HeapType::Index(4)))), "ref.cast (ref null 4)");
 }
 
 #[test]
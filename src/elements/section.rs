@@ -1,12 +1,12 @@
 use crate::rust::{vec::Vec, string::String, borrow::ToOwned};
-use crate::{io, elements};
+use crate::{io::{self, Read}, elements};
 use super::{
 	Serialize,
 	Deserialize,
 	Error,
 	VarUint7,
+	VarInt7,
 	VarUint32,
-	CountedList,
 	ImportEntry,
 	MemoryType,
 	TableType,
@@ -17,12 +17,14 @@ use super::{
 	ElementSegment,
 	DataSegment,
 	CountedWriter,
+	Context,
 	CountedListWriter,
 	External,
 	serialize,
 };
 
-use super::types::Type;
+use super::types::{Type, SubType, RecGroup, Dialect, RECTYPE};
+use super::index_map::IndexMap;
 use super::name_section::NameSection;
 use super::reloc_section::RelocSection;
 
@@ -80,14 +82,38 @@ impl Deserialize for Section {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		Section::deserialize_section(reader, BodyDecoding::Eager)
+	}
+}
+
+/// How function bodies of the code section are decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BodyDecoding {
+	/// Decode bodies right away.
+	Eager,
+	/// Keep bodies encoded until they are accessed.
+	Lazy,
+	/// Decode bodies right away, recording byte offsets of their instructions.
+	Offsets,
+}
+
+impl Section {
+	/// Deserialize a section, decoding function bodies as given by `bodies`.
+	pub(crate) fn deserialize_section<R: io::Read>(reader: &mut R, bodies: BodyDecoding) -> Result<Self, Error> {
+		let start = reader.position();
 		let id = match VarUint7::deserialize(reader) {
 			// todo: be more selective detecting no more section
 			Err(_) => { return Err(Error::UnexpectedEof); },
 			Ok(id) => id,
 		};
 
+		Section::deserialize_payload(id.into(), reader, bodies)
+			.map_err(|e| e.located(Context::Section(id.into()), start))
+	}
+
+	fn deserialize_payload<R: io::Read>(id: u8, reader: &mut R, bodies: BodyDecoding) -> Result<Self, Error> {
 		Ok(
-			match id.into() {
+			match id {
 				0 => {
 					Section::Custom(CustomSection::deserialize(reader)?.into())
 				},
@@ -122,7 +148,12 @@ impl Deserialize for Section {
 					Section::Element(ElementSection::deserialize(reader)?)
 				},
 				10 => {
-					Section::Code(CodeSection::deserialize(reader)?)
+					let read = match bodies {
+						BodyDecoding::Eager => FuncBody::deserialize,
+						BodyDecoding::Lazy => FuncBody::deserialize_lazy,
+						BodyDecoding::Offsets => FuncBody::deserialize_with_offsets,
+					};
+					Section::Code(CodeSection(read_located_entries(reader, Context::FuncBody, read)?))
 				},
 				11 => {
 					Section::Data(DataSection::deserialize(reader)?)
@@ -224,6 +255,26 @@ impl Serialize for Section {
 }
 
 impl Section {
+	/// Id of the section in the binary format.
+	pub(crate) fn id(&self) -> u8 {
+		match *self {
+			Section::Custom(_) | Section::Name(_) | Section::Reloc(_) => 0x00,
+			Section::Unparsed { id, .. } => id,
+			Section::Type(_) => 0x1,
+			Section::Import(_) => 0x2,
+			Section::Function(_) => 0x3,
+			Section::Table(_) => 0x4,
+			Section::Memory(_) => 0x5,
+			Section::Global(_) => 0x6,
+			Section::Export(_) => 0x7,
+			Section::Start(_) => 0x8,
+			Section::Element(_) => 0x9,
+			Section::Code(_) => 0x0a,
+			Section::Data(_) => 0x0b,
+			Section::DataCount(_) => 0x0c,
+		}
+	}
+
 	pub(crate) fn order(&self) -> u8 {
 		match *self {
 			Section::Custom(_) => 0x00,
@@ -249,11 +300,14 @@ impl Section {
 pub(crate) struct SectionReader {
 	cursor: io::Cursor<Vec<u8>>,
 	declared_length: usize,
+	/// Position of the payload in the underlying reader.
+	start: Option<usize>,
 }
 
 impl SectionReader {
 	pub fn new<R: io::Read>(reader: &mut R) -> Result<Self, elements::Error> {
 		let length = u32::from(VarUint32::deserialize(reader)?) as usize;
+		let start = reader.position();
 		let inner_buffer = buffered_read!(ENTRIES_BUFFER_LENGTH, length, reader);
 		let buf_length = inner_buffer.len();
 		let cursor = io::Cursor::new(inner_buffer);
@@ -261,15 +315,16 @@ impl SectionReader {
 		Ok(SectionReader {
 			cursor: cursor,
 			declared_length: buf_length,
+			start,
 		})
 	}
 
-	pub fn close(self) -> Result<(), io::Error> {
+	pub fn close(self) -> Result<(), elements::Error> {
 		let cursor = self.cursor;
 		let buf_length = self.declared_length;
 
 		if cursor.position() != buf_length {
-			Err(io::Error::InvalidData)
+			Err(elements::Error::InconsistentLength { expected: buf_length, actual: cursor.position() })
 		} else {
 			Ok(())
 		}
@@ -281,13 +336,33 @@ impl io::Read for SectionReader {
 		self.cursor.read(buf)?;
 		Ok(())
 	}
+
+	fn position(&self) -> Option<usize> {
+		self.start.map(|start| start + self.cursor.position())
+	}
 }
 
 fn read_entries<R: io::Read, T: Deserialize<Error=elements::Error>>(reader: &mut R)
 	-> Result<Vec<T>, elements::Error>
+{
+	read_located_entries(reader, Context::Entry, T::deserialize)
+}
+
+/// Read the entries of a section with `read`, locating errors with `context` of the entry index.
+fn read_located_entries<R, T>(
+	reader: &mut R,
+	context: fn(u32) -> Context,
+	read: fn(&mut SectionReader) -> Result<T, elements::Error>,
+) -> Result<Vec<T>, elements::Error>
+	where R: io::Read
 {
 	let mut section_reader = SectionReader::new(reader)?;
-	let result = CountedList::<T>::deserialize(&mut section_reader)?.into_inner();
+	let count: u32 = VarUint32::deserialize(&mut section_reader)?.into();
+	let mut result = Vec::new();
+	for idx in 0..count {
+		let start = section_reader.position();
+		result.push(read(&mut section_reader).map_err(|e| e.located(context(idx), start))?);
+	}
 	section_reader.close()?;
 	Ok(result)
 }
@@ -354,23 +429,150 @@ impl Serialize for CustomSection {
 }
 
 /// Section with type declarations.
+///
+/// Types are stored flat in the order of their indices; recursion groups and
+/// subtype declarations are recorded alongside them.
 #[derive(Debug, Default, Clone, PartialEq)]
-pub struct TypeSection(Vec<Type>);
+pub struct TypeSection {
+	types: Vec<Type>,
+	subtypes: IndexMap<SubType>,
+	rec_groups: Vec<RecGroup>,
+}
 
 impl TypeSection {
 	///  New type section with provided types.
 	pub fn with_types(types: Vec<Type>) -> Self {
-		TypeSection(types)
+		TypeSection { types, ..Default::default() }
 	}
 
-	/// List of type declarations.
+	/// List of type declarations, indexed by type index.
 	pub fn types(&self) -> &[Type] {
-		&self.0
+		&self.types
 	}
 
 	/// List of type declarations (mutable).
+	///
+	/// Note that recursion groups and subtype declarations refer to types by index
+	/// and are not updated when types are inserted or removed.
 	pub fn types_mut(&mut self) -> &mut Vec<Type> {
-		&mut self.0
+		&mut self.types
+	}
+
+	/// Subtype declaration of the type with the given index, if any.
+	pub fn subtype(&self, index: u32) -> Option<&SubType> {
+		self.subtypes.get(index)
+	}
+
+	/// Declare the type with the given index as a subtype, returning the previous declaration.
+	pub fn set_subtype(&mut self, index: u32, subtype: SubType) -> Option<SubType> {
+		self.subtypes.insert(index, subtype)
+	}
+
+	/// Remove the subtype declaration of the type with the given index.
+	pub fn remove_subtype(&mut self, index: u32) -> Option<SubType> {
+		self.subtypes.remove(index)
+	}
+
+	/// Explicitly declared recursion groups, ordered by their first type index.
+	pub fn rec_groups(&self) -> &[RecGroup] {
+		&self.rec_groups
+	}
+
+	/// Recursion group of the type with the given index.
+	///
+	/// Types outside of any explicit `rec` form a group of their own.
+	pub fn rec_group(&self, index: u32) -> RecGroup {
+		self.rec_groups.iter()
+			.find(|group| group.contains(index))
+			.cloned()
+			.unwrap_or_else(|| RecGroup::new(index, 1))
+	}
+
+	/// Append types as a single recursion group.
+	pub fn push_rec_group(&mut self, types: Vec<Type>) -> RecGroup {
+		let group = RecGroup::new(self.types.len() as u32, types.len() as u32);
+		self.types.extend(types);
+		self.rec_groups.push(group);
+		group
+	}
+}
+
+impl TypeSection {
+	/// Read a type section with the type definitions encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		let mut section_reader = SectionReader::new(reader)?;
+		let mut section = TypeSection::default();
+
+		let count: u32 = VarUint32::deserialize(&mut section_reader)?.into();
+		for _ in 0..count {
+			let form: i8 = VarInt7::deserialize(&mut section_reader)?.into();
+			if form == RECTYPE {
+				let len: u32 = VarUint32::deserialize(&mut section_reader)?.into();
+				let start = section.types.len() as u32;
+				for _ in 0..len {
+					let form: i8 = VarInt7::deserialize(&mut section_reader)?.into();
+					section.read_subtype(form, &mut section_reader, dialect)?;
+				}
+				section.rec_groups.push(RecGroup::new(start, len));
+			} else {
+				section.read_subtype(form, &mut section_reader, dialect)?;
+			}
+		}
+
+		section_reader.close()?;
+		Ok(section)
+	}
+
+	/// Write a type section with the type definitions encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		let mut counted_writer = CountedWriter::new(writer);
+		let TypeSection { types, mut subtypes, rec_groups } = self;
+
+		let total = types.len() as u32;
+		let mut next = 0u32;
+		let mut count = 0usize;
+		for group in &rec_groups {
+			if group.start() < next || group.end() > total {
+				return Err(Error::Other("Recursion group does not match the type declarations"));
+			}
+			count += (group.start() - next) as usize + 1;
+			next = group.end();
+		}
+		count += (total - next) as usize;
+		VarUint32::from(count).serialize(&mut counted_writer)?;
+
+		let mut groups = rec_groups.into_iter().peekable();
+		let mut types = types.into_iter().enumerate();
+		let mut next = 0u32;
+		loop {
+			if let Some(group) = groups.next_if(|group| group.start() == next) {
+				VarInt7::from(RECTYPE).serialize(&mut counted_writer)?;
+				VarUint32::from(group.len()).serialize(&mut counted_writer)?;
+				for (index, ty) in types.by_ref().take(group.len() as usize) {
+					let subtype = subtypes.remove(index as u32);
+					SubType::serialize_type(subtype, ty, &mut counted_writer, dialect)?;
+				}
+				next = group.end();
+			} else if let Some((index, ty)) = types.next() {
+				let subtype = subtypes.remove(index as u32);
+				SubType::serialize_type(subtype, ty, &mut counted_writer, dialect)?;
+				next += 1;
+			} else {
+				break;
+			}
+		}
+
+		counted_writer.done()?;
+		Ok(())
+	}
+
+	fn read_subtype<R: io::Read>(&mut self, form: i8, reader: &mut R, dialect: Dialect) -> Result<(), Error> {
+		let (subtype, ty) = SubType::deserialize_form(form, reader, dialect)?;
+		if let Some(subtype) = subtype {
+			self.subtypes.insert(self.types.len() as u32, subtype);
+		}
+		self.types.push(ty);
+		Ok(())
 	}
 }
 
@@ -378,7 +580,7 @@ impl Deserialize for TypeSection {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		Ok(TypeSection(read_entries(reader)?))
+		TypeSection::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -386,15 +588,7 @@ impl Serialize for TypeSection {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		let mut counted_writer = CountedWriter::new(writer);
-		let data = self.0;
-		let counted_list = CountedListWriter::<Type, _>(
-			data.len(),
-			data.into_iter().map(Into::into),
-		);
-		counted_list.serialize(&mut counted_writer)?;
-		counted_writer.done()?;
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
@@ -707,7 +901,7 @@ impl Deserialize for CodeSection {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		Ok(CodeSection(read_entries(reader)?))
+		Ok(CodeSection(read_located_entries(reader, Context::FuncBody, FuncBody::deserialize)?))
 	}
 }
 
@@ -951,10 +1145,50 @@ mod tests {
 			_ => panic!("type is not a function"),
 		};
 
-		assert_eq!(Some(NumType::I64.into()), t1.return_type());
+		assert_eq!(t1.results(), &[NumType::I64.into()]);
 		assert_eq!(2, t1.params().len());
 	}
 
+	fn rec_types_test_payload() -> &'static [u8] {
+		&[
+			// section length
+			15,
+			// 2 entries
+			2,
+			// rec group of 2 types
+			0x4e, 0x02,
+				// sub, no supertypes, empty struct
+				0x50, 0x00, 0x5f, 0x00,
+				// sub final, supertype 0, empty struct
+				0x4f, 0x01, 0x00, 0x5f, 0x00,
+			// func, no params, no results
+			0x60, 0x00, 0x00,
+		]
+	}
+
+	#[test]
+	fn type_section_rec_groups() {
+		use super::super::{RecGroup, SubType};
+
+		let type_section: TypeSection =
+			deserialize_buffer(rec_types_test_payload()).expect("type_section be deserialized");
+
+		assert_eq!(type_section.types().len(), 3);
+		match type_section.types()[2] {
+			Type::Function(_) => {},
+			_ => panic!("type 2 is not a function"),
+		}
+		assert_eq!(type_section.rec_groups(), &[RecGroup::new(0, 2)]);
+		assert_eq!(type_section.rec_group(1), RecGroup::new(0, 2));
+		assert_eq!(type_section.rec_group(2), RecGroup::new(2, 1));
+		assert_eq!(type_section.subtype(0), Some(&SubType::new(false, vec![])));
+		assert_eq!(type_section.subtype(1), Some(&SubType::new(true, vec![0])));
+		assert_eq!(type_section.subtype(2), None);
+
+		let buf = serialize(type_section).expect("type section to be serialized");
+		assert_eq!(&buf[..], rec_types_test_payload());
+	}
+
 	fn export_payload() -> &'static [u8] {
 		&[
 			// section id
@@ -1105,6 +1339,28 @@ mod tests {
 		]);
 	}
 
+	#[test]
+	fn element_section_table_index() {
+		let element_section = ElementSection::with_entries(
+			vec![ElementSegment::new(1u32, Some(InitExpr::empty()), vec![2u32], false)]
+		);
+
+		let buf = serialize(element_section.clone()).expect("Element section to be serialized");
+
+		assert_eq!(buf, vec![
+			0x07, // 7 bytes overall
+			0x01, // number of segments
+			0x02, // flags: explicit table index
+			0x01, // table index
+			0x0b, // just `end` op
+			0x00, // element kind: funcref
+			0x01, // 1 element
+			0x02,
+		]);
+		let decoded: ElementSection = deserialize_buffer(&buf).expect("Element section to be deserialized");
+		assert_eq!(decoded, element_section);
+	}
+
 	#[test]
 	fn code_section_ser() {
 		use super::super::Instruction::*;
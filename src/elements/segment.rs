@@ -1,50 +1,171 @@
 use crate::rust::vec::Vec;
 use crate::io;
-use super::{Deserialize, Serialize, Error, VarUint32, CountedList, InitExpr, CountedListWriter};
+use super::{
+	Deserialize, Serialize, Error, VarUint32, Uint8, CountedList, InitExpr, CountedListWriter, RefType,
+};
 
-const FLAG_MEMZERO: u32 = 0;
-const FLAG_PASSIVE: u32 = 1;
-const FLAG_MEM_NONZERO: u32 = 2;
+pub(crate) const FLAG_MEMZERO: u32 = 0;
+pub(crate) const FLAG_PASSIVE: u32 = 1;
+pub(crate) const FLAG_MEM_NONZERO: u32 = 2;
+
+/// Element segment flag of declarative segments, set together with `FLAG_PASSIVE`.
+const FLAG_DECLARATIVE: u32 = 2;
+/// Element segment flag of segments with expression items.
+const FLAG_EXPRESSIONS: u32 = 4;
+
+/// Element kind of function references.
+const ELEM_KIND_FUNCREF: u8 = 0x00;
+
+/// Mode of an element segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementMode {
+	/// Copied into a table at the segment offset on instantiation.
+	Active,
+	/// Copied into a table with `table.init`.
+	Passive,
+	/// Not available at runtime, only declares functions for `ref.func`.
+	Declarative,
+}
+
+/// Items of an element segment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementItems {
+	/// Function indices.
+	Functions(Vec<u32>),
+	/// Constant expressions that compute references, such as `ref.func` or `ref.null`.
+	Expressions(Vec<InitExpr>),
+}
 
 /// Entry in the element section.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ElementSegment {
 	index: u32,
 	offset: Option<InitExpr>,
-	members: Vec<u32>,
+	elem_type: RefType,
+	items: ElementItems,
 	passive: bool,
+	declarative: bool,
+	explicit_index: bool,
 }
 
 impl ElementSegment {
-	/// New element segment.
+	/// New element segment of function indices.
 	pub fn new(index: u32, offset: Option<InitExpr>, members: Vec<u32>, passive: bool) -> Self {
-		ElementSegment { index: index, offset: offset, members: members, passive: passive }
+		let mode = if passive { ElementMode::Passive } else { ElementMode::Active };
+		ElementSegment::with_items(mode, index, offset, RefType::FUNCREF, ElementItems::Functions(members))
+	}
+
+	/// New element segment with the given mode, element type and items.
+	///
+	/// `index` and `offset` are only used by active segments.
+	pub fn with_items(
+		mode: ElementMode,
+		index: u32,
+		offset: Option<InitExpr>,
+		elem_type: RefType,
+		items: ElementItems,
+	) -> Self {
+		let mut segment = ElementSegment {
+			index,
+			offset,
+			elem_type,
+			items,
+			passive: false,
+			declarative: false,
+			explicit_index: false,
+		};
+		segment.set_mode(mode);
+		segment
 	}
 
-	/// Sequence of function indices.
-	pub fn members(&self) -> &[u32] { &self.members }
+	/// Sequence of function indices, empty if the items are expressions.
+	#[deprecated(note = "use `items`, segments may contain expressions")]
+	pub fn members(&self) -> &[u32] {
+		match self.items {
+			ElementItems::Functions(ref funcs) => funcs,
+			ElementItems::Expressions(_) => &[],
+		}
+	}
 
 	/// Sequence of function indices (mutable)
-	pub fn members_mut(&mut self) -> &mut Vec<u32> { &mut self.members }
+	///
+	/// Panics if the items are expressions.
+	#[deprecated(note = "use `items_mut`, segments may contain expressions")]
+	pub fn members_mut(&mut self) -> &mut Vec<u32> {
+		match self.items {
+			ElementItems::Functions(ref mut funcs) => funcs,
+			ElementItems::Expressions(_) => panic!("element segment items are expressions"),
+		}
+	}
+
+	/// Items of the segment.
+	pub fn items(&self) -> &ElementItems { &self.items }
 
-	/// Table index (currently valid only value of `0`)
+	/// Items of the segment (mutable).
+	pub fn items_mut(&mut self) -> &mut ElementItems { &mut self.items }
+
+	/// Type of the elements.
+	pub fn elem_type(&self) -> RefType { self.elem_type }
+
+	/// Type of the elements (mutable).
+	pub fn elem_type_mut(&mut self) -> &mut RefType { &mut self.elem_type }
+
+	/// Index of the table the segment is copied into.
 	pub fn index(&self) -> u32 { self.index }
 
+	/// Index of the table the segment is copied into (mutable).
+	pub fn index_mut(&mut self) -> &mut u32 { &mut self.index }
+
 	/// An i32 initializer expression that computes the offset at which to place the elements.
 	///
-	/// Note that this return `None` if the segment is `passive`.
+	/// Note that this return `None` if the segment is not active.
 	pub fn offset(&self) -> &Option<InitExpr> { &self.offset }
 
 	/// An i32 initializer expression that computes the offset at which to place the elements (mutable)
 	///
-	/// Note that this return `None` if the segment is `passive`.
+	/// Note that this return `None` if the segment is not active.
 	pub fn offset_mut(&mut self) -> &mut Option<InitExpr> { &mut self.offset }
 
-	/// Whether or not this table element is "passive"
-	pub fn passive(&self) -> bool { self.passive }
+	/// Mode of the segment.
+	pub fn mode(&self) -> ElementMode {
+		match (self.passive, self.declarative) {
+			(false, _) => ElementMode::Active,
+			(true, false) => ElementMode::Passive,
+			(true, true) => ElementMode::Declarative,
+		}
+	}
+
+	/// Set the mode of the segment.
+	pub fn set_mode(&mut self, mode: ElementMode) {
+		self.passive = mode != ElementMode::Active;
+		self.declarative = mode == ElementMode::Declarative;
+	}
 
 	/// Whether or not this table element is "passive"
+	pub fn passive(&self) -> bool { self.mode() == ElementMode::Passive }
+
+	/// Whether or not this table element is "passive" (mutable)
+	///
+	/// Declarative segments are passive segments with a declarative flag, which is kept.
+	#[deprecated(note = "use `set_mode`")]
 	pub fn passive_mut(&mut self) -> &mut bool { &mut self.passive }
+
+	/// Flags of the encoding, keeping an explicit table index of table `0` if the segment was decoded with one.
+	fn flags(&self) -> u32 {
+		let expressions = match self.items {
+			ElementItems::Functions(_) => 0,
+			ElementItems::Expressions(_) => FLAG_EXPRESSIONS,
+		};
+		let mode = match self.mode() {
+			ElementMode::Passive => FLAG_PASSIVE,
+			ElementMode::Declarative => FLAG_PASSIVE | FLAG_DECLARATIVE,
+			ElementMode::Active if self.explicit_index || self.index != 0 => FLAG_MEM_NONZERO,
+			// Only function references have an implicit element type.
+			ElementMode::Active if expressions != 0 && self.elem_type != RefType::FUNCREF => FLAG_MEM_NONZERO,
+			ElementMode::Active => FLAG_MEMZERO,
+		};
+		mode | expressions
+	}
 }
 
 impl Deserialize for ElementSegment {
@@ -52,30 +173,50 @@ impl Deserialize for ElementSegment {
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
 		let flags: u32 = VarUint32::deserialize(reader)?.into();
-		let index = if flags == FLAG_MEMZERO || flags == FLAG_PASSIVE {
-			0u32
-		} else if flags == FLAG_MEM_NONZERO {
-			VarUint32::deserialize(reader)?.into()
-		} else {
-			return Err(Error::InvalidSegmentFlags(flags))
+		if flags > (FLAG_PASSIVE | FLAG_DECLARATIVE | FLAG_EXPRESSIONS) {
+			return Err(Error::InvalidSegmentFlags(flags));
+		}
+		let mode = match flags & (FLAG_PASSIVE | FLAG_DECLARATIVE) {
+			FLAG_PASSIVE => ElementMode::Passive,
+			FLAG_MEMZERO | FLAG_MEM_NONZERO => ElementMode::Active,
+			_ => ElementMode::Declarative,
 		};
-		let offset = if flags == FLAG_PASSIVE {
+		let explicit_index = flags & (FLAG_PASSIVE | FLAG_MEM_NONZERO) == FLAG_MEM_NONZERO;
+		let index = if explicit_index { VarUint32::deserialize(reader)?.into() } else { 0 };
+		let offset = if mode == ElementMode::Active {
+			Some(InitExpr::deserialize(reader)?)
+		} else {
 			None
+		};
+
+		let expressions = flags & FLAG_EXPRESSIONS != 0;
+		let implicit_type = mode == ElementMode::Active && !explicit_index;
+		let elem_type = if implicit_type {
+			RefType::FUNCREF
+		} else if expressions {
+			RefType::deserialize(reader)?
+		} else if u8::from(Uint8::deserialize(reader)?) == ELEM_KIND_FUNCREF {
+			RefType::FUNCREF
 		} else {
-			Some(InitExpr::deserialize(reader)?)
+			return Err(Error::Other("Unsupported element kind"));
 		};
-		let funcs: Vec<u32> = CountedList::<VarUint32>::deserialize(reader)?
-			.into_inner()
-			.into_iter()
-			.map(Into::into)
-			.collect();
 
-		Ok(ElementSegment {
-			index: index,
-			offset: offset,
-			members: funcs,
-			passive: flags == FLAG_PASSIVE,
-		})
+		let items = if expressions {
+			ElementItems::Expressions(CountedList::<InitExpr>::deserialize(reader)?.into_inner())
+		} else {
+			ElementItems::Functions(
+				CountedList::<VarUint32>::deserialize(reader)?
+					.into_inner()
+					.into_iter()
+					.map(Into::into)
+					.collect(),
+			)
+		};
+
+		let mut segment = ElementSegment::with_items(mode, index, offset, elem_type, items);
+		// Only keep the explicit table index if it's not implied by the rest of the segment.
+		segment.explicit_index = segment.flags() != flags;
+		Ok(segment)
 	}
 }
 
@@ -83,23 +224,39 @@ impl Serialize for ElementSegment {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		if self.passive {
-			VarUint32::from(FLAG_PASSIVE).serialize(writer)?;
-		} else if self.index != 0 {
-			VarUint32::from(FLAG_MEM_NONZERO).serialize(writer)?;
+		// Function indices only have the funcref element kind.
+		if matches!(self.items, ElementItems::Functions(_)) && self.elem_type != RefType::FUNCREF {
+			return Err(Error::Other("Function indices in element segment of non-funcref type"));
+		}
+		let flags = self.flags();
+		VarUint32::from(flags).serialize(writer)?;
+		if flags & (FLAG_PASSIVE | FLAG_MEM_NONZERO) == FLAG_MEM_NONZERO {
 			VarUint32::from(self.index).serialize(writer)?;
-		} else {
-			VarUint32::from(FLAG_MEMZERO).serialize(writer)?;
 		}
-		if let Some(offset) = self.offset {
-			offset.serialize(writer)?;
+		if self.mode() == ElementMode::Active {
+			self.offset.ok_or(Error::Other("Active element segment without offset"))?.serialize(writer)?;
+		}
+
+		let implicit_type = flags & (FLAG_PASSIVE | FLAG_MEM_NONZERO) == FLAG_MEMZERO;
+		match self.items {
+			ElementItems::Functions(funcs) => {
+				if !implicit_type {
+					Uint8::from(ELEM_KIND_FUNCREF).serialize(writer)?;
+				}
+				let counted_list = CountedListWriter::<VarUint32, _>(
+					funcs.len(),
+					funcs.into_iter().map(Into::into),
+				);
+				counted_list.serialize(writer)?;
+			},
+			ElementItems::Expressions(exprs) => {
+				if !implicit_type {
+					self.elem_type.serialize(writer)?;
+				}
+				let counted_list = CountedListWriter::<InitExpr, _>(exprs.len(), exprs.into_iter());
+				counted_list.serialize(writer)?;
+			},
 		}
-		let data = self.members;
-		let counted_list = CountedListWriter::<VarUint32, _>(
-			data.len(),
-			data.into_iter().map(Into::into),
-		);
-		counted_list.serialize(writer)?;
 		Ok(())
 	}
 }
@@ -124,9 +281,12 @@ impl DataSegment {
 		}
 	}
 
-	/// Linear memory index (currently the only valid value is `0`).
+	/// Index of the linear memory the segment is copied into.
 	pub fn index(&self) -> u32 { self.index }
 
+	/// Index of the linear memory the segment is copied into (mutable).
+	pub fn index_mut(&mut self) -> &mut u32 { &mut self.index }
+
 	/// An i32 initializer expression that computes the offset at which to place the data.
 	///
 	/// Note that this return `None` if the segment is `passive`.
@@ -201,3 +361,79 @@ impl Serialize for DataSegment {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::super::{deserialize_buffer, serialize, Error, InitExpr, Instruction, RefType};
+	use super::{ElementSegment, ElementMode, ElementItems};
+
+	#[test]
+	fn element_flags() {
+		let encodings: [&[u8]; 8] = [
+			&[0x00, 0x41, 0x00, 0x0b, 0x01, 0x00],
+			&[0x01, 0x00, 0x01, 0x00],
+			&[0x02, 0x00, 0x41, 0x00, 0x0b, 0x00, 0x01, 0x00],
+			&[0x03, 0x00, 0x01, 0x00],
+			&[0x04, 0x41, 0x00, 0x0b, 0x01, 0xd2, 0x00, 0x0b],
+			&[0x05, 0x70, 0x02, 0xd2, 0x00, 0x0b, 0xd0, 0x70, 0x0b],
+			&[0x06, 0x01, 0x41, 0x00, 0x0b, 0x6f, 0x01, 0xd0, 0x6f, 0x0b],
+			&[0x07, 0x70, 0x01, 0xd2, 0x01, 0x0b],
+		];
+		let modes = [ElementMode::Active, ElementMode::Passive, ElementMode::Active, ElementMode::Declarative];
+		for (flags, &encoding) in encodings.iter().enumerate() {
+			let segment: ElementSegment = deserialize_buffer(encoding).expect("segment to deserialize");
+			assert_eq!(segment.mode(), modes[flags % 4]);
+			assert_eq!(serialize(segment).expect("segment to serialize"), encoding);
+		}
+
+		let segment: ElementSegment = deserialize_buffer(encodings[6]).expect("segment to deserialize");
+		assert_eq!(segment.index(), 1);
+		assert_eq!(segment.elem_type(), RefType::EXTERNREF);
+		assert_eq!(
+			segment.items(),
+			&ElementItems::Expressions(vec![InitExpr::new(vec![Instruction::RefNull(RefType::EXTERNREF.heap_type()), Instruction::End])]),
+		);
+		let segment: ElementSegment = deserialize_buffer(encodings[3]).expect("segment to deserialize");
+		assert_eq!(segment.items(), &ElementItems::Functions(vec![0]));
+		assert!(segment.offset().is_none());
+
+		assert!(matches!(deserialize_buffer::<ElementSegment>(&[0x08]), Err(Error::InvalidSegmentFlags(8))));
+		assert!(deserialize_buffer::<ElementSegment>(&[0x01, 0x01, 0x00]).is_err());
+	}
+
+	#[test]
+	fn element_encoding() {
+		let exprs = vec![InitExpr::new(vec![Instruction::RefNull(RefType::EXTERNREF.heap_type()), Instruction::End])];
+		let offset = InitExpr::new(vec![Instruction::I32Const(0), Instruction::End]);
+		// Expressions of a non-function type need an explicit table index.
+		let segment = ElementSegment::with_items(
+			ElementMode::Active, 0, Some(offset), RefType::EXTERNREF, ElementItems::Expressions(exprs.clone()),
+		);
+		assert_eq!(serialize(segment).expect("segment to serialize"), vec![0x06, 0x00, 0x41, 0x00, 0x0b, 0x6f, 0x01, 0xd0, 0x6f, 0x0b]);
+
+		let segment = ElementSegment::with_items(
+			ElementMode::Active, 0, None, RefType::EXTERNREF, ElementItems::Expressions(exprs),
+		);
+		assert!(serialize(segment).is_err());
+
+		let segment = ElementSegment::with_items(
+			ElementMode::Passive, 0, None, RefType::EXTERNREF, ElementItems::Functions(vec![0]),
+		);
+		assert!(serialize(segment).is_err());
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn deprecated_accessors() {
+		let mut segment = ElementSegment::new(0, None, vec![1, 2], true);
+		segment.members_mut().push(3);
+		assert_eq!(segment.members(), &[1, 2, 3]);
+
+		*segment.passive_mut() = false;
+		assert_eq!(segment.mode(), ElementMode::Active);
+		segment.set_mode(ElementMode::Declarative);
+		assert!(!segment.passive());
+		*segment.items_mut() = ElementItems::Expressions(vec![]);
+		assert!(segment.members().is_empty());
+	}
+}
@@ -0,0 +1,435 @@
+//! Incremental parser for modules that arrive in chunks.
+
+use super::{
+	Deserialize, Error, Context, Section, ImportEntry, FuncBody, CustomSection, VarUint7,
+	VarUint32, Uint32,
+};
+use super::module::WASM_MAGIC_NUMBER;
+use super::borrowed::{SliceReader, section_order};
+
+const IMPORT_SECTION_ID: u8 = 2;
+const CODE_SECTION_ID: u8 = 10;
+
+/// Length of the magic number and the version.
+const HEADER_LEN: usize = 8;
+
+/// Part of a module produced by the streaming [`Parser`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+	/// Module header.
+	Header {
+		/// Version of the module.
+		version: u32,
+	},
+	/// Start of a section.
+	SectionStart {
+		/// Id of the section.
+		id: u8,
+		/// Offset of the section from the start of the module.
+		offset: usize,
+		/// Size of the section payload.
+		size: u32,
+	},
+	/// Entry of the import section.
+	Import(ImportEntry),
+	/// Function body.
+	FunctionBody {
+		/// Index of the body in the code section.
+		index: u32,
+		/// The body itself.
+		body: FuncBody,
+	},
+	/// Custom section.
+	CustomSection(CustomSection),
+	/// Any other section, once all of it is available.
+	Section(Section),
+	/// End of the module.
+	End,
+}
+
+/// Outcome of a [`Parser::parse`] call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chunk {
+	/// At least this many more bytes are needed to produce the next event.
+	NeedMoreData(usize),
+	/// Event decoded from the first `consumed` bytes of the input.
+	Parsed {
+		/// Number of input bytes used by the event.
+		consumed: usize,
+		/// The event.
+		event: Event,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+	Header,
+	SectionStart,
+	/// Section decoded as a whole, including its header.
+	Section {
+		len: usize,
+	},
+	Imports {
+		start: usize,
+		end: usize,
+		index: u32,
+		remaining: u32,
+	},
+	Code {
+		start: usize,
+		end: usize,
+		index: u32,
+		remaining: u32,
+	},
+	End,
+}
+
+/// Incremental parser producing [`Event`]s from chunks of a module.
+///
+/// The caller keeps the bytes not consumed yet and passes them to
+/// [`parse`](Parser::parse), followed by any newly arrived ones. Only one
+/// section, or one import or function body, needs to be kept at a time.
+#[derive(Debug, Clone)]
+pub struct Parser {
+	state: State,
+	offset: usize,
+	last_section_order: u8,
+	functions: usize,
+	bodies: usize,
+}
+
+impl Default for Parser {
+	fn default() -> Self {
+		Parser::new()
+	}
+}
+
+impl Parser {
+	/// New parser expecting the module header.
+	pub fn new() -> Self {
+		Parser { state: State::Header, offset: 0, last_section_order: 0, functions: 0, bodies: 0 }
+	}
+
+	/// Offset of the next byte to parse, from the start of the module.
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+
+	/// Parse the next event from the bytes following the consumed ones.
+	///
+	/// `eof` tells that no more bytes follow `data`, so the module is truncated
+	/// if more are needed.
+	pub fn parse(&mut self, data: &[u8], eof: bool) -> Result<Chunk, Error> {
+		let chunk = match self.state {
+			State::Header => self.header(data)?,
+			State::SectionStart => self.section_start(data, eof)?,
+			State::Section { len } => self.section(data, len)?,
+			State::Imports { start, end, index, remaining } => {
+				self.import(data, start, end, index, remaining)
+					.map_err(|e| e.located(Context::Section(IMPORT_SECTION_ID), Some(start)))?
+			},
+			State::Code { start, end, index, remaining } => {
+				self.body(data, start, end, index, remaining)
+					.map_err(|e| e.located(Context::Section(CODE_SECTION_ID), Some(start)))?
+			},
+			State::End if data.is_empty() => Chunk::Parsed { consumed: 0, event: Event::End },
+			State::End => return Err(Error::TrailingData),
+		};
+		match chunk {
+			Chunk::NeedMoreData(_) if eof => Err(Error::UnexpectedEof),
+			Chunk::Parsed { consumed, .. } => {
+				self.offset += consumed;
+				Ok(chunk)
+			},
+			chunk => Ok(chunk),
+		}
+	}
+
+	fn header(&mut self, data: &[u8]) -> Result<Chunk, Error> {
+		if data.len() < HEADER_LEN {
+			return Ok(Chunk::NeedMoreData(HEADER_LEN - data.len()));
+		}
+		if data[..4] != WASM_MAGIC_NUMBER {
+			return Err(Error::InvalidMagic);
+		}
+		let version: u32 = Uint32::deserialize(&mut SliceReader::new(&data[4..HEADER_LEN], 4))?.into();
+		if version != 1 {
+			return Err(Error::UnsupportedVersion(version));
+		}
+		self.state = State::SectionStart;
+		Ok(Chunk::Parsed { consumed: HEADER_LEN, event: Event::Header { version } })
+	}
+
+	fn section_start(&mut self, data: &[u8], eof: bool) -> Result<Chunk, Error> {
+		if data.is_empty() {
+			if !eof {
+				return Ok(Chunk::NeedMoreData(1));
+			}
+			if self.functions != self.bodies {
+				return Err(Error::InconsistentCode);
+			}
+			self.state = State::End;
+			return Ok(Chunk::Parsed { consumed: 0, event: Event::End });
+		}
+
+		let offset = self.offset;
+		let header = attempt(data, offset, |reader| {
+			let id: u8 = VarUint7::deserialize(reader)?.into();
+			let size: u32 = VarUint32::deserialize(reader)?.into();
+			Ok((id, size))
+		})?;
+		let ((id, size), header_len) = match header {
+			Some(header) => header,
+			None => return Ok(Chunk::NeedMoreData(1)),
+		};
+		let located = |error: Error| error.located(Context::Section(id), Some(offset));
+
+		let order = section_order(id).ok_or_else(|| located(Error::InvalidSectionId(id)))?;
+		if order != 0 {
+			if self.last_section_order > order {
+				return Err(located(Error::SectionsOutOfOrder));
+			} else if self.last_section_order == order {
+				return Err(located(Error::DuplicatedSections(order)));
+			}
+		}
+
+		let len = header_len + size as usize;
+		let consumed = if id == IMPORT_SECTION_ID || id == CODE_SECTION_ID {
+			// The entry count is consumed along with the header.
+			let section = &data[..data.len().min(len)];
+			let count = attempt(&section[header_len..], offset + header_len, |reader| {
+				Ok(u32::from(VarUint32::deserialize(reader)?))
+			}).map_err(located)?;
+			let (count, count_len) = match count {
+				Some(count) => count,
+				None if section.len() == len => return Err(located(Error::UnexpectedEof)),
+				None => return Ok(Chunk::NeedMoreData(1)),
+			};
+			let consumed = header_len + count_len;
+			self.state = match count {
+				0 if consumed != len => {
+					return Err(located(Error::InconsistentLength { expected: len, actual: consumed }));
+				},
+				0 => State::SectionStart,
+				_ if id == IMPORT_SECTION_ID => {
+					State::Imports { start: offset, end: offset + len, index: 0, remaining: count }
+				},
+				_ => State::Code { start: offset, end: offset + len, index: 0, remaining: count },
+			};
+			consumed
+		} else {
+			self.state = State::Section { len };
+			0
+		};
+
+		if order != 0 {
+			self.last_section_order = order;
+		}
+		Ok(Chunk::Parsed { consumed, event: Event::SectionStart { id, offset, size } })
+	}
+
+	fn section(&mut self, data: &[u8], len: usize) -> Result<Chunk, Error> {
+		if data.len() < len {
+			return Ok(Chunk::NeedMoreData(len - data.len()));
+		}
+		let section = Section::deserialize(&mut SliceReader::new(&data[..len], self.offset))?;
+		let event = match section {
+			Section::Custom(custom) => Event::CustomSection(custom),
+			Section::Function(ref functions) => {
+				self.functions = functions.entries().len();
+				Event::Section(section)
+			},
+			section => Event::Section(section),
+		};
+		self.state = State::SectionStart;
+		Ok(Chunk::Parsed { consumed: len, event })
+	}
+
+	fn import(&mut self, data: &[u8], start: usize, end: usize, index: u32, remaining: u32)
+		-> Result<Chunk, Error>
+	{
+		let left = end - self.offset;
+		let located = |error: Error| error.located(Context::Entry(index), Some(self.offset));
+		let entry = attempt(&data[..data.len().min(left)], self.offset, ImportEntry::deserialize)
+			.map_err(located)?;
+		let (entry, consumed) = match entry {
+			Some(entry) => entry,
+			None if data.len() >= left => return Err(located(Error::UnexpectedEof)),
+			None => return Ok(Chunk::NeedMoreData(1)),
+		};
+		self.state = self.next_entry(start, end, consumed, remaining, |remaining| {
+			State::Imports { start, end, index: index + 1, remaining }
+		})?;
+		Ok(Chunk::Parsed { consumed, event: Event::Import(entry) })
+	}
+
+	fn body(&mut self, data: &[u8], start: usize, end: usize, index: u32, remaining: u32)
+		-> Result<Chunk, Error>
+	{
+		let left = end - self.offset;
+		let located = |error: Error| error.located(Context::FuncBody(index), Some(self.offset));
+		let size = attempt(&data[..data.len().min(left)], self.offset, |reader| {
+			Ok(u32::from(VarUint32::deserialize(reader)?))
+		}).map_err(located)?;
+		let len = match size {
+			Some((size, size_len)) => size_len + size as usize,
+			None if data.len() >= left => return Err(located(Error::UnexpectedEof)),
+			None => return Ok(Chunk::NeedMoreData(1)),
+		};
+		if len > left {
+			return Err(located(Error::InconsistentLength { expected: left, actual: len }));
+		}
+		if data.len() < len {
+			return Ok(Chunk::NeedMoreData(len - data.len()));
+		}
+
+		let body = FuncBody::deserialize(&mut SliceReader::new(&data[..len], self.offset)).map_err(located)?;
+		self.bodies += 1;
+		self.state = self.next_entry(start, end, len, remaining, |remaining| {
+			State::Code { start, end, index: index + 1, remaining }
+		})?;
+		Ok(Chunk::Parsed { consumed: len, event: Event::FunctionBody { index, body } })
+	}
+
+	/// State after an entry of `consumed` bytes, checking that the last entry ends the section.
+	fn next_entry(&self, start: usize, end: usize, consumed: usize, remaining: u32, next: impl FnOnce(u32) -> State)
+		-> Result<State, Error>
+	{
+		let entry_end = self.offset + consumed;
+		match remaining - 1 {
+			0 if entry_end != end => {
+				Err(Error::InconsistentLength { expected: end - start, actual: entry_end - start })
+			},
+			0 => Ok(State::SectionStart),
+			remaining => Ok(next(remaining)),
+		}
+	}
+}
+
+/// Decode from the start of `data` at `offset`, `None` if `data` ends too early.
+fn attempt<'a, T>(data: &'a [u8], offset: usize, f: impl FnOnce(&mut SliceReader<'a>) -> Result<T, Error>)
+	-> Result<Option<(T, usize)>, Error>
+{
+	let mut reader = SliceReader::new(data, offset);
+	match f(&mut reader) {
+		Ok(value) => Ok(Some((value, reader.consumed()))),
+		Err(ref e) if matches!(*e.kind(), Error::UnexpectedEof) => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		serialize, deserialize_buffer, Module, Section, TypeSection, ImportSection, FunctionSection,
+		CodeSection, CustomSection, FunctionType, ImportEntry, External, Func, FuncBody, Instructions,
+		Instruction, Error,
+	};
+	use super::{Parser, Chunk, Event};
+
+	fn wasm() -> Vec<u8> {
+		let body = |value| FuncBody::new(Vec::new(), Instructions::new(vec![
+			Instruction::I32Const(value),
+			Instruction::Drop,
+			Instruction::End,
+		]));
+		serialize(Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![FunctionType::default().into()])),
+			Section::Import(ImportSection::with_entries(vec![
+				ImportEntry::new("env".into(), "a".into(), External::Function(0)),
+				ImportEntry::new("env".into(), "b".into(), External::Function(0)),
+			])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0), Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![body(1), body(100_000)])),
+			Section::Custom(CustomSection::new("extra".into(), vec![1, 2, 3])),
+		])).expect("module to serialize")
+	}
+
+	/// Feed the module to the parser one byte at a time.
+	fn events(wasm: &[u8]) -> Result<Vec<Event>, Error> {
+		let mut parser = Parser::new();
+		let mut events = Vec::new();
+		let mut buffer = Vec::new();
+		let mut input = wasm.iter();
+		loop {
+			let eof = input.len() == 0;
+			match parser.parse(&buffer, eof)? {
+				Chunk::NeedMoreData(n) => {
+					assert!(n > 0);
+					buffer.push(*input.next().expect("parser to need more bytes only before the end"));
+				},
+				Chunk::Parsed { consumed, event } => {
+					buffer.drain(..consumed);
+					if event == Event::End {
+						break;
+					}
+					events.push(event);
+				},
+			}
+		}
+		assert_eq!(parser.offset(), wasm.len());
+		Ok(events)
+	}
+
+	#[test]
+	fn streaming() {
+		let wasm = wasm();
+		let module: Module = deserialize_buffer(&wasm).expect("module to deserialize");
+		let events = events(&wasm).expect("module to parse");
+
+		assert_eq!(events[0], Event::Header { version: 1 });
+		assert!(matches!(events[1], Event::SectionStart { id: 1, offset: 8, .. }));
+		assert_eq!(events[2], Event::Section(module.sections()[0].clone()));
+		assert!(matches!(events[3], Event::SectionStart { id: 2, .. }));
+		assert_eq!(events[4], Event::Import(module.import_section().unwrap().entries()[0].clone()));
+		assert_eq!(events[5], Event::Import(module.import_section().unwrap().entries()[1].clone()));
+		assert!(matches!(events[7], Event::Section(Section::Function(_))));
+		let bodies = module.code_section().unwrap().bodies();
+		assert_eq!(events[9], Event::FunctionBody { index: 0, body: bodies[0].clone() });
+		assert_eq!(events[10], Event::FunctionBody { index: 1, body: bodies[1].clone() });
+		assert!(matches!(events[11], Event::SectionStart { id: 0, .. }));
+		assert_eq!(events[12], Event::CustomSection(CustomSection::new("extra".into(), vec![1, 2, 3])));
+		assert_eq!(events.len(), 13);
+	}
+
+	#[test]
+	fn need_more_data() {
+		let wasm = wasm();
+		let mut parser = Parser::new();
+		assert!(matches!(parser.parse(&wasm[..3], false), Ok(Chunk::NeedMoreData(5))));
+		assert!(matches!(parser.parse(&wasm[..8], false), Ok(Chunk::Parsed { consumed: 8, .. })));
+		let section = &wasm[8..];
+		let size = section[1] as usize;
+		assert!(matches!(parser.parse(&section[..2], false), Ok(Chunk::Parsed { consumed: 0, .. })));
+		assert!(matches!(parser.parse(&section[..2], false), Ok(Chunk::NeedMoreData(n)) if n == size));
+	}
+
+	#[test]
+	fn truncated() {
+		let wasm = wasm();
+		assert!(matches!(events(&wasm[..wasm.len() - 1]), Err(Error::UnexpectedEof)));
+
+		// Stop right before the code section.
+		let mut parser = Parser::new();
+		let mut data = &wasm[..];
+		loop {
+			match parser.parse(data, false).expect("module to parse") {
+				Chunk::Parsed { event: Event::SectionStart { id: 10, .. }, .. } => break,
+				Chunk::Parsed { consumed, .. } => data = &data[consumed..],
+				Chunk::NeedMoreData(_) => unreachable!(),
+			}
+		}
+		let mut parser = Parser::new();
+		let code = wasm.len() - data.len();
+		let mut data = &wasm[..code];
+		loop {
+			match parser.parse(data, true) {
+				Ok(Chunk::Parsed { consumed, .. }) => data = &data[consumed..],
+				result => {
+					assert!(matches!(result, Err(Error::InconsistentCode)));
+					break;
+				},
+			}
+		}
+	}
+}
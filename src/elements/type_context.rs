@@ -0,0 +1,281 @@
+use crate::rust::vec::Vec;
+use super::{
+	TypeSection, Type, ValueType, RefType, HeapType, FunctionType, StructType, ArrayType,
+	FieldType, StorageType,
+};
+
+/// Type context of a module, used to answer subtyping queries.
+///
+/// Concrete heap types are resolved through the types section the context
+/// was built from and compared by their canonical ids. A context built with
+/// `Default` has no type definitions.
+#[derive(Debug, Clone, Default)]
+pub struct TypeContext<'a> {
+	section: Option<&'a TypeSection>,
+	canonical: Vec<u32>,
+}
+
+impl<'a> TypeContext<'a> {
+	/// New type context over the given types section.
+	///
+	/// If the section can not be canonicalized, type indices are compared literally.
+	pub fn new(section: &'a TypeSection) -> Self {
+		let canonical = section.canonical_ids()
+			.unwrap_or_else(|_| (0..section.types().len() as u32).collect());
+		TypeContext { section: Some(section), canonical }
+	}
+
+	/// Type definition with the given index, if any.
+	pub fn ty(&self, index: u32) -> Option<&'a Type> {
+		self.section.and_then(|s| s.types().get(index as usize))
+	}
+
+	/// Declared supertype of the type with the given index, if any.
+	pub fn supertype(&self, index: u32) -> Option<u32> {
+		self.section
+			.and_then(|s| s.subtype(index))
+			.and_then(|sub| sub.supertypes().first().copied())
+	}
+
+	/// Are the types with indices `a` and `b` equivalent.
+	pub fn is_same_type(&self, a: u32, b: u32) -> bool {
+		match (self.canonical.get(a as usize), self.canonical.get(b as usize)) {
+			(Some(a), Some(b)) => a == b,
+			_ => a == b,
+		}
+	}
+
+	/// Is `a` a subtype of `b` in this context.
+	pub fn is_subtype<T: Subtype + ?Sized>(&self, a: &T, b: &T) -> bool {
+		a.is_subtype(b, self)
+	}
+
+	/// Is the type with index `a` declared as a (transitive) subtype of the type with index `b`.
+	fn is_declared_subtype(&self, a: u32, b: u32) -> bool {
+		let mut current = a;
+		// Bound the walk by the number of types so that a cyclic (invalid)
+		// declaration can not loop forever.
+		let limit = self.section.map(|s| s.types().len()).unwrap_or(0);
+		for _ in 0..=limit {
+			if self.is_same_type(current, b) {
+				return true;
+			}
+			match self.supertype(current) {
+				Some(next) => current = next,
+				None => return false,
+			}
+		}
+		false
+	}
+
+	/// Closest abstract supertype of the concrete type with the given index.
+	fn abstract_supertype(&self, index: u32) -> Option<HeapType> {
+		match self.ty(index)? {
+			Type::Function(_) => Some(HeapType::Func),
+			Type::Struct(_) => Some(HeapType::Struct),
+			Type::Array(_) => Some(HeapType::Array),
+		}
+	}
+}
+
+/// Types that can be compared with the subtyping relation.
+pub trait Subtype {
+	/// Is `self` a subtype of `other` in the given type context.
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool;
+}
+
+impl Subtype for HeapType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		use self::HeapType as H;
+
+		match (*self, *other) {
+			(a, b) if a == b => true,
+			(H::Index(a), H::Index(b)) => ctx.is_declared_subtype(a, b),
+			(H::Index(a), b) => ctx.abstract_supertype(a).is_some_and(|sup| sup.is_subtype(&b, ctx)),
+			(H::None, H::Index(b)) => matches!(ctx.abstract_supertype(b), Some(H::Struct) | Some(H::Array)),
+			(H::NoFunc, H::Index(b)) => ctx.abstract_supertype(b) == Some(H::Func),
+			(_, H::Index(_)) => false,
+			(H::None, b) => matches!(b, H::Any | H::Eq | H::I31 | H::Struct | H::Array),
+			(H::I31, H::Eq) | (H::Struct, H::Eq) | (H::Array, H::Eq) => true,
+			(H::I31, H::Any) | (H::Struct, H::Any) | (H::Array, H::Any) | (H::Eq, H::Any) => true,
+			(H::NoFunc, H::Func) | (H::NoExtern, H::Extern) => true,
+			_ => false,
+		}
+	}
+}
+
+impl Subtype for RefType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		(!self.is_nullable() || other.is_nullable())
+			&& self.heap_type().is_subtype(&other.heap_type(), ctx)
+	}
+}
+
+impl Subtype for ValueType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		match (self, other) {
+			(ValueType::Ref(a), ValueType::Ref(b)) => a.is_subtype(b, ctx),
+			(a, b) => a == b,
+		}
+	}
+}
+
+impl Subtype for StorageType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		match (self, other) {
+			(StorageType::Value(a), StorageType::Value(b)) => a.is_subtype(b, ctx),
+			(a, b) => a == b,
+		}
+	}
+}
+
+impl Subtype for FieldType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		let (a, b) = (self.storage_type(), other.storage_type());
+		match (self.is_mutable(), other.is_mutable()) {
+			(false, false) => a.is_subtype(&b, ctx),
+			// Mutable fields are invariant.
+			(true, true) => a.is_subtype(&b, ctx) && b.is_subtype(&a, ctx),
+			_ => false,
+		}
+	}
+}
+
+impl Subtype for StructType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		self.fields().len() >= other.fields().len()
+			&& self.fields().iter()
+				.zip(other.fields())
+				.all(|(a, b)| a.is_subtype(b, ctx))
+	}
+}
+
+impl Subtype for ArrayType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		self.elem().is_subtype(other.elem(), ctx)
+	}
+}
+
+impl Subtype for FunctionType {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		let params = self.params().len() == other.params().len()
+			&& self.params().iter()
+				.zip(other.params())
+				.all(|(a, b)| b.is_subtype(a, ctx));
+		let results = self.results().len() == other.results().len()
+			&& self.results().iter()
+				.zip(other.results())
+				.all(|(a, b)| a.is_subtype(b, ctx));
+		params && results
+	}
+}
+
+impl Subtype for Type {
+	fn is_subtype(&self, other: &Self, ctx: &TypeContext) -> bool {
+		match (self, other) {
+			(Type::Function(a), Type::Function(b)) => a.is_subtype(b, ctx),
+			(Type::Struct(a), Type::Struct(b)) => a.is_subtype(b, ctx),
+			(Type::Array(a), Type::Array(b)) => a.is_subtype(b, ctx),
+			_ => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		TypeSection, Type, ValueType, RefType, HeapType, FunctionType, StructType, ArrayType,
+		FieldType, StorageType, NumType, SubType,
+	};
+	use super::TypeContext;
+
+	fn section() -> TypeSection {
+		let i32_field = FieldType::new(ValueType::from(NumType::I32), false);
+		let mut section = TypeSection::with_types(vec![
+			// 0: (struct (field i32))
+			Type::Struct(StructType::new(vec![i32_field.clone()])),
+			// 1: (sub 0 (struct (field i32) (field i32)))
+			Type::Struct(StructType::new(vec![i32_field.clone(), i32_field])),
+			// 2: (array (mut i8))
+			Type::Array(ArrayType::new(FieldType::new(StorageType::PackedI8, true))),
+			// 3: (func)
+			Type::Function(FunctionType::default()),
+		]);
+		section.set_subtype(0, SubType::new(false, vec![]));
+		section.set_subtype(1, SubType::new(true, vec![0]));
+		section
+	}
+
+	#[test]
+	fn heap_types() {
+		let section = section();
+		let ctx = TypeContext::new(&section);
+
+		assert!(ctx.is_subtype(&HeapType::I31, &HeapType::Any));
+		assert!(ctx.is_subtype(&HeapType::None, &HeapType::Eq));
+		assert!(ctx.is_subtype(&HeapType::NoFunc, &HeapType::Func));
+		assert!(!ctx.is_subtype(&HeapType::None, &HeapType::Func));
+		assert!(!ctx.is_subtype(&HeapType::Any, &HeapType::Eq));
+		assert!(!ctx.is_subtype(&HeapType::Extern, &HeapType::Any));
+
+		assert!(ctx.is_subtype(&HeapType::Index(1), &HeapType::Index(0)));
+		assert!(!ctx.is_subtype(&HeapType::Index(0), &HeapType::Index(1)));
+		assert!(ctx.is_subtype(&HeapType::Index(1), &HeapType::Eq));
+		assert!(ctx.is_subtype(&HeapType::Index(2), &HeapType::Array));
+		assert!(!ctx.is_subtype(&HeapType::Index(2), &HeapType::Struct));
+		assert!(ctx.is_subtype(&HeapType::Index(3), &HeapType::Func));
+		assert!(ctx.is_subtype(&HeapType::None, &HeapType::Index(2)));
+		assert!(ctx.is_subtype(&HeapType::NoFunc, &HeapType::Index(3)));
+		assert!(!ctx.is_subtype(&HeapType::None, &HeapType::Index(3)));
+	}
+
+	#[test]
+	fn value_types() {
+		let section = section();
+		let ctx = TypeContext::new(&section);
+
+		let nonnull = ValueType::from(RefType::new(false, HeapType::Index(1)));
+		let nullable = ValueType::from(RefType::new(true, HeapType::Index(0)));
+		assert!(ctx.is_subtype(&nonnull, &nullable));
+		assert!(!ctx.is_subtype(&nullable, &nonnull));
+		assert!(ctx.is_subtype(&nullable, &ValueType::from(RefType::ANYREF)));
+		assert!(!ctx.is_subtype(&ValueType::from(NumType::I32), &nullable));
+		assert!(ctx.is_subtype(&ValueType::from(NumType::I64), &ValueType::from(NumType::I64)));
+	}
+
+	#[test]
+	fn composite_types() {
+		let ctx = TypeContext::default();
+		let eqref = ValueType::from(RefType::EQREF);
+		let i31ref = ValueType::from(RefType::I31REF);
+
+		let wide = StructType::new(vec![FieldType::new(i31ref, false), FieldType::new(i31ref, true)]);
+		let narrow = StructType::new(vec![FieldType::new(eqref, false)]);
+		assert!(ctx.is_subtype(&wide, &narrow));
+		assert!(!ctx.is_subtype(&narrow, &wide));
+
+		let mutable = StructType::new(vec![FieldType::new(eqref, true)]);
+		assert!(!ctx.is_subtype(&wide, &mutable));
+
+		let sub = ArrayType::new(FieldType::new(i31ref, false));
+		let sup = ArrayType::new(FieldType::new(eqref, false));
+		assert!(ctx.is_subtype(&sub, &sup));
+		assert!(!ctx.is_subtype(&sup, &sub));
+
+		let sub = FunctionType::new(vec![eqref], vec![i31ref]);
+		let sup = FunctionType::new(vec![i31ref], vec![eqref]);
+		assert!(ctx.is_subtype(&sub, &sup));
+		assert!(!ctx.is_subtype(&sup, &sub));
+	}
+
+	#[test]
+	fn equivalent_types() {
+		let ty = Type::Struct(StructType::new(vec![FieldType::new(StorageType::PackedI8, false)]));
+		let section = TypeSection::with_types(vec![ty.clone(), ty]);
+		let ctx = TypeContext::new(&section);
+
+		assert!(ctx.is_same_type(0, 1));
+		assert!(ctx.is_subtype(&HeapType::Index(1), &HeapType::Index(0)));
+		assert!(ctx.is_subtype(&HeapType::Index(0), &HeapType::Index(1)));
+	}
+}
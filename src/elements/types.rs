@@ -2,7 +2,7 @@ use crate::rust::{fmt, vec::Vec};
 use crate::io;
 use super::{
 	Deserialize, Serialize, Error, VarInt7, VarUint1, CountedList,
-	CountedListWriter, VarUint32,
+	CountedListWriter, VarUint32, VarInt64,
 };
 
 const I32TYPE: i8 = -0x01;
@@ -10,16 +10,71 @@ const I64TYPE: i8 = -0x02;
 const F32TYPE: i8 = -0x03;
 const F64TYPE: i8 = -0x04;
 const V128TYPE: i8 = -0x05;
-const ANYFUNCTYPE: i8 = -0x10;
-const ANYREFTYPE: i8 = -0x11;
-const REFTYPE: i8 = -0x12;
-const PACKEDI8TYPE: i8 = -0x18;
-const PACKEDI16TYPE: i8 = -0x19;
+const PACKEDI8TYPE: i8 = -0x08;
+const PACKEDI16TYPE: i8 = -0x09;
+const NOFUNCHEAPTYPE: i8 = -0x0d;
+const NOEXTERNHEAPTYPE: i8 = -0x0e;
+const NONEHEAPTYPE: i8 = -0x0f;
+const FUNCHEAPTYPE: i8 = -0x10;
+const EXTERNHEAPTYPE: i8 = -0x11;
+const ANYHEAPTYPE: i8 = -0x12;
+const EQHEAPTYPE: i8 = -0x13;
+const I31HEAPTYPE: i8 = -0x14;
+const STRUCTHEAPTYPE: i8 = -0x15;
+const ARRAYHEAPTYPE: i8 = -0x16;
+const REFTYPE: i8 = -0x1c;
+const REFNULLTYPE: i8 = -0x1d;
 const FUNCTIONTYPE: i8 = -0x20;
 const STRUCTTYPE: i8 = -0x21;
 const ARRAYTYPE: i8 = -0x22;
+const SUBTYPE: i8 = -0x30;
+const SUBFINALTYPE: i8 = -0x31;
+pub(crate) const RECTYPE: i8 = -0x32;
 const NORESULTTYPE: i8 = -0x40;
 
+const LEGACY_ANYREFTYPE: i8 = -0x11;
+const LEGACY_REFTYPE: i8 = -0x12;
+const LEGACY_PACKEDI8TYPE: i8 = -0x18;
+const LEGACY_PACKEDI16TYPE: i8 = -0x19;
+
+/// Binary encoding dialect of type definitions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Hash, Eq)]
+pub enum Dialect {
+	/// Encoding of the standardized GC proposal.
+	#[default]
+	Standard,
+	/// Prototype GC encoding used by earlier versions of this crate.
+	///
+	/// `anyref` is read as `externref` and `(ref N)` as `(ref null N)`. Block types
+	/// are a single byte, so a reference block type always refers to type `0`.
+	Legacy,
+}
+
+/// Read a counted list of entries.
+pub(crate) fn read_list<R: io::Read, T>(
+	reader: &mut R,
+	mut read: impl FnMut(&mut R) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+	let count: u32 = VarUint32::deserialize(reader)?.into();
+	let mut items = Vec::new();
+	for _ in 0..count {
+		items.push(read(reader)?);
+	}
+	Ok(items)
+}
+
+fn write_list<W: io::Write, T>(
+	writer: &mut W,
+	items: Vec<T>,
+	mut write: impl FnMut(T, &mut W) -> Result<(), Error>,
+) -> Result<(), Error> {
+	VarUint32::from(items.len()).serialize(writer)?;
+	for item in items {
+		write(item, writer)?;
+	}
+	Ok(())
+}
+
 /// Type definition in types section. Currently can be only of the function type.
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub enum Type {
@@ -43,39 +98,146 @@ impl From<ArrayType> for Type {
 	fn from(x: ArrayType) -> Type { Type::Array(x) }
 }
 
-impl Deserialize for Type {
-	type Error = Error;
-
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+impl Type {
+	/// Read a type definition encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
 		let val = VarInt7::deserialize(reader)?.into();
-		match val {
-			FUNCTIONTYPE => FunctionType::deserialize(reader).map(Into::into),
-			STRUCTTYPE => StructType::deserialize(reader).map(Into::into),
-			ARRAYTYPE => ArrayType::deserialize(reader).map(Into::into),
-			_ => Err(Error::UnknownValueType(val.into())),
-		}
+		Type::deserialize_form(val, reader, dialect)
 	}
-}
-
-impl Serialize for Type {
-	type Error = Error;
 
-	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
+	/// Write a type definition encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
 		match self {
 			Type::Function(fn_type) => {
 				VarInt7::from(FUNCTIONTYPE).serialize(writer)?;
-				fn_type.serialize(writer)
+				fn_type.serialize_dialect(writer, dialect)
 			},
 			Type::Struct(stuct_type) => {
 				VarInt7::from(STRUCTTYPE).serialize(writer)?;
-				stuct_type.serialize(writer)
+				stuct_type.serialize_dialect(writer, dialect)
 			},
 			Type::Array(arr_type) => {
 				VarInt7::from(ARRAYTYPE).serialize(writer)?;
-				arr_type.serialize(writer)
+				arr_type.serialize_dialect(writer, dialect)
+			},
+		}
+	}
+
+	/// Read the rest of the type definition given its already consumed `form`.
+	fn deserialize_form<R: io::Read>(form: i8, reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		match form {
+			FUNCTIONTYPE => FunctionType::deserialize_dialect(reader, dialect).map(Into::into),
+			STRUCTTYPE => StructType::deserialize_dialect(reader, dialect).map(Into::into),
+			ARRAYTYPE => ArrayType::deserialize_dialect(reader, dialect).map(Into::into),
+			_ => Err(Error::UnknownValueType(form)),
+		}
+	}
+}
+
+impl Deserialize for Type {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		Type::deserialize_dialect(reader, Dialect::Standard)
+	}
+}
+
+impl Serialize for Type {
+	type Error = Error;
+
+	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
+		self.serialize_dialect(writer, Dialect::Standard)
+	}
+}
+
+/// Subtype declaration (`sub` or `sub final`) of a type in the types section.
+///
+/// Types declared without it are final and have no supertypes.
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+pub struct SubType {
+	is_final: bool,
+	supertypes: Vec<u32>,
+}
+
+impl Default for SubType {
+	fn default() -> Self {
+		SubType { is_final: true, supertypes: Vec::new() }
+	}
+}
+
+impl SubType {
+	/// New subtype declaration with the given finality and supertype indices.
+	pub fn new(is_final: bool, supertypes: Vec<u32>) -> Self {
+		SubType { is_final, supertypes }
+	}
+	/// Is the type declared as final (cannot be subtyped further).
+	pub fn is_final(&self) -> bool { self.is_final }
+	/// Is the type declared as final (mutable).
+	pub fn final_mut(&mut self) -> &mut bool { &mut self.is_final }
+	/// Indices of the declared supertypes.
+	pub fn supertypes(&self) -> &[u32] { &self.supertypes }
+	/// Indices of the declared supertypes (mutable).
+	pub fn supertypes_mut(&mut self) -> &mut Vec<u32> { &mut self.supertypes }
+
+	/// Read a possibly `sub`-wrapped type definition given its already consumed `form`.
+	pub(crate) fn deserialize_form<R: io::Read>(form: i8, reader: &mut R, dialect: Dialect)
+		-> Result<(Option<SubType>, Type), Error>
+	{
+		match form {
+			SUBTYPE | SUBFINALTYPE => {
+				let supertypes: Vec<u32> = CountedList::<VarUint32>::deserialize(reader)?
+					.into_inner()
+					.into_iter()
+					.map(Into::into)
+					.collect();
+				let ty = Type::deserialize_dialect(reader, dialect)?;
+				Ok((Some(SubType { is_final: form == SUBFINALTYPE, supertypes }), ty))
 			},
+			_ => Ok((None, Type::deserialize_form(form, reader, dialect)?)),
 		}
 	}
+
+	/// Write a type definition, wrapped into `sub` if a declaration is given.
+	pub(crate) fn serialize_type<W: io::Write>(
+		sub: Option<SubType>,
+		ty: Type,
+		writer: &mut W,
+		dialect: Dialect,
+	) -> Result<(), Error>
+	{
+		if let Some(sub) = sub {
+			VarInt7::from(if sub.is_final { SUBFINALTYPE } else { SUBTYPE }).serialize(writer)?;
+			CountedListWriter::<VarUint32, _>(
+				sub.supertypes.len(),
+				sub.supertypes.into_iter().map(Into::into),
+			).serialize(writer)?;
+		}
+		ty.serialize_dialect(writer, dialect)
+	}
+}
+
+/// Recursion group (`rec`) of consecutive type definitions in the types section.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+pub struct RecGroup {
+	start: u32,
+	len: u32,
+}
+
+impl RecGroup {
+	/// New recursion group of `len` types starting at type index `start`.
+	pub fn new(start: u32, len: u32) -> Self {
+		RecGroup { start, len }
+	}
+	/// Index of the first type in the group.
+	pub fn start(&self) -> u32 { self.start }
+	/// Number of types in the group.
+	pub fn len(&self) -> u32 { self.len }
+	/// Is the group empty.
+	pub fn is_empty(&self) -> bool { self.len == 0 }
+	/// Index one past the last type in the group.
+	pub fn end(&self) -> u32 { self.start + self.len }
+	/// Does the group contain the type with the given index.
+	pub fn contains(&self, index: u32) -> bool { index >= self.start && index < self.end() }
 }
 
 /// Value type.
@@ -102,92 +264,288 @@ impl From<NumType> for ValueType {
 }
 
 impl ValueType {
-	fn from_bits(x: i8) -> Option<ValueType> {
+	/// Read a value type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		let val: i8 = VarInt7::deserialize(reader)?.into();
+		ValueType::read_bits(val, reader, dialect)?.ok_or(Error::UnknownValueType(val))
+	}
+
+	/// Write a value type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		match self {
+			ValueType::Num(n) => VarInt7::from(n.to_bits()).serialize(writer),
+			ValueType::Ref(r) => r.serialize_dialect(writer, dialect),
+			ValueType::V128 => VarInt7::from(V128TYPE).serialize(writer),
+		}
+	}
+
+	/// Decode the rest of a value type given its leading byte.
+	///
+	/// Returns `None` if `bits` does not start a value type.
+	fn read_bits<R: io::Read>(bits: i8, reader: &mut R, dialect: Dialect)
+		-> Result<Option<ValueType>, Error>
+	{
+		if bits == V128TYPE {
+			return Ok(Some(ValueType::V128));
+		}
+		if let Some(n) = NumType::from_bits(bits) {
+			return Ok(Some(n.into()));
+		}
+		Ok(RefType::read_bits(bits, reader, dialect)?.map(Into::into))
+	}
+}
+
+/// Heap type, the target of a reference.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub enum HeapType {
+	/// Any function
+	Func,
+	/// Any external (host) reference
+	Extern,
+	/// Any internal reference
+	Any,
+	/// Any reference comparable with `ref.eq`
+	Eq,
+	/// Unboxed 31-bit scalar
+	I31,
+	/// Any structure
+	Struct,
+	/// Any array
+	Array,
+	/// Bottom type of the `any` hierarchy
+	None,
+	/// Bottom type of the `func` hierarchy
+	NoFunc,
+	/// Bottom type of the `extern` hierarchy
+	NoExtern,
+	/// Concrete type defined in the type section
+	Index(u32),
+}
+
+impl HeapType {
+	fn from_bits(x: i8) -> Option<HeapType> {
 		match x {
-			V128TYPE => Some(ValueType::V128),
+			FUNCHEAPTYPE => Some(HeapType::Func),
+			EXTERNHEAPTYPE => Some(HeapType::Extern),
+			ANYHEAPTYPE => Some(HeapType::Any),
+			EQHEAPTYPE => Some(HeapType::Eq),
+			I31HEAPTYPE => Some(HeapType::I31),
+			STRUCTHEAPTYPE => Some(HeapType::Struct),
+			ARRAYHEAPTYPE => Some(HeapType::Array),
+			NONEHEAPTYPE => Some(HeapType::None),
+			NOFUNCHEAPTYPE => Some(HeapType::NoFunc),
+			NOEXTERNHEAPTYPE => Some(HeapType::NoExtern),
 			_ => None,
 		}
-		.or(NumType::from_bits(x).map(Into::into))
-		.or(RefType::from_bits(x).map(Into::into))
 	}
 
-	fn to_bits(self) -> i8 {
+	fn to_bits(self) -> Option<i8> {
 		match self {
-			ValueType::V128 => V128TYPE,
-			ValueType::Num(n) => n.to_bits(),
-			ValueType::Ref(r) => r.to_bits(),
+			HeapType::Func => Some(FUNCHEAPTYPE),
+			HeapType::Extern => Some(EXTERNHEAPTYPE),
+			HeapType::Any => Some(ANYHEAPTYPE),
+			HeapType::Eq => Some(EQHEAPTYPE),
+			HeapType::I31 => Some(I31HEAPTYPE),
+			HeapType::Struct => Some(STRUCTHEAPTYPE),
+			HeapType::Array => Some(ARRAYHEAPTYPE),
+			HeapType::None => Some(NONEHEAPTYPE),
+			HeapType::NoFunc => Some(NOFUNCHEAPTYPE),
+			HeapType::NoExtern => Some(NOEXTERNHEAPTYPE),
+			HeapType::Index(_) => None,
 		}
 	}
 
-	fn read_rest(&mut self, reader: &mut impl io::Read) -> Result<(), Error> {
-		match self {
-			ValueType::Ref(r) => r.read_rest(reader),
-			_ => Ok(()),
+	/// Is this an abstract heap type (i.e. not a type index)?
+	pub fn is_abstract(&self) -> bool {
+		!matches!(*self, HeapType::Index(_))
+	}
+
+	/// Type index of a concrete heap type.
+	pub fn index(&self) -> Option<u32> {
+		match *self {
+			HeapType::Index(idx) => Some(idx),
+			_ => None,
 		}
 	}
+}
 
-	fn write_rest(&self, writer: &mut impl io::Write) -> Result<(), Error> {
-		match self {
-			ValueType::Ref(r) => r.write_rest(writer),
-			_ => Ok(()),
+/// Read a type code shared by block types and heap types.
+///
+/// Type indices are a non-negative signed 33-bit integer, negative values are
+/// single byte type codes and may not be padded.
+fn read_type_code<R: io::Read>(reader: &mut R) -> Result<i64, Error> {
+	let mut res = 0i64;
+	let mut shift = 0;
+	let mut u8buf = [0u8; 1];
+	loop {
+		reader.read(&mut u8buf)?;
+		let b = u8buf[0];
+		res |= ((b & 0x7f) as i64) << shift;
+		shift += 7;
+		if shift == 35 {
+			// The last byte carries bits 28..33; the unused bits must repeat the sign bit.
+			if b & 0x80 != 0 || (b & 0x70 != 0 && b & 0x70 != 0x70) {
+				return Err(Error::Other("Invalid signed 33-bit integer"));
+			}
+			res = (res << 31) >> 31;
+			break;
+		}
+		if b & 0x80 == 0 {
+			if b & 0x40 != 0 {
+				res |= -1i64 << shift;
+			}
+			break;
 		}
 	}
+	if res < 0 && shift > 7 {
+		return Err(Error::Other("Type code is not a single byte"));
+	}
+	Ok(res)
 }
 
-/// Reference type.
-#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
-pub enum RefType {
-	/// Infinite union of all references
-	AnyRef,
-	/// Infinite union of all references to functions
-	AnyFunc,
-	/// Reference to a specific definition
-	Ref(u32),
+impl Deserialize for HeapType {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		// Heap types are encoded as a signed 33-bit integer: negative values
+		// are abstract heap types, non-negative values are type indices.
+		let val = read_type_code(reader)?;
+		if val >= 0 {
+			if val > u32::MAX as i64 {
+				return Err(Error::UnknownHeapType(val));
+			}
+			return Ok(HeapType::Index(val as u32));
+		}
+		if val < -0x40 {
+			return Err(Error::UnknownHeapType(val));
+		}
+		HeapType::from_bits(val as i8).ok_or(Error::UnknownHeapType(val))
+	}
 }
 
-impl RefType {
-	fn from_bits(x: i8) -> Option<RefType> {
-		match x {
-			ANYFUNCTYPE => Some(RefType::AnyFunc),
-			ANYREFTYPE => Some(RefType::AnyRef),
-			REFTYPE => Some(RefType::Ref(0)),
-			_ => None,
+impl Serialize for HeapType {
+	type Error = Error;
+
+	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
+		match (self, self.to_bits()) {
+			(_, Some(bits)) => VarInt7::from(bits).serialize(writer),
+			(HeapType::Index(idx), None) => VarInt64::from(idx as i64).serialize(writer),
+			(_, None) => unreachable!("abstract heap types have a single byte encoding"),
 		}
 	}
+}
 
-	fn to_bits(self) -> i8 {
-		match self {
-			RefType::AnyFunc => ANYFUNCTYPE,
-			RefType::AnyRef => ANYREFTYPE,
-			RefType::Ref(_) => REFTYPE,
+impl fmt::Display for HeapType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			HeapType::Func => write!(f, "func"),
+			HeapType::Extern => write!(f, "extern"),
+			HeapType::Any => write!(f, "any"),
+			HeapType::Eq => write!(f, "eq"),
+			HeapType::I31 => write!(f, "i31"),
+			HeapType::Struct => write!(f, "struct"),
+			HeapType::Array => write!(f, "array"),
+			HeapType::None => write!(f, "none"),
+			HeapType::NoFunc => write!(f, "nofunc"),
+			HeapType::NoExtern => write!(f, "noextern"),
+			HeapType::Index(idx) => write!(f, "{}", idx),
 		}
 	}
+}
 
-	fn read_rest(&mut self, reader: &mut impl io::Read) -> Result<(), Error> {
-		match self {
-			RefType::Ref(i) => *i = VarUint32::deserialize(reader)?.into(),
-			_ => (),
-		};
-		Ok(())
+/// Reference type, a possibly nullable reference to a heap type.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+pub struct RefType {
+	nullable: bool,
+	heap_type: HeapType,
+}
+
+impl RefType {
+	/// `funcref`, i.e. `(ref null func)`
+	pub const FUNCREF: RefType = RefType { nullable: true, heap_type: HeapType::Func };
+	/// `externref`, i.e. `(ref null extern)`
+	pub const EXTERNREF: RefType = RefType { nullable: true, heap_type: HeapType::Extern };
+	/// `anyref`, i.e. `(ref null any)`
+	pub const ANYREF: RefType = RefType { nullable: true, heap_type: HeapType::Any };
+	/// `eqref`, i.e. `(ref null eq)`
+	pub const EQREF: RefType = RefType { nullable: true, heap_type: HeapType::Eq };
+	/// `i31ref`, i.e. `(ref null i31)`
+	pub const I31REF: RefType = RefType { nullable: true, heap_type: HeapType::I31 };
+	/// `structref`, i.e. `(ref null struct)`
+	pub const STRUCTREF: RefType = RefType { nullable: true, heap_type: HeapType::Struct };
+	/// `arrayref`, i.e. `(ref null array)`
+	pub const ARRAYREF: RefType = RefType { nullable: true, heap_type: HeapType::Array };
+	/// `nullref`, i.e. `(ref null none)`
+	pub const NULLREF: RefType = RefType { nullable: true, heap_type: HeapType::None };
+	/// `nullfuncref`, i.e. `(ref null nofunc)`
+	pub const NULLFUNCREF: RefType = RefType { nullable: true, heap_type: HeapType::NoFunc };
+	/// `nullexternref`, i.e. `(ref null noextern)`
+	pub const NULLEXTERNREF: RefType = RefType { nullable: true, heap_type: HeapType::NoExtern };
+
+	/// New reference type to the given heap type.
+	pub fn new(nullable: bool, heap_type: HeapType) -> Self {
+		RefType { nullable, heap_type }
 	}
+	/// Is `null` a member of this reference type.
+	pub fn is_nullable(&self) -> bool { self.nullable }
+	/// Heap type referenced by this type.
+	pub fn heap_type(&self) -> HeapType { self.heap_type }
 
-	fn write_rest(&self, writer: &mut impl io::Write) -> Result<(), Error> {
-		match self {
-			RefType::Ref(i) => VarUint32::from(*i).serialize(writer),
-			_ => Ok(()),
+	/// Read a reference type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		let val: i8 = VarInt7::deserialize(reader)?.into();
+		RefType::read_bits(val, reader, dialect)?.ok_or(Error::UnknownValueType(val))
+	}
+
+	/// Write a reference type encoded in the given dialect.
+	///
+	/// Only `funcref`, `externref` and `(ref null N)` can be written in the legacy dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		match (dialect, self.nullable, self.heap_type) {
+			(Dialect::Legacy, true, HeapType::Func) => VarInt7::from(FUNCHEAPTYPE).serialize(writer),
+			(Dialect::Legacy, true, HeapType::Extern) => VarInt7::from(LEGACY_ANYREFTYPE).serialize(writer),
+			(Dialect::Legacy, true, HeapType::Index(idx)) => {
+				VarInt7::from(LEGACY_REFTYPE).serialize(writer)?;
+				VarUint32::from(idx).serialize(writer)
+			},
+			(Dialect::Legacy, _, _) => Err(Error::Other("Reference type has no legacy encoding")),
+			(Dialect::Standard, nullable, heap_type) => match (nullable, heap_type.to_bits()) {
+				// Nullable abstract references use the shorthand encoding.
+				(true, Some(bits)) => VarInt7::from(bits).serialize(writer),
+				(nullable, _) => {
+					VarInt7::from(if nullable { REFNULLTYPE } else { REFTYPE }).serialize(writer)?;
+					heap_type.serialize(writer)
+				},
+			},
 		}
 	}
+
+	/// Decode the rest of a reference type given its leading byte.
+	///
+	/// Returns `None` if `bits` does not start a reference type.
+	fn read_bits<R: io::Read>(bits: i8, reader: &mut R, dialect: Dialect)
+		-> Result<Option<RefType>, Error>
+	{
+		Ok(match (dialect, bits) {
+			(Dialect::Standard, REFNULLTYPE) => Some(RefType::new(true, HeapType::deserialize(reader)?)),
+			(Dialect::Standard, REFTYPE) => Some(RefType::new(false, HeapType::deserialize(reader)?)),
+			(Dialect::Standard, _) => HeapType::from_bits(bits).map(|ht| RefType::new(true, ht)),
+			(Dialect::Legacy, FUNCHEAPTYPE) => Some(RefType::FUNCREF),
+			(Dialect::Legacy, LEGACY_ANYREFTYPE) => Some(RefType::EXTERNREF),
+			(Dialect::Legacy, LEGACY_REFTYPE) => {
+				let idx: u32 = VarUint32::deserialize(reader)?.into();
+				Some(RefType::new(true, HeapType::Index(idx)))
+			},
+			(Dialect::Legacy, _) => None,
+		})
+	}
 }
 
 impl Deserialize for RefType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let val = VarInt7::deserialize(reader)?;
-		let mut item = RefType::from_bits(val.into())
-			.ok_or(Error::UnknownValueType(val.into()))?;
-		item.read_rest(reader)?;
-		Ok(item)
+		RefType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -195,10 +553,28 @@ impl Serialize for RefType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		let val: VarInt7 = self.to_bits().into();
-		val.serialize(writer)?;
-		self.write_rest(writer)?;
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
+	}
+}
+
+impl fmt::Display for RefType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if !self.nullable {
+			return write!(f, "(ref {})", self.heap_type);
+		}
+		match self.heap_type {
+			HeapType::Func => write!(f, "funcref"),
+			HeapType::Extern => write!(f, "externref"),
+			HeapType::Any => write!(f, "anyref"),
+			HeapType::Eq => write!(f, "eqref"),
+			HeapType::I31 => write!(f, "i31ref"),
+			HeapType::Struct => write!(f, "structref"),
+			HeapType::Array => write!(f, "arrayref"),
+			HeapType::None => write!(f, "nullref"),
+			HeapType::NoFunc => write!(f, "nullfuncref"),
+			HeapType::NoExtern => write!(f, "nullexternref"),
+			HeapType::Index(idx) => write!(f, "(ref null {})", idx),
+		}
 	}
 }
 
@@ -240,11 +616,7 @@ impl Deserialize for ValueType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let val = VarInt7::deserialize(reader)?;
-		let mut item = ValueType::from_bits(val.into())
-			.ok_or(Error::UnknownValueType(val.into()))?;
-		item.read_rest(reader)?;
-		Ok(item)
+		ValueType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -252,10 +624,7 @@ impl Serialize for ValueType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		let val: VarInt7 = self.to_bits().into();
-		val.serialize(writer)?;
-		self.write_rest(writer)?;
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
@@ -266,9 +635,7 @@ impl fmt::Display for ValueType {
 			ValueType::Num(NumType::I64) => write!(f, "i64"),
 			ValueType::Num(NumType::F32) => write!(f, "f32"),
 			ValueType::Num(NumType::F64) => write!(f, "f64"),
-			ValueType::Ref(RefType::AnyRef) => write!(f, "anyref"),
-			ValueType::Ref(RefType::AnyFunc) => write!(f, "anyfunc"),
-			ValueType::Ref(RefType::Ref(idx)) => write!(f, "(ref {})", idx),
+			ValueType::Ref(r) => write!(f, "{}", r),
 			ValueType::V128 => write!(f, "v128"),
 		}
 	}
@@ -281,20 +648,62 @@ pub enum BlockType {
 	Value(ValueType),
 	/// No specified block type
 	NoResult,
+	/// Index of the function type with the block params and results
+	TypeIndex(u32),
+}
+
+impl BlockType {
+	/// Read a block type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		// Standard block types are a signed 33-bit integer: negative values are
+		// a single byte value type, non-negative values are type indices.
+		let val: i64 = match dialect {
+			Dialect::Standard => read_type_code(reader)?,
+			Dialect::Legacy => i8::from(VarInt7::deserialize(reader)?).into(),
+		};
+		if val >= 0 && dialect == Dialect::Standard {
+			if val > u32::MAX as i64 {
+				return Err(Error::Other("Block type index is out of range"));
+			}
+			return Ok(BlockType::TypeIndex(val as u32));
+		}
+		if val < -0x40 {
+			return Err(Error::Other("Invalid block type"));
+		}
+		let val = val as i8;
+		match (dialect, val) {
+			(_, NORESULTTYPE) => Ok(BlockType::NoResult),
+			// Legacy block types are a single byte without the type index.
+			(Dialect::Legacy, LEGACY_REFTYPE) => {
+				Ok(BlockType::Value(RefType::new(true, HeapType::Index(0)).into()))
+			},
+			_ => ValueType::read_bits(val, reader, dialect)?
+				.map(BlockType::Value)
+				.ok_or(Error::UnknownValueType(val)),
+		}
+	}
+
+	/// Write a block type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		match (dialect, self) {
+			(_, BlockType::NoResult) => VarInt7::from(NORESULTTYPE).serialize(writer),
+			(Dialect::Standard, BlockType::TypeIndex(idx)) => VarInt64::from(idx as i64).serialize(writer),
+			(Dialect::Legacy, BlockType::TypeIndex(_)) => Err(Error::Other("Block type has no legacy encoding")),
+			(Dialect::Legacy, BlockType::Value(ValueType::Ref(r))) => match r.heap_type() {
+				HeapType::Index(0) if r.is_nullable() => VarInt7::from(LEGACY_REFTYPE).serialize(writer),
+				HeapType::Index(_) => Err(Error::Other("Block type has no legacy encoding")),
+				_ => r.serialize_dialect(writer, dialect),
+			},
+			(_, BlockType::Value(v)) => v.serialize_dialect(writer, dialect),
+		}
+	}
 }
 
 impl Deserialize for BlockType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let val = VarInt7::deserialize(reader)?;
-		let bits = val.into();
-		match bits {
-			NORESULTTYPE => Some(BlockType::NoResult),
-			_ => None,
-		}
-		.or(ValueType::from_bits(bits).map(BlockType::Value))
-		.ok_or(Error::UnknownValueType(val.into()))
+		BlockType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -302,12 +711,7 @@ impl Serialize for BlockType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		let val: VarInt7 = match self {
-			BlockType::NoResult => NORESULTTYPE,
-			BlockType::Value(v) => v.to_bits(),
-		}.into();
-		val.serialize(writer)?;
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
@@ -315,24 +719,24 @@ impl Serialize for BlockType {
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct FunctionType {
 	params: Vec<ValueType>,
-	return_type: Option<ValueType>,
+	results: Vec<ValueType>,
 }
 
 impl Default for FunctionType {
 	fn default() -> Self {
 		FunctionType {
 			params: Vec::new(),
-			return_type: None,
+			results: Vec::new(),
 		}
 	}
 }
 
 impl FunctionType {
-	/// New function type given the signature in-params(`params`) and return type (`return_type`)
-	pub fn new(params: Vec<ValueType>, return_type: Option<ValueType>) -> Self {
+	/// New function type given the signature in-params(`params`) and results (`results`)
+	pub fn new(params: Vec<ValueType>, results: Vec<ValueType>) -> Self {
 		FunctionType {
 			params: params,
-			return_type: return_type,
+			results: results,
 			..Default::default()
 		}
 	}
@@ -340,95 +744,135 @@ impl FunctionType {
 	pub fn params(&self) -> &[ValueType] { &self.params }
 	/// Mutable parameters in the function signature.
 	pub fn params_mut(&mut self) -> &mut Vec<ValueType> { &mut self.params }
-	/// Return type in the function signature, if any.
-	pub fn return_type(&self) -> Option<ValueType> { self.return_type }
-	/// Mutable type in the function signature, if any.
-	pub fn return_type_mut(&mut self) -> &mut Option<ValueType> { &mut self.return_type }
+	/// Results in the function signature.
+	pub fn results(&self) -> &[ValueType] { &self.results }
+	/// Mutable results in the function signature.
+	pub fn results_mut(&mut self) -> &mut Vec<ValueType> { &mut self.results }
 }
 
-impl Deserialize for FunctionType {
-	type Error = Error;
-
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let params: Vec<ValueType> = CountedList::deserialize(reader)?.into_inner();
-		let return_types: u32 = VarUint32::deserialize(reader)?.into();
-
-		let return_type = if return_types == 1 {
-			Some(ValueType::deserialize(reader)?)
-		} else if return_types == 0 {
-			None
-		} else {
-			return Err(Error::Other("Return types length should be 0 or 1"));
-		};
+impl FunctionType {
+	/// Read a function type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		let params = read_list(reader, |r| ValueType::deserialize_dialect(r, dialect))?;
+		let results = read_list(reader, |r| ValueType::deserialize_dialect(r, dialect))?;
 
 		Ok(FunctionType {
 			params: params,
-			return_type: return_type,
+			results: results,
 		})
 	}
+
+	/// Write a function type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		write_list(writer, self.params, |v, w| v.serialize_dialect(w, dialect))?;
+		write_list(writer, self.results, |v, w| v.serialize_dialect(w, dialect))
+	}
+}
+
+impl Deserialize for FunctionType {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		FunctionType::deserialize_dialect(reader, Dialect::Standard)
+	}
 }
 
 impl Serialize for FunctionType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		let data = self.params;
-		let counted_list = CountedListWriter::<ValueType, _>(
-			data.len(),
-			data.into_iter().map(Into::into),
-		);
-		counted_list.serialize(writer)?;
-
-		if let Some(return_type) = self.return_type {
-			VarUint1::from(true).serialize(writer)?;
-			return_type.serialize(writer)?;
-		} else {
-			VarUint1::from(false).serialize(writer)?;
-		}
-
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
-/// Structure type.
+/// Storage type of a struct field or array element.
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub enum StorageType {
+	/// Unpacked value
 	Value(ValueType),
+	/// Packed 8-bit integer
 	PackedI8,
+	/// Packed 16-bit integer
 	PackedI16,
 }
 
-impl Deserialize for StorageType {
-	type Error = Error;
+impl StorageType {
+	/// Is this a packed integer storage type?
+	pub fn is_packed(&self) -> bool {
+		match *self {
+			StorageType::PackedI8 | StorageType::PackedI16 => true,
+			StorageType::Value(_) => false,
+		}
+	}
 
-	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+	/// Value type used on the operand stack for this storage type.
+	///
+	/// Packed integers are unpacked to `i32`.
+	pub fn unpacked(&self) -> ValueType {
+		match *self {
+			StorageType::Value(v) => v,
+			StorageType::PackedI8 | StorageType::PackedI16 => NumType::I32.into(),
+		}
+	}
+
+	/// Read a storage type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		let (packed_i8, packed_i16) = StorageType::packed_bits(dialect);
 		let val: i8 = VarInt7::deserialize(reader)?.into();
 		match val {
-			PACKEDI8TYPE => Some(StorageType::PackedI8),
-			PACKEDI16TYPE => Some(StorageType::PackedI16),
-			_ => match ValueType::from_bits(val) {
-				Some(mut item) => {
-					item.read_rest(reader)?;
-					Some(StorageType::Value(item))
-				},
-				None => None,
-			},
+			_ if val == packed_i8 => Some(StorageType::PackedI8),
+			_ if val == packed_i16 => Some(StorageType::PackedI16),
+			_ => ValueType::read_bits(val, reader, dialect)?.map(StorageType::Value),
 		}.ok_or(Error::UnknownValueType(val))
 	}
+
+	/// Write a storage type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		let (packed_i8, packed_i16) = StorageType::packed_bits(dialect);
+		match self {
+			StorageType::PackedI8 => VarInt7::from(packed_i8).serialize(writer),
+			StorageType::PackedI16 => VarInt7::from(packed_i16).serialize(writer),
+			StorageType::Value(val) => val.serialize_dialect(writer, dialect),
+		}
+	}
+
+	fn packed_bits(dialect: Dialect) -> (i8, i8) {
+		match dialect {
+			Dialect::Standard => (PACKEDI8TYPE, PACKEDI16TYPE),
+			Dialect::Legacy => (LEGACY_PACKEDI8TYPE, LEGACY_PACKEDI16TYPE),
+		}
+	}
+}
+
+impl From<ValueType> for StorageType {
+	fn from(v: ValueType) -> StorageType {
+		StorageType::Value(v)
+	}
+}
+
+impl fmt::Display for StorageType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			StorageType::Value(v) => write!(f, "{}", v),
+			StorageType::PackedI8 => write!(f, "i8"),
+			StorageType::PackedI16 => write!(f, "i16"),
+		}
+	}
+}
+
+impl Deserialize for StorageType {
+	type Error = Error;
+
+	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
+		StorageType::deserialize_dialect(reader, Dialect::Standard)
+	}
 }
 
 impl Serialize for StorageType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		match self {
-			StorageType::PackedI8 => VarInt7::from(PACKEDI8TYPE).serialize(writer),
-			StorageType::PackedI16 => VarInt7::from(PACKEDI16TYPE).serialize(writer),
-			StorageType::Value(val) => {
-				VarInt7::from(val.to_bits()).serialize(writer)?;
-				val.write_rest(writer)
-			},
-		}
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
@@ -439,15 +883,75 @@ pub struct FieldType {
 	mutable: bool,
 }
 
+impl FieldType {
+	/// New field type of the given storage type and mutability.
+	pub fn new(elem: impl Into<StorageType>, mutable: bool) -> Self {
+		FieldType { elem: elem.into(), mutable }
+	}
+	/// Storage type of the field.
+	pub fn storage_type(&self) -> StorageType { self.elem }
+	/// Storage type of the field (mutable).
+	pub fn storage_type_mut(&mut self) -> &mut StorageType { &mut self.elem }
+	/// Is field declared as mutable.
+	pub fn is_mutable(&self) -> bool { self.mutable }
+	/// Is field declared as mutable (mutable).
+	pub fn mutable_mut(&mut self) -> &mut bool { &mut self.mutable }
+}
+
+impl fmt::Display for FieldType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.mutable {
+			write!(f, "(mut {})", self.elem)
+		} else {
+			write!(f, "{}", self.elem)
+		}
+	}
+}
+
+impl FieldType {
+	/// Read a field type encoded in the given dialect.
+	///
+	/// The legacy dialect puts the mutability flag before the storage type.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		match dialect {
+			Dialect::Standard => {
+				let elem = StorageType::deserialize_dialect(reader, dialect)?;
+				Ok(FieldType {
+					elem,
+					mutable: VarUint1::deserialize(reader)?.into(),
+				})
+			},
+			Dialect::Legacy => {
+				let mutable = VarUint1::deserialize(reader)?.into();
+				Ok(FieldType {
+					elem: StorageType::deserialize_dialect(reader, dialect)?,
+					mutable,
+				})
+			},
+		}
+	}
+
+	/// Write a field type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		match dialect {
+			Dialect::Standard => {
+				self.elem.serialize_dialect(writer, dialect)?;
+				VarUint1::from(self.mutable).serialize(writer)?;
+			},
+			Dialect::Legacy => {
+				VarUint1::from(self.mutable).serialize(writer)?;
+				self.elem.serialize_dialect(writer, dialect)?;
+			},
+		}
+		Ok(())
+	}
+}
+
 impl Deserialize for FieldType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let mutable = VarUint1::deserialize(reader)?.into();
-		Ok(FieldType {
-			elem: StorageType::deserialize(reader)?,
-			mutable,
-		})
+		FieldType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -455,26 +959,56 @@ impl Serialize for FieldType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		VarUint1::from(self.mutable).serialize(writer)?;
-		self.elem.serialize(writer)?;
-		Ok(())
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
 /// Structure type.
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Hash, Eq)]
 pub struct StructType {
 	fields: Vec<FieldType>,
 }
 
+impl StructType {
+	/// New structure type with the given fields.
+	pub fn new(fields: Vec<FieldType>) -> Self {
+		StructType { fields }
+	}
+	/// Fields of the structure.
+	pub fn fields(&self) -> &[FieldType] { &self.fields }
+	/// Fields of the structure (mutable).
+	pub fn fields_mut(&mut self) -> &mut Vec<FieldType> { &mut self.fields }
+}
+
+impl fmt::Display for StructType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "(struct")?;
+		for field in &self.fields {
+			write!(f, " (field {})", field)?;
+		}
+		write!(f, ")")
+	}
+}
+
+impl StructType {
+	/// Read a structure type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		Ok(StructType {
+			fields: read_list(reader, |r| FieldType::deserialize_dialect(r, dialect))?,
+		})
+	}
+
+	/// Write a structure type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		write_list(writer, self.fields, |field, w| field.serialize_dialect(w, dialect))
+	}
+}
+
 impl Deserialize for StructType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		let fields: Vec<FieldType> = CountedList::deserialize(reader)?.into_inner();
-		Ok(StructType {
-			fields,
-		})
+		StructType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -482,10 +1016,7 @@ impl Serialize for StructType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		CountedListWriter::<FieldType, _>(
-			self.fields.len(),
-			self.fields.into_iter(),
-		).serialize(writer)
+		self.serialize_dialect(writer, Dialect::Standard)
 	}
 }
 
@@ -495,13 +1026,42 @@ pub struct ArrayType {
 	elem: FieldType,
 }
 
+impl ArrayType {
+	/// New array type with the given element type.
+	pub fn new(elem: FieldType) -> Self {
+		ArrayType { elem }
+	}
+	/// Element type of the array.
+	pub fn elem(&self) -> &FieldType { &self.elem }
+	/// Element type of the array (mutable).
+	pub fn elem_mut(&mut self) -> &mut FieldType { &mut self.elem }
+}
+
+impl fmt::Display for ArrayType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "(array {})", self.elem)
+	}
+}
+
+impl ArrayType {
+	/// Read an array type encoded in the given dialect.
+	pub fn deserialize_dialect<R: io::Read>(reader: &mut R, dialect: Dialect) -> Result<Self, Error> {
+		Ok(ArrayType {
+			elem: FieldType::deserialize_dialect(reader, dialect)?,
+		})
+	}
+
+	/// Write an array type encoded in the given dialect.
+	pub fn serialize_dialect<W: io::Write>(self, writer: &mut W, dialect: Dialect) -> Result<(), Error> {
+		self.elem.serialize_dialect(writer, dialect)
+	}
+}
+
 impl Deserialize for ArrayType {
 	type Error = Error;
 
 	fn deserialize<R: io::Read>(reader: &mut R) -> Result<Self, Self::Error> {
-		Ok(ArrayType {
-			elem: FieldType::deserialize(reader)?,
-		})
+		ArrayType::deserialize_dialect(reader, Dialect::Standard)
 	}
 }
 
@@ -509,6 +1069,172 @@ impl Serialize for ArrayType {
 	type Error = Error;
 
 	fn serialize<W: io::Write>(self, writer: &mut W) -> Result<(), Self::Error> {
-		self.elem.serialize(writer)
+		self.serialize_dialect(writer, Dialect::Standard)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{deserialize_buffer, serialize};
+	use super::{
+		Type, StructType, ArrayType, FieldType, StorageType, NumType, RefType, HeapType, ValueType,
+		BlockType, Dialect, FunctionType,
+	};
+
+	#[test]
+	fn struct_type_display() {
+		let ty = StructType::new(vec![
+			FieldType::new(ValueType::from(NumType::I32), false),
+			FieldType::new(StorageType::PackedI8, true),
+			FieldType::new(ValueType::from(RefType::ANYREF), true),
+		]);
+		assert_eq!(format!("{}", ty), "(struct (field i32) (field (mut i8)) (field (mut anyref)))");
+		assert_eq!(format!("{}", StructType::default()), "(struct)");
+
+		let ty = ArrayType::new(FieldType::new(StorageType::PackedI16, true));
+		assert_eq!(format!("{}", ty), "(array (mut i16))");
+	}
+
+	#[test]
+	fn struct_type_roundtrip() {
+		let ty: Type = StructType::new(vec![
+			FieldType::new(ValueType::from(NumType::F64), true),
+			FieldType::new(StorageType::PackedI16, false),
+		]).into();
+		let buf = serialize(ty.clone()).expect("struct type to serialize");
+		let decoded: Type = deserialize_buffer(&buf).expect("struct type to deserialize");
+		assert_eq!(decoded, ty);
+
+		match decoded {
+			Type::Struct(ref s) => {
+				assert_eq!(s.fields().len(), 2);
+				assert!(s.fields()[0].is_mutable());
+				assert_eq!(s.fields()[1].storage_type().unpacked(), NumType::I32.into());
+			},
+			_ => panic!("Should be deserialized as struct type"),
+		}
+	}
+
+	#[test]
+	fn ref_type_display() {
+		assert_eq!(format!("{}", RefType::FUNCREF), "funcref");
+		assert_eq!(format!("{}", RefType::NULLEXTERNREF), "nullexternref");
+		assert_eq!(format!("{}", RefType::new(true, HeapType::Index(3))), "(ref null 3)");
+		assert_eq!(format!("{}", RefType::new(false, HeapType::Index(3))), "(ref 3)");
+		assert_eq!(format!("{}", RefType::new(false, HeapType::I31)), "(ref i31)");
+	}
+
+	#[test]
+	fn ref_type_encoding() {
+		let cases: &[(&[u8], RefType)] = &[
+			(&[0x70], RefType::FUNCREF),
+			(&[0x6f], RefType::EXTERNREF),
+			(&[0x6e], RefType::ANYREF),
+			(&[0x71], RefType::NULLREF),
+			(&[0x64, 0x6a], RefType::new(false, HeapType::Array)),
+			(&[0x63, 0x05], RefType::new(true, HeapType::Index(5))),
+			(&[0x64, 0x80, 0x01], RefType::new(false, HeapType::Index(128))),
+		];
+		for &(bytes, ty) in cases {
+			let decoded: RefType = deserialize_buffer(bytes).expect("ref type to deserialize");
+			assert_eq!(decoded, ty);
+			assert_eq!(serialize(ty).expect("ref type to serialize"), bytes);
+		}
+
+		// The long form of an abstract nullable reference decodes to the same type.
+		let decoded: RefType = deserialize_buffer(&[0x63, 0x70]).expect("ref type to deserialize");
+		assert_eq!(decoded, RefType::FUNCREF);
+
+		assert!(deserialize_buffer::<RefType>(&[0x63, 0x60]).is_err());
+	}
+
+	#[test]
+	fn block_type_ref() {
+		let ty = BlockType::Value(RefType::new(false, HeapType::Index(2)).into());
+		let buf = serialize(ty).expect("block type to serialize");
+		assert_eq!(buf, vec![0x64, 0x02]);
+		let decoded: BlockType = deserialize_buffer(&buf).expect("block type to deserialize");
+		assert_eq!(decoded, ty);
+	}
+
+	#[test]
+	fn multi_value() {
+		let i32 = ValueType::from(NumType::I32);
+		let ty: Type = FunctionType::new(vec![i32], vec![i32, NumType::I64.into()]).into();
+		let buf = serialize(ty.clone()).expect("function type to serialize");
+		assert_eq!(buf, vec![0x60, 0x01, 0x7f, 0x02, 0x7f, 0x7e]);
+		let decoded: Type = deserialize_buffer(&buf).expect("function type to deserialize");
+		assert_eq!(decoded, ty);
+
+		let cases: &[(&[u8], BlockType)] = &[
+			(&[0x40], BlockType::NoResult),
+			(&[0x7f], BlockType::Value(i32)),
+			(&[0x03], BlockType::TypeIndex(3)),
+			(&[0xc8, 0x01], BlockType::TypeIndex(200)),
+		];
+		for &(bytes, ty) in cases {
+			let decoded: BlockType = deserialize_buffer(bytes).expect("block type to deserialize");
+			assert_eq!(decoded, ty);
+			assert_eq!(serialize(ty).expect("block type to serialize"), bytes);
+		}
+
+		let mut buf = Vec::new();
+		assert!(BlockType::TypeIndex(3).serialize_dialect(&mut buf, Dialect::Legacy).is_err());
+		assert!(BlockType::deserialize_dialect(&mut &[0x03][..], Dialect::Legacy).is_err());
+	}
+
+	#[test]
+	fn s33_encoding() {
+		let cases: &[(&[u8], BlockType)] = &[
+			(&[0x83, 0x80, 0x80, 0x80, 0x00], BlockType::TypeIndex(3)),
+			(&[0xff, 0xff, 0xff, 0xff, 0x0f], BlockType::TypeIndex(u32::MAX)),
+		];
+		for &(bytes, ty) in cases {
+			let decoded: BlockType = deserialize_buffer(bytes).expect("block type to deserialize");
+			assert_eq!(decoded, ty);
+		}
+		// Longer than five bytes.
+		assert!(deserialize_buffer::<BlockType>(&[0x83, 0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+		// Unused bits of the last byte don't match the sign bit.
+		assert!(deserialize_buffer::<BlockType>(&[0x83, 0x80, 0x80, 0x80, 0x20]).is_err());
+		assert!(deserialize_buffer::<BlockType>(&[0xff, 0xff, 0xff, 0xff, 0x4f]).is_err());
+		// Value types are a single byte.
+		assert!(deserialize_buffer::<BlockType>(&[0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+		assert!(deserialize_buffer::<BlockType>(&[0xff, 0x7f]).is_err());
+
+		// Abstract heap types are a single byte.
+		assert!(deserialize_buffer::<HeapType>(&[0xf0, 0xff, 0xff, 0xff, 0x7f]).is_err());
+		assert!(deserialize_buffer::<HeapType>(&[0xf0, 0x7f]).is_err());
+		assert_eq!(deserialize_buffer::<HeapType>(&[0x70]).expect("heap type to deserialize"), HeapType::Func);
+		assert!(deserialize_buffer::<HeapType>(&[0x87, 0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+		assert!(deserialize_buffer::<HeapType>(&[0x87, 0x80, 0x80, 0x80, 0x40]).is_err());
+	}
+
+	#[test]
+	fn field_type_dialects() {
+		let field = FieldType::new(StorageType::PackedI16, true);
+		let mut legacy = Vec::new();
+		field.clone().serialize_dialect(&mut legacy, Dialect::Legacy).expect("field to serialize");
+		assert_eq!(legacy, vec![0x01, 0x67]);
+		assert_eq!(serialize(field.clone()).expect("field to serialize"), vec![0x77, 0x01]);
+
+		let decoded = FieldType::deserialize_dialect(&mut &legacy[..], Dialect::Legacy)
+			.expect("legacy field to deserialize");
+		assert_eq!(decoded, field);
+	}
+
+	#[test]
+	fn ref_type_legacy() {
+		let ty = RefType::new(true, HeapType::Index(7));
+		let mut buf = Vec::new();
+		ty.serialize_dialect(&mut buf, Dialect::Legacy).expect("ref type to serialize");
+		assert_eq!(buf, vec![0x6e, 0x07]);
+		assert_eq!(RefType::deserialize_dialect(&mut &buf[..], Dialect::Legacy).unwrap(), ty);
+
+		let legacy_anyref = RefType::deserialize_dialect(&mut &[0x6f][..], Dialect::Legacy).unwrap();
+		assert_eq!(legacy_anyref, RefType::EXTERNREF);
+
+		let mut buf = Vec::new();
+		assert!(RefType::new(false, HeapType::Index(7)).serialize_dialect(&mut buf, Dialect::Legacy).is_err());
 	}
 }
@@ -0,0 +1,149 @@
+use crate::rust::{vec::Vec, boxed::Box};
+use super::{ValidatedModule, ValidationError};
+use super::super::{InitExpr, Instruction, ValueType, NumType, HeapType, RefType};
+
+/// Value of an initializer expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+	/// 32-bit integer.
+	I32(i32),
+	/// 64-bit integer.
+	I64(i64),
+	/// 32-bit float, as its bit pattern.
+	F32(u32),
+	/// 64-bit float, as its bit pattern.
+	F64(u64),
+	/// 128-bit vector.
+	V128([u8; 16]),
+	/// Null reference of the given heap type.
+	Null(HeapType),
+	/// Reference to the function with the given index.
+	Func(u32),
+	/// Unboxed 31-bit integer reference, the top bit is always clear.
+	I31(u32),
+	/// Internal reference converted to an external one.
+	Extern(Box<ConstValue>),
+}
+
+impl<'a> ValidatedModule<'a> {
+	/// Evaluate an initializer expression that passed [`check_init_expr`](Self::check_init_expr).
+	///
+	/// `imports` are the values of the imported globals, in import order.
+	/// Expressions that allocate struct or array objects have no constant value.
+	pub fn eval_init_expr(&self, expr: &InitExpr, imports: &[ConstValue]) -> Result<ConstValue, ValidationError> {
+		let mut stack = Vec::new();
+		for instruction in expr.code() {
+			let value = match *instruction {
+				Instruction::End => break,
+				Instruction::I32Const(v) => ConstValue::I32(v),
+				Instruction::I64Const(v) => ConstValue::I64(v),
+				Instruction::F32Const(v) => ConstValue::F32(v),
+				Instruction::F64Const(v) => ConstValue::F64(v),
+				Instruction::V128Const(ref v) => ConstValue::V128(**v),
+				Instruction::RefNull(ht) => ConstValue::Null(ht),
+				Instruction::RefFunc(func) => ConstValue::Func(func),
+				Instruction::GetGlobal(idx) => {
+					imports.get(idx as usize).cloned().ok_or(ValidationError::UnknownGlobal(idx))?
+				},
+				Instruction::RefI31 => match stack.pop() {
+					Some(ConstValue::I32(v)) => ConstValue::I31(v as u32 & 0x7fff_ffff),
+					_ => return Err(ValidationError::StackUnderflow),
+				},
+				Instruction::AnyConvertExtern => match stack.pop() {
+					Some(ConstValue::Null(_)) => ConstValue::Null(HeapType::Any),
+					Some(ConstValue::Extern(value)) => *value,
+					Some(value) => value,
+					None => return Err(ValidationError::StackUnderflow),
+				},
+				Instruction::ExternConvertAny => match stack.pop() {
+					Some(ConstValue::Null(_)) => ConstValue::Null(HeapType::Extern),
+					Some(value) => ConstValue::Extern(Box::new(value)),
+					None => return Err(ValidationError::StackUnderflow),
+				},
+				Instruction::StructNew(_) | Instruction::StructNewDefault(_) | Instruction::ArrayNew(_) |
+				Instruction::ArrayNewDefault(_) | Instruction::ArrayNewFixed(..) => {
+					return Err(ValidationError::HeapAllocation);
+				},
+				_ => return Err(ValidationError::ConstantExpressionRequired),
+			};
+			stack.push(value);
+		}
+
+		let value = stack.pop().ok_or(ValidationError::StackUnderflow)?;
+		if !stack.is_empty() {
+			return Err(ValidationError::UnusedValues(stack.len()));
+		}
+		Ok(value)
+	}
+
+	/// Values of all globals, given the values of the imported globals.
+	pub fn global_values(&self, imports: &[ConstValue]) -> Result<Vec<ConstValue>, ValidationError> {
+		let imported = self.imported_globals as usize;
+		if imports.len() != imported {
+			return Err(ValidationError::ImportedGlobalValue(imports.len().min(imported) as u32));
+		}
+		for (idx, (value, global)) in imports.iter().zip(&self.globals).enumerate() {
+			if !self.fits(value, global.content_type()) {
+				return Err(ValidationError::ImportedGlobalValue(idx as u32));
+			}
+		}
+
+		let mut values = imports.to_vec();
+		for entry in self.module.global_section().map(|s| s.entries()).unwrap_or(&[]) {
+			values.push(self.eval_init_expr(entry.init_expr(), imports)?);
+		}
+		Ok(values)
+	}
+
+	/// Offsets of the data segments, `None` for passive segments.
+	///
+	/// Offsets into 32-bit memories are zero-extended.
+	pub fn data_offsets(&self, imports: &[ConstValue]) -> Result<Vec<Option<u64>>, ValidationError> {
+		self.module.data_section().map(|s| s.entries()).unwrap_or(&[]).iter()
+			.map(|segment| match *segment.offset() {
+				Some(ref expr) => {
+					let index_type = self.memory(segment.index())?.index_type();
+					self.offset(expr, imports, index_type).map(Some)
+				},
+				None => Ok(None),
+			})
+			.collect()
+	}
+
+	/// Offsets of the element segments, `None` for passive and declarative segments.
+	pub fn element_offsets(&self, imports: &[ConstValue]) -> Result<Vec<Option<u32>>, ValidationError> {
+		self.module.elements_section().map(|s| s.entries()).unwrap_or(&[]).iter()
+			.map(|segment| match *segment.offset() {
+				Some(ref expr) => self.offset(expr, imports, NumType::I32).map(|offset| Some(offset as u32)),
+				None => Ok(None),
+			})
+			.collect()
+	}
+
+	/// Can the value be stored in a global or local of the given type.
+	fn fits(&self, value: &ConstValue, ty: ValueType) -> bool {
+		let holds = |heap_type, r| self.types.is_subtype(&RefType::new(false, heap_type), &r);
+		match (value, ty) {
+			(ConstValue::I32(_), ValueType::Num(NumType::I32)) => true,
+			(ConstValue::I64(_), ValueType::Num(NumType::I64)) => true,
+			(ConstValue::F32(_), ValueType::Num(NumType::F32)) => true,
+			(ConstValue::F64(_), ValueType::Num(NumType::F64)) => true,
+			(ConstValue::V128(_), ValueType::V128) => true,
+			(ConstValue::Null(_), ValueType::Ref(r)) => r.is_nullable(),
+			(ConstValue::Func(func), ValueType::Ref(r)) => {
+				self.func_type_ref(*func).map(|idx| holds(HeapType::Index(idx), r)).unwrap_or(false)
+			},
+			(ConstValue::I31(_), ValueType::Ref(r)) => holds(HeapType::I31, r),
+			(ConstValue::Extern(_), ValueType::Ref(r)) => holds(HeapType::Extern, r),
+			_ => false,
+		}
+	}
+
+	fn offset(&self, expr: &InitExpr, imports: &[ConstValue], index_type: NumType) -> Result<u64, ValidationError> {
+		match (self.eval_init_expr(expr, imports)?, index_type) {
+			(ConstValue::I32(offset), NumType::I32) => Ok(u64::from(offset as u32)),
+			(ConstValue::I64(offset), NumType::I64) => Ok(offset as u64),
+			_ => Err(ValidationError::TypeMismatch { expected: index_type.into(), actual: None }),
+		}
+	}
+}
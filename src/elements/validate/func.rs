@@ -0,0 +1,1112 @@
+//! Operand stack type checking of function bodies.
+
+use crate::rust::{vec::Vec, boxed::Box, string::ToString, collections::BTreeSet};
+use super::super::{
+	Error, ValueType, RefType, HeapType, NumType, BlockType, FunctionType, StorageType, FieldType,
+	Instruction, FuncBody, Local, Type, MemArg,
+};
+use super::{ValidatedModule, ValidationError};
+
+const I32: ValueType = ValueType::Num(NumType::I32);
+const I64: ValueType = ValueType::Num(NumType::I64);
+const F32: ValueType = ValueType::Num(NumType::F32);
+const F64: ValueType = ValueType::Num(NumType::F64);
+const V128: ValueType = ValueType::V128;
+
+/// Kind of a control frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameKind {
+	Block,
+	Loop,
+	If,
+	Else,
+}
+
+/// Control frame of a block, loop, if or the function body itself.
+#[derive(Debug, Clone)]
+struct Frame {
+	kind: FrameKind,
+	params: Vec<ValueType>,
+	results: Vec<ValueType>,
+	/// Height of the operand stack when the frame was entered.
+	height: usize,
+	/// Number of locals initialized before the frame was entered.
+	inits: usize,
+	/// Rest of the frame is unreachable and its operand stack is polymorphic.
+	unreachable: bool,
+}
+
+impl Frame {
+	/// Types of the values a branch to this frame takes.
+	fn label_types(&self) -> &[ValueType] {
+		match self.kind {
+			FrameKind::Loop => &self.params,
+			_ => &self.results,
+		}
+	}
+}
+
+/// Operand stack type checker of a single function body.
+///
+/// Instructions are fed one by one with [`step`](FuncValidator::step) and
+/// [`finish`](FuncValidator::finish) checks that the body was terminated.
+/// Values on the operand stack of unreachable code are tracked as `None`.
+#[derive(Debug)]
+pub struct FuncValidator<'a> {
+	module: &'a ValidatedModule<'a>,
+	/// Locals, including parameters, with the index past the last local of each run.
+	locals: Vec<(u32, ValueType)>,
+	params: u32,
+	/// Non-defaultable locals that were set, in the order they were set.
+	inits: Vec<u32>,
+	initialized: BTreeSet<u32>,
+	stack: Vec<Option<ValueType>>,
+	frames: Vec<Frame>,
+	results: Vec<ValueType>,
+}
+
+impl<'a> FuncValidator<'a> {
+	/// New checker of a body of the given function type with the given local declarations.
+	pub fn new(module: &'a ValidatedModule<'a>, func_type: &FunctionType, locals: &[Local])
+		-> Result<Self, ValidationError>
+	{
+		let mut runs = Vec::with_capacity(func_type.params().len() + locals.len());
+		let mut count = 0u32;
+		for &param in func_type.params() {
+			count += 1;
+			runs.push((count, param));
+		}
+		let params = count;
+		for local in locals {
+			module.check_value_type(local.value_type())?;
+			count = count.checked_add(local.count()).ok_or(ValidationError::TooManyLocals)?;
+			runs.push((count, local.value_type()));
+		}
+
+		let results = func_type.results().to_vec();
+		let mut validator = FuncValidator {
+			module,
+			locals: runs,
+			params,
+			inits: Vec::new(),
+			initialized: BTreeSet::new(),
+			stack: Vec::new(),
+			frames: Vec::new(),
+			results: results.clone(),
+		};
+		validator.push_frame(FrameKind::Block, Vec::new(), results);
+		Ok(validator)
+	}
+
+	/// Check the next instruction of the body.
+	pub fn step(&mut self, instruction: &Instruction) -> Result<(), ValidationError> {
+		use self::Instruction::*;
+
+		if self.frames.is_empty() {
+			return Err(ValidationError::UnbalancedControl);
+		}
+
+		match *instruction {
+			Unreachable => self.unreachable(),
+			Nop => {},
+			Block(ty) => {
+				let (params, results) = self.block_type(ty)?;
+				self.pop_values(&params)?;
+				self.push_frame(FrameKind::Block, params, results);
+			},
+			Loop(ty) => {
+				let (params, results) = self.block_type(ty)?;
+				self.pop_values(&params)?;
+				self.push_frame(FrameKind::Loop, params, results);
+			},
+			If(ty) => {
+				let (params, results) = self.block_type(ty)?;
+				self.pop(I32)?;
+				self.pop_values(&params)?;
+				self.push_frame(FrameKind::If, params, results);
+			},
+			Else => {
+				let frame = self.pop_frame()?;
+				if frame.kind != FrameKind::If {
+					return Err(ValidationError::UnbalancedControl);
+				}
+				self.push_frame(FrameKind::Else, frame.params, frame.results);
+			},
+			End => {
+				let mut frame = self.pop_frame()?;
+				if frame.kind == FrameKind::If {
+					// `if` without `else` behaves as if the `else` branch was empty.
+					self.push_frame(FrameKind::Else, frame.params, frame.results);
+					frame = self.pop_frame()?;
+				}
+				if !self.frames.is_empty() {
+					self.push_values(&frame.results);
+				}
+			},
+			Br(depth) => {
+				let types = self.label_types(depth)?;
+				self.pop_values(&types)?;
+				self.unreachable();
+			},
+			BrIf(depth) => {
+				let types = self.label_types(depth)?;
+				self.pop(I32)?;
+				self.pop_values(&types)?;
+				self.push_values(&types);
+			},
+			BrTable(ref table) => {
+				self.pop(I32)?;
+				let default = self.label_types(table.default)?;
+				for &depth in table.table.iter() {
+					let types = self.label_types(depth)?;
+					if types.len() != default.len() {
+						return Err(ValidationError::LabelTypeMismatch(depth));
+					}
+					let values = self.pop_values(&types)?;
+					self.stack.extend(values);
+				}
+				self.pop_values(&default)?;
+				self.unreachable();
+			},
+			Return => {
+				let results = self.results.clone();
+				self.pop_values(&results)?;
+				self.unreachable();
+			},
+
+			Call(func) => {
+				let func_type = self.module.func_type(func)?;
+				self.call(func_type)?;
+			},
+			CallIndirect(ty, table) => {
+				let elem_type = self.module.table(table)?.elem_type();
+				self.check_subtype(elem_type.into(), RefType::FUNCREF.into())?;
+				let func_type = self.module.function_type(ty)?;
+				self.pop(I32)?;
+				self.call(func_type)?;
+			},
+			CallRef(ty) => {
+				let func_type = self.module.function_type(ty)?;
+				self.pop(RefType::new(true, HeapType::Index(ty)).into())?;
+				self.call(func_type)?;
+			},
+			ReturnCallRef(ty) => {
+				let func_type = self.module.function_type(ty)?;
+				self.pop(RefType::new(true, HeapType::Index(ty)).into())?;
+				self.pop_values(func_type.params())?;
+				let results = func_type.results();
+				// Results of the callee are returned from the caller.
+				for (idx, &expected) in self.results.iter().enumerate() {
+					match results.get(idx) {
+						Some(&actual) => self.check_subtype(actual, expected)?,
+						None => return Err(ValidationError::TypeMismatch { expected, actual: None }),
+					}
+				}
+				if results.len() > self.results.len() {
+					return Err(ValidationError::UnusedValues(results.len() - self.results.len()));
+				}
+				self.unreachable();
+			},
+
+			RefNull(ht) => {
+				self.module.check_heap_type(ht)?;
+				self.push(RefType::new(true, ht).into());
+			},
+			RefIsNull => {
+				self.pop_ref()?;
+				self.push(I32);
+			},
+			RefFunc(func) => {
+				let ty = self.module.func_type_ref(func)?;
+				if !self.module.is_declared_ref(func) {
+					return Err(ValidationError::UndeclaredFunctionReference(func));
+				}
+				self.push(RefType::new(false, HeapType::Index(ty)).into());
+			},
+			RefEq => self.op(&[RefType::EQREF.into(), RefType::EQREF.into()], &[I32])?,
+			RefAsNonNull => {
+				let r = self.pop_ref()?;
+				self.stack.push(r.map(|r| RefType::new(false, r.heap_type()).into()));
+			},
+			BrOnNull(depth) => {
+				let r = self.pop_ref()?;
+				let types = self.label_types(depth)?;
+				self.pop_values(&types)?;
+				self.push_values(&types);
+				self.stack.push(r.map(|r| RefType::new(false, r.heap_type()).into()));
+			},
+			BrOnNonNull(depth) => {
+				let r = self.pop_ref()?;
+				let types = self.label_types(depth)?;
+				let (target, prefix) = match types.split_last() {
+					Some((&ValueType::Ref(target), prefix)) => (target, prefix),
+					_ => return Err(ValidationError::LabelTypeMismatch(depth)),
+				};
+				if let Some(r) = r {
+					self.check_subtype(RefType::new(false, r.heap_type()).into(), target.into())?;
+				}
+				self.pop_values(prefix)?;
+				self.push_values(prefix);
+			},
+
+			Drop => { self.pop_any()?; },
+			Select => {
+				self.pop(I32)?;
+				let first = self.pop_any()?;
+				let second = self.pop_any()?;
+				match (first, second) {
+					(Some(ValueType::Ref(_)), _) | (_, Some(ValueType::Ref(_))) => {
+						return Err(ValidationError::InvalidSelect);
+					},
+					(Some(first), Some(second)) if first != second => {
+						return Err(ValidationError::TypeMismatch { expected: first, actual: Some(second) });
+					},
+					_ => {},
+				}
+				self.stack.push(first.or(second));
+			},
+
+			GetLocal(idx) => {
+				let ty = self.local(idx)?;
+				if !is_defaultable(ty) && idx >= self.params && !self.initialized.contains(&idx) {
+					return Err(ValidationError::UninitializedLocal(idx));
+				}
+				self.push(ty);
+			},
+			SetLocal(idx) => {
+				let ty = self.local(idx)?;
+				self.pop(ty)?;
+				self.init_local(idx, ty);
+			},
+			TeeLocal(idx) => {
+				let ty = self.local(idx)?;
+				self.pop(ty)?;
+				self.init_local(idx, ty);
+				self.push(ty);
+			},
+			GetGlobal(idx) => {
+				let ty = self.module.global(idx)?.content_type();
+				self.push(ty);
+			},
+			SetGlobal(idx) => {
+				let global = *self.module.global(idx)?;
+				if !global.is_mutable() {
+					return Err(ValidationError::ImmutableGlobal(idx));
+				}
+				self.pop(global.content_type())?;
+			},
+			GetTable(idx) => {
+				let elem_type = self.module.table(idx)?.elem_type();
+				self.op(&[I32], &[elem_type.into()])?;
+			},
+			SetTable(idx) => {
+				let elem_type = self.module.table(idx)?.elem_type();
+				self.op(&[I32, elem_type.into()], &[])?;
+			},
+			TableGrow(idx) => {
+				let elem_type = self.module.table(idx)?.elem_type();
+				self.op(&[elem_type.into(), I32], &[I32])?;
+			},
+			TableSize(idx) => {
+				self.module.table(idx)?;
+				self.push(I32);
+			},
+			TableFill(idx) => {
+				let elem_type = self.module.table(idx)?.elem_type();
+				self.op(&[I32, elem_type.into(), I32], &[])?;
+			},
+
+			I32Load(ref memarg) => self.load(memarg, 2, I32)?,
+			I64Load(ref memarg) => self.load(memarg, 3, I64)?,
+			F32Load(ref memarg) => self.load(memarg, 2, F32)?,
+			F64Load(ref memarg) => self.load(memarg, 3, F64)?,
+			I32Load8S(ref memarg) | I32Load8U(ref memarg) => self.load(memarg, 0, I32)?,
+			I32Load16S(ref memarg) | I32Load16U(ref memarg) => self.load(memarg, 1, I32)?,
+			I64Load8S(ref memarg) | I64Load8U(ref memarg) => self.load(memarg, 0, I64)?,
+			I64Load16S(ref memarg) | I64Load16U(ref memarg) => self.load(memarg, 1, I64)?,
+			I64Load32S(ref memarg) | I64Load32U(ref memarg) => self.load(memarg, 2, I64)?,
+			I32Store(ref memarg) => self.store(memarg, 2, I32)?,
+			I64Store(ref memarg) => self.store(memarg, 3, I64)?,
+			F32Store(ref memarg) => self.store(memarg, 2, F32)?,
+			F64Store(ref memarg) => self.store(memarg, 3, F64)?,
+			I32Store8(ref memarg) => self.store(memarg, 0, I32)?,
+			I32Store16(ref memarg) => self.store(memarg, 1, I32)?,
+			I64Store8(ref memarg) => self.store(memarg, 0, I64)?,
+			I64Store16(ref memarg) => self.store(memarg, 1, I64)?,
+			I64Store32(ref memarg) => self.store(memarg, 2, I64)?,
+			CurrentMemory(idx) => {
+				let index_type = self.index_type(idx)?;
+				self.push(index_type);
+			},
+			GrowMemory(idx) => {
+				let index_type = self.index_type(idx)?;
+				self.op(&[index_type], &[index_type])?;
+			},
+
+			I32Const(_) => self.push(I32),
+			I64Const(_) => self.push(I64),
+			F32Const(_) => self.push(F32),
+			F64Const(_) => self.push(F64),
+
+			I32Eqz => self.op(&[I32], &[I32])?,
+			I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS | I32GeU =>
+				self.op(&[I32, I32], &[I32])?,
+			I64Eqz => self.op(&[I64], &[I32])?,
+			I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU | I64GeS | I64GeU =>
+				self.op(&[I64, I64], &[I32])?,
+			F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge => self.op(&[F32, F32], &[I32])?,
+			F64Eq | F64Ne | F64Lt | F64Gt | F64Le | F64Ge => self.op(&[F64, F64], &[I32])?,
+
+			I32Clz | I32Ctz | I32Popcnt | I32Extend8S | I32Extend16S => self.op(&[I32], &[I32])?,
+			I32Add | I32Sub | I32Mul | I32DivS | I32DivU | I32RemS | I32RemU | I32And | I32Or |
+			I32Xor | I32Shl | I32ShrS | I32ShrU | I32Rotl | I32Rotr => self.op(&[I32, I32], &[I32])?,
+			I64Clz | I64Ctz | I64Popcnt | I64Extend8S | I64Extend16S | I64Extend32S =>
+				self.op(&[I64], &[I64])?,
+			I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS | I64RemU | I64And | I64Or |
+			I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl | I64Rotr => self.op(&[I64, I64], &[I64])?,
+			F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt => self.op(&[F32], &[F32])?,
+			F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => self.op(&[F32, F32], &[F32])?,
+			F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt => self.op(&[F64], &[F64])?,
+			F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => self.op(&[F64, F64], &[F64])?,
+
+			I32WrapI64 => self.op(&[I64], &[I32])?,
+			I32TruncSF32 | I32TruncUF32 | I32ReinterpretF32 => self.op(&[F32], &[I32])?,
+			I32TruncSF64 | I32TruncUF64 => self.op(&[F64], &[I32])?,
+			I64ExtendSI32 | I64ExtendUI32 => self.op(&[I32], &[I64])?,
+			I64TruncSF32 | I64TruncUF32 => self.op(&[F32], &[I64])?,
+			I64TruncSF64 | I64TruncUF64 | I64ReinterpretF64 => self.op(&[F64], &[I64])?,
+			F32ConvertSI32 | F32ConvertUI32 | F32ReinterpretI32 => self.op(&[I32], &[F32])?,
+			F32ConvertSI64 | F32ConvertUI64 => self.op(&[I64], &[F32])?,
+			F32DemoteF64 => self.op(&[F64], &[F32])?,
+			F64ConvertSI32 | F64ConvertUI32 => self.op(&[I32], &[F64])?,
+			F64ConvertSI64 | F64ConvertUI64 | F64ReinterpretI64 => self.op(&[I64], &[F64])?,
+			F64PromoteF32 => self.op(&[F32], &[F64])?,
+
+			AtomicWake(ref arg) => self.atomic(arg, 2, &[I32], &[I32])?,
+			I32AtomicWait(ref arg) => self.atomic(arg, 2, &[I32, I64], &[I32])?,
+			I64AtomicWait(ref arg) => self.atomic(arg, 3, &[I64, I64], &[I32])?,
+			I32AtomicLoad(ref arg) => self.atomic(arg, 2, &[], &[I32])?,
+			I64AtomicLoad(ref arg) => self.atomic(arg, 3, &[], &[I64])?,
+			I32AtomicLoad8u(ref arg) => self.atomic(arg, 0, &[], &[I32])?,
+			I32AtomicLoad16u(ref arg) => self.atomic(arg, 1, &[], &[I32])?,
+			I64AtomicLoad8u(ref arg) => self.atomic(arg, 0, &[], &[I64])?,
+			I64AtomicLoad16u(ref arg) => self.atomic(arg, 1, &[], &[I64])?,
+			I64AtomicLoad32u(ref arg) => self.atomic(arg, 2, &[], &[I64])?,
+			I32AtomicStore(ref arg) => self.atomic(arg, 2, &[I32], &[])?,
+			I64AtomicStore(ref arg) => self.atomic(arg, 3, &[I64], &[])?,
+			I32AtomicStore8u(ref arg) => self.atomic(arg, 0, &[I32], &[])?,
+			I32AtomicStore16u(ref arg) => self.atomic(arg, 1, &[I32], &[])?,
+			I64AtomicStore8u(ref arg) => self.atomic(arg, 0, &[I64], &[])?,
+			I64AtomicStore16u(ref arg) => self.atomic(arg, 1, &[I64], &[])?,
+			I64AtomicStore32u(ref arg) => self.atomic(arg, 2, &[I64], &[])?,
+			I32AtomicRmwAdd(ref arg) | I32AtomicRmwSub(ref arg) | I32AtomicRmwAnd(ref arg) | I32AtomicRmwOr(ref arg) |
+			I32AtomicRmwXor(ref arg) | I32AtomicRmwXchg(ref arg) => self.atomic(arg, 2, &[I32], &[I32])?,
+			I32AtomicRmwAdd8u(ref arg) | I32AtomicRmwSub8u(ref arg) | I32AtomicRmwAnd8u(ref arg) |
+			I32AtomicRmwOr8u(ref arg) | I32AtomicRmwXor8u(ref arg) | I32AtomicRmwXchg8u(ref arg) =>
+				self.atomic(arg, 0, &[I32], &[I32])?,
+			I32AtomicRmwAdd16u(ref arg) | I32AtomicRmwSub16u(ref arg) | I32AtomicRmwAnd16u(ref arg) |
+			I32AtomicRmwOr16u(ref arg) | I32AtomicRmwXor16u(ref arg) | I32AtomicRmwXchg16u(ref arg) =>
+				self.atomic(arg, 1, &[I32], &[I32])?,
+			I64AtomicRmwAdd(ref arg) | I64AtomicRmwSub(ref arg) | I64AtomicRmwAnd(ref arg) | I64AtomicRmwOr(ref arg) |
+			I64AtomicRmwXor(ref arg) | I64AtomicRmwXchg(ref arg) => self.atomic(arg, 3, &[I64], &[I64])?,
+			I64AtomicRmwAdd8u(ref arg) | I64AtomicRmwSub8u(ref arg) | I64AtomicRmwAnd8u(ref arg) |
+			I64AtomicRmwOr8u(ref arg) | I64AtomicRmwXor8u(ref arg) | I64AtomicRmwXchg8u(ref arg) =>
+				self.atomic(arg, 0, &[I64], &[I64])?,
+			I64AtomicRmwAdd16u(ref arg) | I64AtomicRmwSub16u(ref arg) | I64AtomicRmwAnd16u(ref arg) |
+			I64AtomicRmwOr16u(ref arg) | I64AtomicRmwXor16u(ref arg) | I64AtomicRmwXchg16u(ref arg) =>
+				self.atomic(arg, 1, &[I64], &[I64])?,
+			I64AtomicRmwAdd32u(ref arg) | I64AtomicRmwSub32u(ref arg) | I64AtomicRmwAnd32u(ref arg) |
+			I64AtomicRmwOr32u(ref arg) | I64AtomicRmwXor32u(ref arg) | I64AtomicRmwXchg32u(ref arg) =>
+				self.atomic(arg, 2, &[I64], &[I64])?,
+			I32AtomicRmwCmpxchg(ref arg) => self.atomic(arg, 2, &[I32, I32], &[I32])?,
+			I64AtomicRmwCmpxchg(ref arg) => self.atomic(arg, 3, &[I64, I64], &[I64])?,
+			I32AtomicRmwCmpxchg8u(ref arg) => self.atomic(arg, 0, &[I32, I32], &[I32])?,
+			I32AtomicRmwCmpxchg16u(ref arg) => self.atomic(arg, 1, &[I32, I32], &[I32])?,
+			I64AtomicRmwCmpxchg8u(ref arg) => self.atomic(arg, 0, &[I64, I64], &[I64])?,
+			I64AtomicRmwCmpxchg16u(ref arg) => self.atomic(arg, 1, &[I64, I64], &[I64])?,
+			I64AtomicRmwCmpxchg32u(ref arg) => self.atomic(arg, 2, &[I64, I64], &[I64])?,
+
+			V128Const(_) => self.push(V128),
+			V128Load(ref arg) => self.load(arg, 4, V128)?,
+			V128Store(ref arg) => self.store(arg, 4, V128)?,
+			I8x16Splat | I16x8Splat | I32x4Splat => self.op(&[I32], &[V128])?,
+			I64x2Splat => self.op(&[I64], &[V128])?,
+			F32x4Splat => self.op(&[F32], &[V128])?,
+			F64x2Splat => self.op(&[F64], &[V128])?,
+			I8x16ExtractLaneS(lane) | I8x16ExtractLaneU(lane) => self.extract_lane(lane, 16, I32)?,
+			I16x8ExtractLaneS(lane) | I16x8ExtractLaneU(lane) => self.extract_lane(lane, 8, I32)?,
+			I32x4ExtractLane(lane) => self.extract_lane(lane, 4, I32)?,
+			I64x2ExtractLane(lane) => self.extract_lane(lane, 2, I64)?,
+			F32x4ExtractLane(lane) => self.extract_lane(lane, 4, F32)?,
+			F64x2ExtractLane(lane) => self.extract_lane(lane, 2, F64)?,
+			I8x16ReplaceLane(lane) => self.replace_lane(lane, 16, I32)?,
+			I16x8ReplaceLane(lane) => self.replace_lane(lane, 8, I32)?,
+			I32x4ReplaceLane(lane) => self.replace_lane(lane, 4, I32)?,
+			I64x2ReplaceLane(lane) => self.replace_lane(lane, 2, I64)?,
+			F32x4ReplaceLane(lane) => self.replace_lane(lane, 4, F32)?,
+			F64x2ReplaceLane(lane) => self.replace_lane(lane, 2, F64)?,
+			V8x16Shuffle(ref lanes) => {
+				if let Some(&lane) = lanes.iter().find(|&&lane| lane >= 32) {
+					return Err(ValidationError::InvalidLane(lane));
+				}
+				self.op(&[V128, V128], &[V128])?;
+			},
+			I8x16Add | I16x8Add | I32x4Add | I64x2Add | I8x16Sub | I16x8Sub | I32x4Sub | I64x2Sub |
+			I8x16Mul | I16x8Mul | I32x4Mul |
+			I8x16AddSaturateS | I8x16AddSaturateU | I16x8AddSaturateS | I16x8AddSaturateU |
+			I8x16SubSaturateS | I8x16SubSaturateU | I16x8SubSaturateS | I16x8SubSaturateU |
+			V128And | V128Or | V128Xor |
+			I8x16Eq | I16x8Eq | I32x4Eq | F32x4Eq | F64x2Eq |
+			I8x16Ne | I16x8Ne | I32x4Ne | F32x4Ne | F64x2Ne |
+			I8x16LtS | I8x16LtU | I16x8LtS | I16x8LtU | I32x4LtS | I32x4LtU | F32x4Lt | F64x2Lt |
+			I8x16LeS | I8x16LeU | I16x8LeS | I16x8LeU | I32x4LeS | I32x4LeU | F32x4Le | F64x2Le |
+			I8x16GtS | I8x16GtU | I16x8GtS | I16x8GtU | I32x4GtS | I32x4GtU | F32x4Gt | F64x2Gt |
+			I8x16GeS | I8x16GeU | I16x8GeS | I16x8GeU | I32x4GeS | I32x4GeU | F32x4Ge | F64x2Ge |
+			F32x4Min | F64x2Min | F32x4Max | F64x2Max | F32x4Add | F64x2Add | F32x4Sub | F64x2Sub |
+			F32x4Div | F64x2Div | F32x4Mul | F64x2Mul => self.op(&[V128, V128], &[V128])?,
+			I8x16Neg | I16x8Neg | I32x4Neg | I64x2Neg | V128Not | F32x4Neg | F64x2Neg |
+			F32x4Abs | F64x2Abs | F32x4Sqrt | F64x2Sqrt |
+			F32x4ConvertSI32x4 | F32x4ConvertUI32x4 | F64x2ConvertSI64x2 | F64x2ConvertUI64x2 |
+			I32x4TruncSF32x4Sat | I32x4TruncUF32x4Sat | I64x2TruncSF64x2Sat | I64x2TruncUF64x2Sat =>
+				self.op(&[V128], &[V128])?,
+			I8x16Shl | I16x8Shl | I32x4Shl | I64x2Shl | I8x16ShrS | I8x16ShrU | I16x8ShrS | I16x8ShrU |
+			I32x4ShrS | I32x4ShrU | I64x2ShrS | I64x2ShrU => self.op(&[V128, I32], &[V128])?,
+			V128Bitselect => self.op(&[V128, V128, V128], &[V128])?,
+			I8x16AnyTrue | I16x8AnyTrue | I32x4AnyTrue | I64x2AnyTrue |
+			I8x16AllTrue | I16x8AllTrue | I32x4AllTrue | I64x2AllTrue => self.op(&[V128], &[I32])?,
+
+			MemoryInit(data, mem) => {
+				let index_type = self.index_type(mem)?;
+				self.module.data_segment(data)?;
+				self.op(&[index_type, I32, I32], &[])?;
+			},
+			MemoryDrop(data) => self.module.data_segment(data)?,
+			MemoryCopy(dst, src) => {
+				let dst_type = self.index_type(dst)?;
+				let src_type = self.index_type(src)?;
+				// The length is only 64-bit if both memories are.
+				let len_type = if dst_type == I64 && src_type == I64 { I64 } else { I32 };
+				self.op(&[dst_type, src_type, len_type], &[])?;
+			},
+			MemoryFill(mem) => {
+				let index_type = self.index_type(mem)?;
+				self.op(&[index_type, I32, index_type], &[])?;
+			},
+			TableInit(elem, table) => {
+				let elem_type = self.module.table(table)?.elem_type();
+				let segment_type = self.module.element_segment(elem)?;
+				self.check_subtype(segment_type.into(), elem_type.into())?;
+				self.op(&[I32, I32, I32], &[])?;
+			},
+			TableDrop(elem) => { self.module.element_segment(elem)?; },
+			TableCopy(dst, src) => {
+				let dst_type = self.module.table(dst)?.elem_type();
+				let src_type = self.module.table(src)?.elem_type();
+				self.check_subtype(src_type.into(), dst_type.into())?;
+				self.op(&[I32, I32, I32], &[])?;
+			},
+
+			StructNew(ty) => {
+				let fields = self.module.struct_type(ty)?.fields();
+				for field in fields.iter().rev() {
+					self.pop(field.storage_type().unpacked())?;
+				}
+				self.push(RefType::new(false, HeapType::Index(ty)).into());
+			},
+			StructNewDefault(ty) => {
+				for field in self.module.struct_type(ty)?.fields() {
+					check_defaultable(field.storage_type().unpacked())?;
+				}
+				self.push(RefType::new(false, HeapType::Index(ty)).into());
+			},
+			StructGet(ty, idx) => {
+				let field = self.field(ty, idx, Some(false))?;
+				self.op(&[struct_ref(ty)], &[field.storage_type().unpacked()])?;
+			},
+			StructGetS(ty, idx) | StructGetU(ty, idx) => {
+				self.field(ty, idx, Some(true))?;
+				self.op(&[struct_ref(ty)], &[I32])?;
+			},
+			StructSet(ty, idx) => {
+				let field = self.field(ty, idx, None)?;
+				if !field.is_mutable() {
+					return Err(ValidationError::ImmutableField { type_index: ty, field: idx });
+				}
+				self.op(&[struct_ref(ty), field.storage_type().unpacked()], &[])?;
+			},
+			ArrayNew(ty) => {
+				let elem = self.array_elem(ty, None)?.storage_type().unpacked();
+				self.op(&[elem, I32], &[array_ref(ty, false)])?;
+			},
+			ArrayNewDefault(ty) => {
+				check_defaultable(self.array_elem(ty, None)?.storage_type().unpacked())?;
+				self.op(&[I32], &[array_ref(ty, false)])?;
+			},
+			ArrayNewFixed(ty, len) => {
+				let elem = self.array_elem(ty, None)?.storage_type().unpacked();
+				for _ in 0..len {
+					self.pop(elem)?;
+				}
+				self.push(array_ref(ty, false));
+			},
+			ArrayNewData(ty, data) => {
+				self.data_array(ty, data)?;
+				self.op(&[I32, I32], &[array_ref(ty, false)])?;
+			},
+			ArrayNewElem(ty, elem) => {
+				self.elem_array(ty, elem)?;
+				self.op(&[I32, I32], &[array_ref(ty, false)])?;
+			},
+			ArrayGet(ty) => {
+				let elem = self.array_elem(ty, Some(false))?.storage_type().unpacked();
+				self.op(&[array_ref(ty, true), I32], &[elem])?;
+			},
+			ArrayGetS(ty) | ArrayGetU(ty) => {
+				self.array_elem(ty, Some(true))?;
+				self.op(&[array_ref(ty, true), I32], &[I32])?;
+			},
+			ArraySet(ty) => {
+				let elem = self.mutable_array_elem(ty)?;
+				self.op(&[array_ref(ty, true), I32, elem], &[])?;
+			},
+			ArrayLen => self.op(&[RefType::ARRAYREF.into()], &[I32])?,
+			ArrayFill(ty) => {
+				let elem = self.mutable_array_elem(ty)?;
+				self.op(&[array_ref(ty, true), I32, elem, I32], &[])?;
+			},
+			ArrayCopy(dst, src) => {
+				let dst_elem = self.module.array_type(dst)?.elem().storage_type();
+				self.mutable_array_elem(dst)?;
+				let src_elem = self.module.array_type(src)?.elem().storage_type();
+				if !self.module.type_context().is_subtype(&src_elem, &dst_elem) {
+					return Err(ValidationError::InvalidArrayElement(src));
+				}
+				self.op(&[array_ref(dst, true), I32, array_ref(src, true), I32, I32], &[])?;
+			},
+			ArrayInitData(ty, data) => {
+				self.mutable_array_elem(ty)?;
+				self.data_array(ty, data)?;
+				self.op(&[array_ref(ty, true), I32, I32, I32], &[])?;
+			},
+			ArrayInitElem(ty, elem) => {
+				self.mutable_array_elem(ty)?;
+				self.elem_array(ty, elem)?;
+				self.op(&[array_ref(ty, true), I32, I32, I32], &[])?;
+			},
+
+			RefTest(r) => {
+				self.module.check_heap_type(r.heap_type())?;
+				self.op(&[self.top_type(r.heap_type())], &[I32])?;
+			},
+			RefCast(r) => {
+				self.module.check_heap_type(r.heap_type())?;
+				self.op(&[self.top_type(r.heap_type())], &[r.into()])?;
+			},
+			BrOnCast(ref data) | BrOnCastFail(ref data) => {
+				let fail = matches!(*instruction, BrOnCastFail(_));
+				self.module.check_heap_type(data.from.heap_type())?;
+				self.module.check_heap_type(data.to.heap_type())?;
+				self.check_subtype(data.to.into(), data.from.into())?;
+
+				// Type of the operand when the cast fails.
+				let diff = RefType::new(data.from.is_nullable() && !data.to.is_nullable(), data.from.heap_type());
+				let (taken, fallthrough) = if fail { (diff, data.to) } else { (data.to, diff) };
+
+				let types = self.label_types(data.label)?;
+				let (target, prefix) = match types.split_last() {
+					Some((&ValueType::Ref(target), prefix)) => (target, prefix),
+					_ => return Err(ValidationError::LabelTypeMismatch(data.label)),
+				};
+				self.check_subtype(taken.into(), target.into())?;
+				self.pop(data.from.into())?;
+				self.pop_values(prefix)?;
+				self.push_values(prefix);
+				self.push(fallthrough.into());
+			},
+			AnyConvertExtern => {
+				let r = self.pop(RefType::EXTERNREF.into())?;
+				self.stack.push(r.map(|r| RefType::new(is_nullable(r), HeapType::Any).into()));
+			},
+			ExternConvertAny => {
+				let r = self.pop(RefType::ANYREF.into())?;
+				self.stack.push(r.map(|r| RefType::new(is_nullable(r), HeapType::Extern).into()));
+			},
+			RefI31 => self.op(&[I32], &[RefType::new(false, HeapType::I31).into()])?,
+			I31GetS | I31GetU => self.op(&[RefType::I31REF.into()], &[I32])?,
+		}
+		Ok(())
+	}
+
+	/// Check that the body was terminated by the final `end`.
+	pub fn finish(self) -> Result<(), ValidationError> {
+		if self.frames.is_empty() {
+			Ok(())
+		} else {
+			Err(ValidationError::UnbalancedControl)
+		}
+	}
+
+	fn push(&mut self, ty: ValueType) {
+		self.stack.push(Some(ty));
+	}
+
+	fn push_values(&mut self, types: &[ValueType]) {
+		self.stack.extend(types.iter().copied().map(Some));
+	}
+
+	/// Pop any value, `None` if the value is unknown because the code is unreachable.
+	fn pop_any(&mut self) -> Result<Option<ValueType>, ValidationError> {
+		let frame = self.frames.last().ok_or(ValidationError::UnbalancedControl)?;
+		if self.stack.len() == frame.height {
+			return if frame.unreachable { Ok(None) } else { Err(ValidationError::StackUnderflow) };
+		}
+		Ok(self.stack.pop().and_then(|ty| ty))
+	}
+
+	/// Pop a value that is a subtype of `expected`.
+	fn pop(&mut self, expected: ValueType) -> Result<Option<ValueType>, ValidationError> {
+		let actual = match self.pop_any() {
+			Err(ValidationError::StackUnderflow) => {
+				return Err(ValidationError::TypeMismatch { expected, actual: None });
+			},
+			actual => actual?,
+		};
+		if let Some(actual) = actual {
+			self.check_subtype(actual, expected)?;
+		}
+		Ok(actual)
+	}
+
+	/// Pop any reference.
+	fn pop_ref(&mut self) -> Result<Option<RefType>, ValidationError> {
+		match self.pop_any()? {
+			Some(ValueType::Ref(r)) => Ok(Some(r)),
+			Some(actual) => Err(ValidationError::ExpectedReference(actual)),
+			None => Ok(None),
+		}
+	}
+
+	fn pop_values(&mut self, types: &[ValueType]) -> Result<Vec<Option<ValueType>>, ValidationError> {
+		let mut values = Vec::with_capacity(types.len());
+		for &ty in types.iter().rev() {
+			values.push(self.pop(ty)?);
+		}
+		values.reverse();
+		Ok(values)
+	}
+
+	/// Pop the inputs and push the outputs of an instruction.
+	fn op(&mut self, inputs: &[ValueType], outputs: &[ValueType]) -> Result<(), ValidationError> {
+		self.pop_values(inputs)?;
+		self.push_values(outputs);
+		Ok(())
+	}
+
+	fn call(&mut self, func_type: &FunctionType) -> Result<(), ValidationError> {
+		self.pop_values(func_type.params())?;
+		self.push_values(func_type.results());
+		Ok(())
+	}
+
+	fn check_subtype(&self, actual: ValueType, expected: ValueType) -> Result<(), ValidationError> {
+		if self.module.type_context().is_subtype(&actual, &expected) {
+			Ok(())
+		} else {
+			Err(ValidationError::TypeMismatch { expected, actual: Some(actual) })
+		}
+	}
+
+	fn push_frame(&mut self, kind: FrameKind, params: Vec<ValueType>, results: Vec<ValueType>) {
+		let frame = Frame {
+			kind,
+			height: self.stack.len(),
+			inits: self.inits.len(),
+			unreachable: false,
+			params,
+			results,
+		};
+		self.push_values(&frame.params);
+		self.frames.push(frame);
+	}
+
+	fn pop_frame(&mut self) -> Result<Frame, ValidationError> {
+		let results = self.frames.last().ok_or(ValidationError::UnbalancedControl)?.results.clone();
+		self.pop_values(&results)?;
+		let frame = self.frames.pop().ok_or(ValidationError::UnbalancedControl)?;
+		if self.stack.len() != frame.height {
+			return Err(ValidationError::UnusedValues(self.stack.len() - frame.height));
+		}
+		// Locals set inside the frame are not known to be set after it.
+		for local in self.inits.drain(frame.inits..) {
+			self.initialized.remove(&local);
+		}
+		Ok(frame)
+	}
+
+	fn unreachable(&mut self) {
+		if let Some(frame) = self.frames.last_mut() {
+			self.stack.truncate(frame.height);
+			frame.unreachable = true;
+		}
+	}
+
+	fn label_types(&self, depth: u32) -> Result<Vec<ValueType>, ValidationError> {
+		let frame = (depth as usize).checked_add(1)
+			.and_then(|n| self.frames.len().checked_sub(n))
+			.and_then(|idx| self.frames.get(idx))
+			.ok_or(ValidationError::UnknownLabel(depth))?;
+		Ok(frame.label_types().to_vec())
+	}
+
+	fn block_type(&self, ty: BlockType) -> Result<(Vec<ValueType>, Vec<ValueType>), ValidationError> {
+		self.module.check_block_type(ty)?;
+		match ty {
+			BlockType::Value(v) => Ok((Vec::new(), vec![v])),
+			BlockType::NoResult => Ok((Vec::new(), Vec::new())),
+			BlockType::TypeIndex(idx) => {
+				let func_type = self.module.function_type(idx)?;
+				Ok((func_type.params().to_vec(), func_type.results().to_vec()))
+			},
+		}
+	}
+
+	fn local(&self, idx: u32) -> Result<ValueType, ValidationError> {
+		let run = self.locals.partition_point(|&(end, _)| end <= idx);
+		self.locals.get(run).map(|&(_, ty)| ty).ok_or(ValidationError::UnknownLocal(idx))
+	}
+
+	fn init_local(&mut self, idx: u32, ty: ValueType) {
+		if !is_defaultable(ty) && self.initialized.insert(idx) {
+			self.inits.push(idx);
+		}
+	}
+
+	fn load(&mut self, arg: &MemArg, natural: u32, ty: ValueType) -> Result<(), ValidationError> {
+		self.memory_op(arg, natural, &[], &[ty])
+	}
+
+	fn store(&mut self, arg: &MemArg, natural: u32, ty: ValueType) -> Result<(), ValidationError> {
+		self.memory_op(arg, natural, &[ty], &[])
+	}
+
+	fn atomic(&mut self, arg: &MemArg, natural: u32, inputs: &[ValueType], outputs: &[ValueType])
+		-> Result<(), ValidationError>
+	{
+		// Atomic accesses must be naturally aligned.
+		if u32::from(arg.align) != natural {
+			return Err(ValidationError::InvalidAlignment { align: arg.align.into(), natural });
+		}
+		self.memory_op(arg, natural, inputs, outputs)
+	}
+
+	/// Pop the address and the inputs of a memory access and push its outputs.
+	fn memory_op(&mut self, arg: &MemArg, natural: u32, inputs: &[ValueType], outputs: &[ValueType])
+		-> Result<(), ValidationError>
+	{
+		let index_type = self.index_type(arg.memory)?;
+		let align = u32::from(arg.align);
+		if align > natural {
+			return Err(ValidationError::InvalidAlignment { align, natural });
+		}
+		if index_type == I32 && arg.offset > u64::from(u32::MAX) {
+			return Err(ValidationError::OffsetTooLarge(arg.offset));
+		}
+		self.pop_values(inputs)?;
+		self.pop(index_type)?;
+		self.push_values(outputs);
+		Ok(())
+	}
+
+	/// Type of the addresses of the memory.
+	fn index_type(&self, memory: u32) -> Result<ValueType, ValidationError> {
+		Ok(self.module.memory(memory)?.index_type().into())
+	}
+
+	fn extract_lane(&mut self, lane: u8, lanes: u8, ty: ValueType) -> Result<(), ValidationError> {
+		if lane >= lanes {
+			return Err(ValidationError::InvalidLane(lane));
+		}
+		self.op(&[V128], &[ty])
+	}
+
+	fn replace_lane(&mut self, lane: u8, lanes: u8, ty: ValueType) -> Result<(), ValidationError> {
+		if lane >= lanes {
+			return Err(ValidationError::InvalidLane(lane));
+		}
+		self.op(&[V128, ty], &[V128])
+	}
+
+	/// Field of a struct type, `packed` requires the field to be packed or not.
+	fn field(&self, ty: u32, idx: u32, packed: Option<bool>) -> Result<&'a FieldType, ValidationError> {
+		let field = self.module.struct_type(ty)?.fields().get(idx as usize)
+			.ok_or(ValidationError::UnknownField { type_index: ty, field: idx })?;
+		match packed {
+			Some(packed) if packed != field.storage_type().is_packed() => {
+				Err(ValidationError::InvalidPackedAccess(ty))
+			},
+			_ => Ok(field),
+		}
+	}
+
+	/// Element of an array type, `packed` requires the element to be packed or not.
+	fn array_elem(&self, ty: u32, packed: Option<bool>) -> Result<&'a FieldType, ValidationError> {
+		let elem = self.module.array_type(ty)?.elem();
+		match packed {
+			Some(packed) if packed != elem.storage_type().is_packed() => {
+				Err(ValidationError::InvalidPackedAccess(ty))
+			},
+			_ => Ok(elem),
+		}
+	}
+
+	fn mutable_array_elem(&self, ty: u32) -> Result<ValueType, ValidationError> {
+		let elem = self.array_elem(ty, None)?;
+		if !elem.is_mutable() {
+			return Err(ValidationError::ImmutableArray(ty));
+		}
+		Ok(elem.storage_type().unpacked())
+	}
+
+	/// Check that the array can be initialized from the data segment.
+	fn data_array(&self, ty: u32, data: u32) -> Result<(), ValidationError> {
+		self.module.data_segment(data)?;
+		match self.array_elem(ty, None)?.storage_type() {
+			StorageType::Value(ValueType::Ref(_)) => Err(ValidationError::InvalidArrayElement(ty)),
+			_ => Ok(()),
+		}
+	}
+
+	/// Check that the array can be initialized from the element segment.
+	fn elem_array(&self, ty: u32, elem: u32) -> Result<(), ValidationError> {
+		let segment_type = self.module.element_segment(elem)?;
+		let elem_type = self.array_elem(ty, None)?.storage_type().unpacked();
+		if self.module.type_context().is_subtype(&segment_type.into(), &elem_type) {
+			Ok(())
+		} else {
+			Err(ValidationError::InvalidArrayElement(ty))
+		}
+	}
+
+	/// Nullable top type of the hierarchy the heap type belongs to.
+	fn top_type(&self, heap_type: HeapType) -> ValueType {
+		let top = match heap_type {
+			HeapType::Func | HeapType::NoFunc => HeapType::Func,
+			HeapType::Extern | HeapType::NoExtern => HeapType::Extern,
+			HeapType::Index(idx) => match self.module.type_context().ty(idx) {
+				Some(Type::Function(_)) => HeapType::Func,
+				_ => HeapType::Any,
+			},
+			_ => HeapType::Any,
+		};
+		RefType::new(true, top).into()
+	}
+}
+
+impl<'a> ValidatedModule<'a> {
+	/// Type check the body of the function with the given index.
+	///
+	/// Errors are reported as [`ValidationError::Function`] with the index of the
+	/// offending instruction.
+	pub fn validate_function(&self, func: u32, body: &FuncBody) -> Result<(), ValidationError> {
+		let wrap = |instruction: Option<u32>| move |error| ValidationError::Function {
+			func,
+			instruction,
+			error: Box::new(error),
+		};
+
+		let func_type = self.func_type(func).map_err(wrap(None))?;
+		let malformed = |error: Error| wrap(None)(ValidationError::MalformedBody(error.to_string()));
+		let locals = body.try_locals().map_err(malformed)?;
+		let code = body.try_code().map_err(malformed)?.elements();
+		let mut validator = FuncValidator::new(self, func_type, locals).map_err(wrap(None))?;
+		for (index, instruction) in code.iter().enumerate() {
+			validator.step(instruction).map_err(wrap(Some(index as u32)))?;
+		}
+		validator.finish().map_err(wrap(Some(code.len() as u32)))
+	}
+}
+
+fn struct_ref(ty: u32) -> ValueType {
+	RefType::new(true, HeapType::Index(ty)).into()
+}
+
+fn array_ref(ty: u32, nullable: bool) -> ValueType {
+	RefType::new(nullable, HeapType::Index(ty)).into()
+}
+
+fn is_nullable(ty: ValueType) -> bool {
+	match ty {
+		ValueType::Ref(r) => r.is_nullable(),
+		_ => true,
+	}
+}
+
+/// Can a local or field of this type be initialized with a default value.
+fn is_defaultable(ty: ValueType) -> bool {
+	is_nullable(ty)
+}
+
+fn check_defaultable(ty: ValueType) -> Result<(), ValidationError> {
+	if is_defaultable(ty) {
+		Ok(())
+	} else {
+		Err(ValidationError::NonDefaultable(ty))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::super::{
+		Module, Section, TypeSection, FunctionSection, CodeSection, FunctionType, Func, FuncBody,
+		Instructions, Instruction, BlockType, BrTableData, Local, RefType, HeapType,
+	};
+	use super::super::{validate, ValidationError};
+	use super::{I32, I64, FuncValidator};
+
+	fn check(func_type: FunctionType, locals: Vec<Local>, code: Vec<Instruction>) -> Result<(), ValidationError> {
+		let module = Module::new(vec![
+			Section::Type(TypeSection::with_types(vec![func_type.into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+			Section::Code(CodeSection::with_bodies(vec![FuncBody::new(locals, Instructions::new(code))])),
+		]);
+		validate(&module).map(drop)
+	}
+
+	fn error_at(instruction: u32, error: ValidationError) -> ValidationError {
+		ValidationError::Function { func: 0, instruction: Some(instruction), error: Box::new(error) }
+	}
+
+	#[test]
+	fn operand_stack() {
+		use self::Instruction::*;
+
+		let add = FunctionType::new(vec![I32, I32], vec![I32]);
+		assert!(check(add.clone(), vec![], vec![GetLocal(0), GetLocal(1), I32Add, End]).is_ok());
+		assert_eq!(
+			check(add.clone(), vec![], vec![GetLocal(0), I32Add, End]),
+			Err(error_at(1, ValidationError::TypeMismatch { expected: I32, actual: None })),
+		);
+		assert_eq!(
+			check(add.clone(), vec![], vec![GetLocal(0), I64Const(1), I32Add, End]),
+			Err(error_at(2, ValidationError::TypeMismatch { expected: I32, actual: Some(I64) })),
+		);
+		assert_eq!(
+			check(add, vec![], vec![GetLocal(0), GetLocal(1), End]),
+			Err(error_at(2, ValidationError::UnusedValues(1))),
+		);
+		assert_eq!(
+			check(FunctionType::default(), vec![], vec![Drop, End]),
+			Err(error_at(0, ValidationError::StackUnderflow)),
+		);
+	}
+
+	#[test]
+	fn control_frames() {
+		use self::Instruction::*;
+
+		let func_type = FunctionType::new(vec![I32], vec![I64]);
+		let code = vec![
+			Block(BlockType::Value(I64)),
+				I64Const(1),
+				GetLocal(0),
+				BrIf(0),
+				Drop,
+				I64Const(2),
+			End,
+			End,
+		];
+		assert!(check(func_type.clone(), vec![], code).is_ok());
+
+		// `if` without `else` can not produce a value.
+		let code = vec![GetLocal(0), If(BlockType::Value(I64)), I64Const(1), End, End];
+		assert_eq!(
+			check(func_type.clone(), vec![], code),
+			Err(error_at(3, ValidationError::TypeMismatch { expected: I64, actual: None })),
+		);
+
+		let table = BrTableData { table: Box::new([0, 1]), default: 0 };
+		let code = vec![Block(BlockType::NoResult), GetLocal(0), BrTable(Box::new(table)), End, I64Const(0), End];
+		assert_eq!(check(func_type.clone(), vec![], code), Err(error_at(2, ValidationError::LabelTypeMismatch(1))));
+
+		let code = vec![Br(1), End];
+		assert_eq!(check(func_type.clone(), vec![], code), Err(error_at(0, ValidationError::UnknownLabel(1))));
+
+		assert_eq!(
+			check(func_type.clone(), vec![], vec![I64Const(0), End, Nop]),
+			Err(error_at(2, ValidationError::UnbalancedControl)),
+		);
+		assert_eq!(
+			check(func_type, vec![], vec![I64Const(0)]),
+			Err(error_at(1, ValidationError::UnbalancedControl)),
+		);
+	}
+
+	#[test]
+	fn multi_value() {
+		use self::Instruction::*;
+
+		let func_type = FunctionType::new(vec![I32, I64], vec![I32, I64]);
+		let code = vec![GetLocal(0), GetLocal(1), Block(BlockType::TypeIndex(0)), End, End];
+		assert!(check(func_type.clone(), vec![], code).is_ok());
+
+		let code = vec![GetLocal(1), GetLocal(0), Block(BlockType::TypeIndex(0)), End, End];
+		assert_eq!(
+			check(func_type.clone(), vec![], code),
+			Err(error_at(2, ValidationError::TypeMismatch { expected: I64, actual: Some(I32) })),
+		);
+
+		let code = vec![GetLocal(0), GetLocal(1), Block(BlockType::TypeIndex(1)), End, End];
+		assert_eq!(check(func_type, vec![], code), Err(error_at(2, ValidationError::UnknownType(1))));
+	}
+
+	#[test]
+	fn unreachable_code() {
+		use self::Instruction::*;
+
+		let func_type = FunctionType::new(vec![], vec![I32]);
+		// Operand stack is polymorphic after an unconditional branch.
+		assert!(check(func_type.clone(), vec![], vec![Unreachable, I32Add, End]).is_ok());
+		assert!(check(func_type.clone(), vec![], vec![I32Const(1), Return, Select, End]).is_ok());
+		assert_eq!(
+			check(func_type, vec![], vec![Unreachable, I64Const(0), I32Add, End]),
+			Err(error_at(2, ValidationError::TypeMismatch { expected: I32, actual: Some(I64) })),
+		);
+	}
+
+	#[test]
+	fn non_defaultable_locals() {
+		use self::Instruction::*;
+
+		let func_ref = RefType::new(false, HeapType::Func);
+		let func_type = FunctionType::new(vec![func_ref.into()], Vec::new());
+		let locals = vec![Local::new(1, func_ref)];
+
+		let code = vec![GetLocal(0), SetLocal(1), GetLocal(1), Drop, End];
+		assert!(check(func_type.clone(), locals.clone(), code).is_ok());
+
+		let code = vec![GetLocal(1), Drop, End];
+		assert_eq!(
+			check(func_type.clone(), locals.clone(), code),
+			Err(error_at(0, ValidationError::UninitializedLocal(1))),
+		);
+
+		// Initialization does not outlive the block it happened in.
+		let code = vec![Block(BlockType::NoResult), GetLocal(0), SetLocal(1), End, GetLocal(1), Drop, End];
+		assert_eq!(
+			check(func_type, locals, code),
+			Err(error_at(4, ValidationError::UninitializedLocal(1))),
+		);
+	}
+
+	#[test]
+	fn incremental() {
+		let module = Module::new(vec![Section::Type(TypeSection::with_types(vec![FunctionType::default().into()]))]);
+		let validated = validate(&module).expect("module to be valid");
+		let func_type = FunctionType::new(vec![], vec![I32]);
+
+		let mut validator = FuncValidator::new(&validated, &func_type, &[]).expect("locals to be valid");
+		validator.step(&Instruction::I32Const(1)).expect("i32.const to be valid");
+		validator.step(&Instruction::End).expect("end to be valid");
+		validator.finish().expect("body to be terminated");
+	}
+}
@@ -0,0 +1,1130 @@
+//! Validation of modules according to the WebAssembly specification.
+//!
+//! Decoding only checks that a module is well-formed. [`validate`] additionally
+//! checks that all indices are in bounds, that types and limits are consistent,
+//! that export names are unique and that initializer expressions are constant.
+//! Function bodies are type checked with [`FuncValidator`]. Initializer
+//! expressions of a validated module can be evaluated to [`ConstValue`]s.
+
+use crate::rust::{fmt, string::String, vec::Vec, boxed::Box, collections::BTreeSet};
+use super::{
+	Module, Section, TypeSection, Type, FunctionType, StructType, ArrayType, ValueType, RefType,
+	HeapType, NumType, BlockType, TableType, MemoryType, GlobalType, ResizableLimits, External,
+	Internal, InitExpr, Instruction, TypeContext, Subtype, ElementMode, ElementItems,
+};
+
+mod func;
+mod eval;
+
+pub use self::func::FuncValidator;
+pub use self::eval::ConstValue;
+
+/// Maximum number of pages of a linear memory.
+const MAX_PAGES: u64 = 65536;
+/// Maximum number of pages of a linear memory with 64-bit addresses.
+const MAX_PAGES_64: u64 = 1 << 48;
+
+/// Reason for a module to be invalid.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+	/// Unknown type index.
+	UnknownType(u32),
+	/// Unknown function index.
+	UnknownFunction(u32),
+	/// Unknown table index.
+	UnknownTable(u32),
+	/// Unknown memory index.
+	UnknownMemory(u32),
+	/// Unknown global index.
+	UnknownGlobal(u32),
+	/// Unknown local index.
+	UnknownLocal(u32),
+	/// Unknown element segment index.
+	UnknownElementSegment(u32),
+	/// Unknown data segment index.
+	UnknownDataSegment(u32),
+	/// Unknown field of a struct type.
+	UnknownField {
+		/// Index of the struct type.
+		type_index: u32,
+		/// Index of the field.
+		field: u32,
+	},
+	/// Type index does not refer to a function type.
+	NotFunctionType(u32),
+	/// Type index does not refer to a struct type.
+	NotStructType(u32),
+	/// Type index does not refer to an array type.
+	NotArrayType(u32),
+	/// Declared supertype of the type is unknown, final or not a supertype.
+	InvalidSubtype(u32),
+	/// Minimum of the limits is greater than the maximum.
+	InvalidLimits,
+	/// Memory limits are greater than 65536 pages, or 2^48 pages for 64-bit memories.
+	MemoryTooLarge,
+	/// Shared memory does not declare its maximum.
+	SharedMemoryWithoutMaximum,
+	/// Two exports have the same name.
+	DuplicateExport(String),
+	/// Start function takes parameters or returns a value.
+	InvalidStartFunction(u32),
+	/// Initializer expression contains an instruction that is not constant.
+	ConstantExpressionRequired,
+	/// Value of unexpected type.
+	TypeMismatch {
+		/// Expected type.
+		expected: ValueType,
+		/// Actual type, `None` if no value was found.
+		actual: Option<ValueType>,
+	},
+	/// Global is immutable and can not be set.
+	ImmutableGlobal(u32),
+	/// Function is referenced by `ref.func` in code, but not declared by the module.
+	UndeclaredFunctionReference(u32),
+	/// Offset of the element segment does not match its mode.
+	ElementSegmentOffset(u32),
+	/// Offset of the data segment does not match whether it is passive.
+	DataSegmentOffset(u32),
+	/// Data segment is used in code without a data count section.
+	DataCountRequired,
+	/// Data count section does not match the number of data segments.
+	DataCountMismatch {
+		/// Count declared by the data count section.
+		declared: u32,
+		/// Actual number of data segments.
+		actual: u32,
+	},
+	/// Number of function bodies and signatures does not match.
+	InconsistentCode,
+	/// Sum of counts of locals is greater than 2^32.
+	TooManyLocals,
+	/// Branch to a label that is not in scope.
+	UnknownLabel(u32),
+	/// Label does not take the values required by the branch.
+	LabelTypeMismatch(u32),
+	/// Operand stack of the block is empty.
+	StackUnderflow,
+	/// Values left on the operand stack at the end of a block.
+	UnusedValues(usize),
+	/// Non-reference value where a reference is required.
+	ExpectedReference(ValueType),
+	/// Untyped `select` of references.
+	InvalidSelect,
+	/// `else` or `end` without a matching block, or code after the final `end`.
+	UnbalancedControl,
+	/// Local of a non-defaultable type is read before it is set.
+	UninitializedLocal(u32),
+	/// Type has no default value.
+	NonDefaultable(ValueType),
+	/// Alignment of a memory access is greater than its natural alignment.
+	InvalidAlignment {
+		/// Alignment exponent of the access.
+		align: u32,
+		/// Natural alignment exponent of the access.
+		natural: u32,
+	},
+	/// Offset of a memory access does not fit the addresses of a 32-bit memory.
+	OffsetTooLarge(u64),
+	/// SIMD lane index out of range.
+	InvalidLane(u8),
+	/// Struct field is immutable and can not be set.
+	ImmutableField {
+		/// Index of the struct type.
+		type_index: u32,
+		/// Index of the field.
+		field: u32,
+	},
+	/// Array type is immutable and can not be set.
+	ImmutableArray(u32),
+	/// Packed field accessed without sign extension, or unpacked field with it.
+	InvalidPackedAccess(u32),
+	/// Element type of the array does not fit the segment or source array.
+	InvalidArrayElement(u32),
+	/// Initializer expression allocates a struct or array and has no constant value.
+	HeapAllocation,
+	/// Lazily decoded function body is malformed.
+	MalformedBody(String),
+	/// Value of the imported global with the given index is missing or of the wrong type.
+	ImportedGlobalValue(u32),
+	/// Invalid function body.
+	Function {
+		/// Index of the function, including imported ones.
+		func: u32,
+		/// Index of the offending instruction, `None` for errors in local declarations.
+		instruction: Option<u32>,
+		/// Error in the function body.
+		error: Box<ValidationError>,
+	},
+}
+
+impl fmt::Display for ValidationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ValidationError::UnknownType(idx) => write!(f, "Unknown type {}", idx),
+			ValidationError::UnknownFunction(idx) => write!(f, "Unknown function {}", idx),
+			ValidationError::UnknownTable(idx) => write!(f, "Unknown table {}", idx),
+			ValidationError::UnknownMemory(idx) => write!(f, "Unknown memory {}", idx),
+			ValidationError::UnknownGlobal(idx) => write!(f, "Unknown global {}", idx),
+			ValidationError::UnknownLocal(idx) => write!(f, "Unknown local {}", idx),
+			ValidationError::UnknownElementSegment(idx) => write!(f, "Unknown element segment {}", idx),
+			ValidationError::UnknownDataSegment(idx) => write!(f, "Unknown data segment {}", idx),
+			ValidationError::UnknownField { type_index, field } => {
+				write!(f, "Unknown field {} of type {}", field, type_index)
+			},
+			ValidationError::NotFunctionType(idx) => write!(f, "Type {} is not a function type", idx),
+			ValidationError::NotStructType(idx) => write!(f, "Type {} is not a struct type", idx),
+			ValidationError::NotArrayType(idx) => write!(f, "Type {} is not an array type", idx),
+			ValidationError::InvalidSubtype(idx) => write!(f, "Invalid supertype of type {}", idx),
+			ValidationError::InvalidLimits => write!(f, "Size minimum must not be greater than maximum"),
+			ValidationError::MemoryTooLarge => write!(f, "Memory size is too large"),
+			ValidationError::SharedMemoryWithoutMaximum => write!(f, "Shared memory must have maximum"),
+			ValidationError::DuplicateExport(ref name) => write!(f, "Duplicate export name {:?}", name),
+			ValidationError::InvalidStartFunction(idx) => write!(f, "Invalid start function {}", idx),
+			ValidationError::ConstantExpressionRequired => write!(f, "Constant expression required"),
+			ValidationError::TypeMismatch { expected, actual: Some(actual) } => {
+				write!(f, "Type mismatch: expected {}, found {}", expected, actual)
+			},
+			ValidationError::TypeMismatch { expected, actual: None } => {
+				write!(f, "Type mismatch: expected {}, found nothing", expected)
+			},
+			ValidationError::ImmutableGlobal(idx) => write!(f, "Global {} is immutable", idx),
+			ValidationError::UndeclaredFunctionReference(idx) => {
+				write!(f, "Undeclared function reference {}", idx)
+			},
+			ValidationError::ElementSegmentOffset(idx) => {
+				write!(f, "Element segment {} has an offset only if it is active", idx)
+			},
+			ValidationError::DataSegmentOffset(idx) => {
+				write!(f, "Data segment {} has an offset only if it is active", idx)
+			},
+			ValidationError::DataCountRequired => write!(f, "Data count section required"),
+			ValidationError::DataCountMismatch { declared, actual } => {
+				write!(f, "Data count {} does not match {} data segments", declared, actual)
+			},
+			ValidationError::InconsistentCode => {
+				write!(f, "Number of function body entries and signatures does not match")
+			},
+			ValidationError::TooManyLocals => write!(f, "Too many locals"),
+			ValidationError::UnknownLabel(depth) => write!(f, "Unknown label {}", depth),
+			ValidationError::LabelTypeMismatch(depth) => write!(f, "Type mismatch of label {}", depth),
+			ValidationError::StackUnderflow => write!(f, "Operand stack underflow"),
+			ValidationError::UnusedValues(count) => write!(f, "{} values left on the operand stack", count),
+			ValidationError::ExpectedReference(ty) => write!(f, "Expected reference, found {}", ty),
+			ValidationError::InvalidSelect => write!(f, "Untyped select of references"),
+			ValidationError::UnbalancedControl => write!(f, "Unbalanced control instructions"),
+			ValidationError::UninitializedLocal(idx) => write!(f, "Uninitialized local {}", idx),
+			ValidationError::NonDefaultable(ty) => write!(f, "Type {} is not defaultable", ty),
+			ValidationError::InvalidAlignment { align, natural } => {
+				write!(f, "Alignment 2^{} is greater than natural alignment 2^{}", align, natural)
+			},
+			ValidationError::OffsetTooLarge(offset) => write!(f, "Offset {} is too large for a 32-bit memory", offset),
+			ValidationError::InvalidLane(lane) => write!(f, "Invalid lane index {}", lane),
+			ValidationError::ImmutableField { type_index, field } => {
+				write!(f, "Field {} of type {} is immutable", field, type_index)
+			},
+			ValidationError::ImmutableArray(idx) => write!(f, "Array type {} is immutable", idx),
+			ValidationError::InvalidPackedAccess(idx) => write!(f, "Invalid packed access of type {}", idx),
+			ValidationError::InvalidArrayElement(idx) => write!(f, "Invalid element type of array type {}", idx),
+			ValidationError::HeapAllocation => write!(f, "Initializer expression allocates on the heap"),
+			ValidationError::MalformedBody(ref error) => write!(f, "Malformed function body: {}", error),
+			ValidationError::ImportedGlobalValue(idx) => write!(f, "Invalid value of imported global {}", idx),
+			ValidationError::Function { func, instruction: Some(instruction), ref error } => {
+				write!(f, "Function {}, instruction {}: {}", func, instruction, error)
+			},
+			ValidationError::Function { func, instruction: None, ref error } => {
+				write!(f, "Function {}: {}", func, error)
+			},
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ValidationError {}
+
+/// Module that passed validation, along with its index spaces.
+#[derive(Debug, Clone)]
+pub struct ValidatedModule<'a> {
+	module: &'a Module,
+	types: TypeContext<'a>,
+	funcs: Vec<u32>,
+	tables: Vec<TableType>,
+	memories: Vec<MemoryType>,
+	globals: Vec<GlobalType>,
+	imported_globals: u32,
+	elements: Vec<RefType>,
+	data_count: Option<u32>,
+	refs: BTreeSet<u32>,
+}
+
+/// Validate the module.
+pub fn validate(module: &Module) -> Result<ValidatedModule<'_>, ValidationError> {
+	let mut validated = ValidatedModule {
+		module,
+		types: TypeContext::default(),
+		funcs: Vec::new(),
+		tables: Vec::new(),
+		memories: Vec::new(),
+		globals: Vec::new(),
+		imported_globals: 0,
+		elements: module.elements_section().map(|s| s.entries()).unwrap_or(&[]).iter().map(|s| s.elem_type()).collect(),
+		data_count: None,
+		refs: BTreeSet::new(),
+	};
+
+	if let Some(types) = module.type_section() {
+		validate_types(types)?;
+		validated.types = TypeContext::new(types);
+	}
+	validated.validate_imports()?;
+	validated.validate_definitions()?;
+	validated.validate_exports()?;
+	validated.validate_start()?;
+	validated.validate_segments()?;
+	validated.validate_code()?;
+	Ok(validated)
+}
+
+impl<'a> ValidatedModule<'a> {
+	/// Module that was validated.
+	pub fn module(&self) -> &'a Module { self.module }
+
+	/// Type context of the module.
+	pub fn type_context(&self) -> &TypeContext<'a> { &self.types }
+
+	/// Number of functions, including imported ones.
+	pub fn functions_space(&self) -> usize { self.funcs.len() }
+
+	/// Type index of the function with the given index.
+	pub fn func_type_ref(&self, func: u32) -> Result<u32, ValidationError> {
+		self.funcs.get(func as usize).copied().ok_or(ValidationError::UnknownFunction(func))
+	}
+
+	/// Type of the function with the given index.
+	pub fn func_type(&self, func: u32) -> Result<&'a FunctionType, ValidationError> {
+		self.function_type(self.func_type_ref(func)?)
+	}
+
+	/// Function type with the given type index.
+	pub fn function_type(&self, index: u32) -> Result<&'a FunctionType, ValidationError> {
+		match self.types.ty(index) {
+			Some(Type::Function(f)) => Ok(f),
+			Some(_) => Err(ValidationError::NotFunctionType(index)),
+			None => Err(ValidationError::UnknownType(index)),
+		}
+	}
+
+	/// Struct type with the given type index.
+	pub fn struct_type(&self, index: u32) -> Result<&'a StructType, ValidationError> {
+		match self.types.ty(index) {
+			Some(Type::Struct(s)) => Ok(s),
+			Some(_) => Err(ValidationError::NotStructType(index)),
+			None => Err(ValidationError::UnknownType(index)),
+		}
+	}
+
+	/// Array type with the given type index.
+	pub fn array_type(&self, index: u32) -> Result<&'a ArrayType, ValidationError> {
+		match self.types.ty(index) {
+			Some(Type::Array(a)) => Ok(a),
+			Some(_) => Err(ValidationError::NotArrayType(index)),
+			None => Err(ValidationError::UnknownType(index)),
+		}
+	}
+
+	/// Table with the given index, including imported ones.
+	pub fn table(&self, index: u32) -> Result<&TableType, ValidationError> {
+		self.tables.get(index as usize).ok_or(ValidationError::UnknownTable(index))
+	}
+
+	/// Memory with the given index, including imported ones.
+	pub fn memory(&self, index: u32) -> Result<&MemoryType, ValidationError> {
+		self.memories.get(index as usize).ok_or(ValidationError::UnknownMemory(index))
+	}
+
+	/// Global with the given index, including imported ones.
+	pub fn global(&self, index: u32) -> Result<&GlobalType, ValidationError> {
+		self.globals.get(index as usize).ok_or(ValidationError::UnknownGlobal(index))
+	}
+
+	/// Element type of the element segment with the given index.
+	pub fn element_segment(&self, index: u32) -> Result<RefType, ValidationError> {
+		self.elements.get(index as usize).copied().ok_or(ValidationError::UnknownElementSegment(index))
+	}
+
+	/// Check that the data segment with the given index can be used in code.
+	pub fn data_segment(&self, index: u32) -> Result<(), ValidationError> {
+		match self.data_count {
+			Some(count) if index < count => Ok(()),
+			Some(_) => Err(ValidationError::UnknownDataSegment(index)),
+			None => Err(ValidationError::DataCountRequired),
+		}
+	}
+
+	/// Is the function declared to be referenced with `ref.func` in code.
+	pub fn is_declared_ref(&self, func: u32) -> bool {
+		self.refs.contains(&func)
+	}
+
+	/// Check that the heap type refers to a defined type.
+	pub fn check_heap_type(&self, heap_type: HeapType) -> Result<(), ValidationError> {
+		check_heap_type(heap_type, self.types_len())
+	}
+
+	/// Check that the value type refers to defined types only.
+	pub fn check_value_type(&self, value_type: ValueType) -> Result<(), ValidationError> {
+		check_value_type(value_type, self.types_len())
+	}
+
+	/// Check that the block type refers to defined types only.
+	pub fn check_block_type(&self, block_type: BlockType) -> Result<(), ValidationError> {
+		match block_type {
+			BlockType::Value(v) => self.check_value_type(v),
+			BlockType::NoResult => Ok(()),
+			BlockType::TypeIndex(idx) => self.function_type(idx).map(|_| ()),
+		}
+	}
+
+	/// Check that the initializer expression is constant and produces a value of the expected type.
+	///
+	/// Only imported immutable globals can be read. The value of a checked
+	/// expression is computed by [`eval_init_expr`](Self::eval_init_expr).
+	pub fn check_init_expr(&self, expr: &InitExpr, expected: ValueType) -> Result<(), ValidationError> {
+		let (last, code) = expr.code().split_last().ok_or(ValidationError::ConstantExpressionRequired)?;
+		if *last != Instruction::End {
+			return Err(ValidationError::ConstantExpressionRequired);
+		}
+
+		let mut stack = Vec::new();
+		for instruction in code {
+			let result: ValueType = match *instruction {
+				Instruction::I32Const(_) => NumType::I32.into(),
+				Instruction::I64Const(_) => NumType::I64.into(),
+				Instruction::F32Const(_) => NumType::F32.into(),
+				Instruction::F64Const(_) => NumType::F64.into(),
+				Instruction::V128Const(_) => ValueType::V128,
+				Instruction::RefNull(ht) => {
+					self.check_heap_type(ht)?;
+					RefType::new(true, ht).into()
+				},
+				Instruction::RefFunc(func) => RefType::new(false, HeapType::Index(self.func_type_ref(func)?)).into(),
+				Instruction::GetGlobal(idx) => {
+					let global = self.global(idx)?;
+					if idx >= self.imported_globals || global.is_mutable() {
+						return Err(ValidationError::ConstantExpressionRequired);
+					}
+					global.content_type()
+				},
+				Instruction::RefI31 => {
+					self.pop(&mut stack, NumType::I32.into())?;
+					RefType::new(false, HeapType::I31).into()
+				},
+				Instruction::StructNew(ty) => {
+					for field in self.struct_type(ty)?.fields().iter().rev() {
+						self.pop(&mut stack, field.storage_type().unpacked())?;
+					}
+					RefType::new(false, HeapType::Index(ty)).into()
+				},
+				Instruction::StructNewDefault(ty) => {
+					self.struct_type(ty)?;
+					RefType::new(false, HeapType::Index(ty)).into()
+				},
+				Instruction::ArrayNew(ty) => {
+					let elem = self.array_type(ty)?.elem().storage_type().unpacked();
+					self.pop(&mut stack, NumType::I32.into())?;
+					self.pop(&mut stack, elem)?;
+					RefType::new(false, HeapType::Index(ty)).into()
+				},
+				Instruction::ArrayNewDefault(ty) => {
+					self.array_type(ty)?;
+					self.pop(&mut stack, NumType::I32.into())?;
+					RefType::new(false, HeapType::Index(ty)).into()
+				},
+				Instruction::ArrayNewFixed(ty, len) => {
+					let elem = self.array_type(ty)?.elem().storage_type().unpacked();
+					for _ in 0..len {
+						self.pop(&mut stack, elem)?;
+					}
+					RefType::new(false, HeapType::Index(ty)).into()
+				},
+				Instruction::AnyConvertExtern => {
+					let nullable = self.pop_ref(&mut stack, RefType::EXTERNREF)?;
+					RefType::new(nullable, HeapType::Any).into()
+				},
+				Instruction::ExternConvertAny => {
+					let nullable = self.pop_ref(&mut stack, RefType::ANYREF)?;
+					RefType::new(nullable, HeapType::Extern).into()
+				},
+				_ => return Err(ValidationError::ConstantExpressionRequired),
+			};
+			stack.push(result);
+		}
+
+		self.pop(&mut stack, expected)?;
+		if !stack.is_empty() {
+			return Err(ValidationError::TypeMismatch { expected, actual: stack.pop() });
+		}
+		Ok(())
+	}
+
+	/// Pop a value that is a subtype of `expected` from the operand stack of an initializer expression.
+	fn pop(&self, stack: &mut Vec<ValueType>, expected: ValueType) -> Result<ValueType, ValidationError> {
+		match stack.pop() {
+			Some(actual) if self.types.is_subtype(&actual, &expected) => Ok(actual),
+			actual => Err(ValidationError::TypeMismatch { expected, actual }),
+		}
+	}
+
+	/// Pop a reference and return whether it is nullable.
+	fn pop_ref(&self, stack: &mut Vec<ValueType>, expected: RefType) -> Result<bool, ValidationError> {
+		match self.pop(stack, expected.into())? {
+			ValueType::Ref(r) => Ok(r.is_nullable()),
+			_ => Ok(true),
+		}
+	}
+
+	fn types_len(&self) -> u32 {
+		self.module.type_section().map(|s| s.types().len() as u32).unwrap_or(0)
+	}
+
+	fn check_ref_subtype(&self, actual: RefType, expected: RefType) -> Result<(), ValidationError> {
+		if self.types.is_subtype(&actual, &expected) {
+			Ok(())
+		} else {
+			Err(ValidationError::TypeMismatch { expected: expected.into(), actual: Some(actual.into()) })
+		}
+	}
+
+	fn check_table_type(&self, table: &TableType) -> Result<(), ValidationError> {
+		check_limits(table.limits())?;
+		self.check_heap_type(table.elem_type().heap_type())
+	}
+
+	fn check_memory_type(&self, memory: &MemoryType) -> Result<(), ValidationError> {
+		let limits = memory.limits();
+		check_limits(limits)?;
+		let max_pages = match limits.index_type() {
+			NumType::I64 => MAX_PAGES_64,
+			_ => MAX_PAGES,
+		};
+		if limits.initial() > max_pages || limits.maximum().is_some_and(|max| max > max_pages) {
+			return Err(ValidationError::MemoryTooLarge);
+		}
+		if limits.shared() && limits.maximum().is_none() {
+			return Err(ValidationError::SharedMemoryWithoutMaximum);
+		}
+		Ok(())
+	}
+
+	fn push_memory(&mut self, memory: MemoryType) -> Result<(), ValidationError> {
+		self.check_memory_type(&memory)?;
+		self.memories.push(memory);
+		Ok(())
+	}
+
+	fn validate_imports(&mut self) -> Result<(), ValidationError> {
+		let entries = self.module.import_section().map(|s| s.entries()).unwrap_or(&[]);
+		for entry in entries {
+			match *entry.external() {
+				External::Function(ty) => {
+					self.function_type(ty)?;
+					self.funcs.push(ty);
+				},
+				External::Table(ref table) => {
+					self.check_table_type(table)?;
+					self.tables.push(*table);
+				},
+				External::Memory(ref memory) => self.push_memory(*memory)?,
+				External::Global(ref global) => {
+					self.check_value_type(global.content_type())?;
+					self.globals.push(*global);
+				},
+			}
+		}
+		self.imported_globals = self.globals.len() as u32;
+		Ok(())
+	}
+
+	fn validate_definitions(&mut self) -> Result<(), ValidationError> {
+		let module = self.module;
+		for func in module.function_section().map(|s| s.entries()).unwrap_or(&[]) {
+			self.function_type(func.type_ref())?;
+			self.funcs.push(func.type_ref());
+		}
+		for table in module.table_section().map(|s| s.entries()).unwrap_or(&[]) {
+			self.check_table_type(table)?;
+			self.tables.push(*table);
+		}
+		for memory in module.memory_section().map(|s| s.entries()).unwrap_or(&[]) {
+			self.push_memory(*memory)?;
+		}
+		for global in module.global_section().map(|s| s.entries()).unwrap_or(&[]) {
+			let global_type = *global.global_type();
+			self.check_value_type(global_type.content_type())?;
+			self.check_init_expr(global.init_expr(), global_type.content_type())?;
+			self.declare_refs(global.init_expr());
+			self.globals.push(global_type);
+		}
+		Ok(())
+	}
+
+	fn validate_exports(&mut self) -> Result<(), ValidationError> {
+		let entries = self.module.export_section().map(|s| s.entries()).unwrap_or(&[]);
+		let mut names = BTreeSet::new();
+		for entry in entries {
+			match *entry.internal() {
+				Internal::Function(idx) => {
+					self.func_type_ref(idx)?;
+					self.refs.insert(idx);
+				},
+				Internal::Table(idx) => { self.table(idx)?; },
+				Internal::Memory(idx) => { self.memory(idx)?; },
+				Internal::Global(idx) => { self.global(idx)?; },
+			}
+			if !names.insert(entry.field()) {
+				return Err(ValidationError::DuplicateExport(entry.field().into()));
+			}
+		}
+		Ok(())
+	}
+
+	fn validate_start(&self) -> Result<(), ValidationError> {
+		if let Some(start) = self.module.start_section() {
+			let func_type = self.func_type(start)?;
+			if !func_type.params().is_empty() || !func_type.results().is_empty() {
+				return Err(ValidationError::InvalidStartFunction(start));
+			}
+		}
+		Ok(())
+	}
+
+	fn validate_segments(&mut self) -> Result<(), ValidationError> {
+		let module = self.module;
+		let segments = module.elements_section().map(|s| s.entries()).unwrap_or(&[]);
+		for (idx, segment) in segments.iter().enumerate() {
+			let elem_type = segment.elem_type();
+			self.check_heap_type(elem_type.heap_type())?;
+			match *segment.items() {
+				ElementItems::Functions(ref funcs) => {
+					self.check_ref_subtype(RefType::new(false, HeapType::Func), elem_type)?;
+					for &func in funcs {
+						self.func_type_ref(func)?;
+						self.refs.insert(func);
+					}
+				},
+				ElementItems::Expressions(ref exprs) => {
+					for expr in exprs {
+						self.check_init_expr(expr, elem_type.into())?;
+						self.declare_refs(expr);
+					}
+				},
+			}
+			match (segment.mode(), segment.offset()) {
+				(ElementMode::Active, Some(ref offset)) => {
+					let table = self.table(segment.index())?;
+					self.check_ref_subtype(elem_type, table.elem_type())?;
+					self.check_init_expr(offset, NumType::I32.into())?;
+				},
+				(ElementMode::Passive, None) | (ElementMode::Declarative, None) => {},
+				_ => return Err(ValidationError::ElementSegmentOffset(idx as u32)),
+			}
+		}
+
+		let segments = module.data_section().map(|s| s.entries()).unwrap_or(&[]);
+		for (idx, segment) in segments.iter().enumerate() {
+			match (segment.passive(), segment.offset()) {
+				(false, Some(ref offset)) => {
+					let index_type = self.memory(segment.index())?.index_type();
+					self.check_init_expr(offset, index_type.into())?;
+				},
+				(true, None) => {},
+				_ => return Err(ValidationError::DataSegmentOffset(idx as u32)),
+			}
+		}
+
+		let data_count = module.sections().iter().filter_map(|section| match *section {
+			Section::DataCount(count) => Some(count),
+			_ => None,
+		}).next();
+		if let Some(declared) = data_count {
+			if declared as usize != segments.len() {
+				return Err(ValidationError::DataCountMismatch { declared, actual: segments.len() as u32 });
+			}
+		}
+		self.data_count = data_count;
+		Ok(())
+	}
+
+	fn validate_code(&self) -> Result<(), ValidationError> {
+		let imported = self.module.import_count(super::ImportCountType::Function);
+		let bodies = self.module.code_section().map(|s| s.bodies()).unwrap_or(&[]);
+		if imported + bodies.len() != self.funcs.len() {
+			return Err(ValidationError::InconsistentCode);
+		}
+		for (index, body) in bodies.iter().enumerate() {
+			self.validate_function((imported + index) as u32, body)?;
+		}
+		Ok(())
+	}
+
+	/// Declare functions referenced by `ref.func` in the expression.
+	fn declare_refs(&mut self, expr: &InitExpr) {
+		for instruction in expr.code() {
+			if let Instruction::RefFunc(func) = *instruction {
+				self.refs.insert(func);
+			}
+		}
+	}
+}
+
+fn check_limits(limits: &ResizableLimits) -> Result<(), ValidationError> {
+	match limits.maximum() {
+		Some(max) if max < limits.initial() => Err(ValidationError::InvalidLimits),
+		_ => Ok(()),
+	}
+}
+
+fn check_heap_type(heap_type: HeapType, bound: u32) -> Result<(), ValidationError> {
+	match heap_type {
+		HeapType::Index(idx) if idx >= bound => Err(ValidationError::UnknownType(idx)),
+		_ => Ok(()),
+	}
+}
+
+fn check_value_type(value_type: ValueType, bound: u32) -> Result<(), ValidationError> {
+	match value_type {
+		ValueType::Ref(r) => check_heap_type(r.heap_type(), bound),
+		_ => Ok(()),
+	}
+}
+
+/// Check type definitions and their declared supertypes.
+fn validate_types(section: &TypeSection) -> Result<(), ValidationError> {
+	// Types can only refer to types in the same or earlier recursion groups.
+	for (idx, ty) in section.types().iter().enumerate() {
+		let bound = section.rec_group(idx as u32).end();
+		match *ty {
+			Type::Function(ref f) => {
+				for &param in f.params() {
+					check_value_type(param, bound)?;
+				}
+				for &result in f.results() {
+					check_value_type(result, bound)?;
+				}
+			},
+			Type::Struct(ref s) => {
+				for field in s.fields() {
+					check_value_type(field.storage_type().unpacked(), bound)?;
+				}
+			},
+			Type::Array(ref a) => check_value_type(a.elem().storage_type().unpacked(), bound)?,
+		}
+	}
+
+	let ctx = TypeContext::new(section);
+	for (idx, ty) in section.types().iter().enumerate() {
+		let idx = idx as u32;
+		let supertypes = section.subtype(idx).map(|sub| sub.supertypes()).unwrap_or(&[]);
+		match *supertypes {
+			[] => {},
+			[supertype] if supertype < idx => {
+				// Types declared without a supertype clause are final.
+				let is_final = section.subtype(supertype).map(|sub| sub.is_final()).unwrap_or(true);
+				let valid = ctx.ty(supertype).is_some_and(|sup| ty.is_subtype(sup, &ctx));
+				if is_final || !valid {
+					return Err(ValidationError::InvalidSubtype(idx));
+				}
+			},
+			_ => return Err(ValidationError::InvalidSubtype(idx)),
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::{
+		Module, Section, TypeSection, FunctionSection, CodeSection, ExportSection, GlobalSection,
+		MemorySection, FunctionType, Func, FuncBody, Instructions, Instruction, ExportEntry, Internal,
+		GlobalEntry, GlobalType, InitExpr, MemoryType, NumType, ValueType, Type, StructType,
+		FieldType, SubType, ImportSection, ImportEntry, External, DataSection, DataSegment, MemArg,
+		TableSection, TableType, ArrayType, ElementSection, ElementSegment, ElementMode, ElementItems, RefType,
+	};
+	use super::{validate, ValidationError, ConstValue};
+
+	fn with_code(code: Vec<Instruction>, mut sections: Vec<Section>) -> Module {
+		let func_type = FunctionType::new(vec![ValueType::from(NumType::I32)], Vec::new());
+		let mut module_sections = vec![
+			Section::Type(TypeSection::with_types(vec![func_type.into()])),
+			Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+		];
+		module_sections.append(&mut sections);
+		module_sections.push(Section::Code(CodeSection::with_bodies(vec![
+			FuncBody::new(Vec::new(), Instructions::new(code)),
+		])));
+		Module::new(module_sections)
+	}
+
+	fn global(mutable: bool, init: Instruction) -> GlobalEntry {
+		GlobalEntry::new(
+			GlobalType::new(NumType::I32.into(), mutable),
+			InitExpr::new(vec![init, Instruction::End]),
+		)
+	}
+
+	#[test]
+	fn valid_module() {
+		let module = with_code(
+			vec![Instruction::GetLocal(0), Instruction::SetGlobal(0), Instruction::End],
+			vec![
+				Section::Global(GlobalSection::with_entries(vec![global(true, Instruction::I32Const(1))])),
+				Section::Export(ExportSection::with_entries(vec![
+					ExportEntry::new("f".into(), Internal::Function(0)),
+					ExportEntry::new("g".into(), Internal::Global(0)),
+				])),
+			],
+		);
+		let validated = validate(&module).expect("module to be valid");
+		assert_eq!(validated.functions_space(), 1);
+		assert!(validated.is_declared_ref(0));
+		assert!(validated.global(0).expect("global to exist").is_mutable());
+	}
+
+	/// Error of the only function body.
+	fn body_error(module: &Module) -> ValidationError {
+		match validate(module).unwrap_err() {
+			ValidationError::Function { func: 0, error, .. } => *error,
+			error => panic!("Expected error in function body, got {:?}", error),
+		}
+	}
+
+	#[test]
+	fn unknown_indices() {
+		let module = with_code(vec![Instruction::GetLocal(1), Instruction::End], vec![]);
+		assert_eq!(body_error(&module), ValidationError::UnknownLocal(1));
+
+		let module = with_code(vec![Instruction::Call(1), Instruction::End], vec![]);
+		assert_eq!(body_error(&module), ValidationError::UnknownFunction(1));
+
+		let module = with_code(vec![Instruction::I32Const(0), Instruction::I32Load(MemArg::new(2, 0)), Instruction::End], vec![]);
+		assert_eq!(body_error(&module), ValidationError::UnknownMemory(0));
+
+		let module = with_code(vec![Instruction::I32Const(0), Instruction::SetGlobal(0), Instruction::End], vec![
+			Section::Global(GlobalSection::with_entries(vec![global(false, Instruction::I32Const(1))])),
+		]);
+		assert_eq!(body_error(&module), ValidationError::ImmutableGlobal(0));
+	}
+
+	#[test]
+	fn exports_and_start() {
+		let module = with_code(vec![Instruction::End], vec![
+			Section::Export(ExportSection::with_entries(vec![
+				ExportEntry::new("f".into(), Internal::Function(0)),
+				ExportEntry::new("f".into(), Internal::Function(0)),
+			])),
+		]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::DuplicateExport("f".into()));
+
+		let module = with_code(vec![Instruction::End], vec![Section::Start(0)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::InvalidStartFunction(0));
+	}
+
+	#[test]
+	fn init_exprs() {
+		let globals = vec![global(false, Instruction::I32Const(1)), global(false, Instruction::GetGlobal(0))];
+		let module = with_code(vec![Instruction::End], vec![Section::Global(GlobalSection::with_entries(globals))]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::ConstantExpressionRequired);
+
+		let globals = vec![global(false, Instruction::I64Const(1))];
+		let module = with_code(vec![Instruction::End], vec![Section::Global(GlobalSection::with_entries(globals))]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::TypeMismatch {
+			expected: NumType::I32.into(),
+			actual: Some(NumType::I64.into()),
+		});
+	}
+
+	#[test]
+	fn evaluation() {
+		let import = ImportEntry::new("env".into(), "base".into(), External::Global(GlobalType::new(NumType::I32.into(), false)));
+		let segments = vec![
+			DataSegment::new(0, Some(InitExpr::new(vec![Instruction::GetGlobal(0), Instruction::End])), vec![1], false),
+			DataSegment::new(0, None, vec![2], true),
+		];
+		let module = with_code(vec![Instruction::End], vec![
+			Section::Import(ImportSection::with_entries(vec![import])),
+			Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None, false)])),
+			Section::Global(GlobalSection::with_entries(vec![
+				global(false, Instruction::GetGlobal(0)),
+				global(true, Instruction::I32Const(-1)),
+			])),
+			Section::Data(DataSection::with_entries(segments)),
+		]);
+		let validated = validate(&module).expect("module to validate");
+
+		let imports = [ConstValue::I32(1024)];
+		assert_eq!(
+			validated.global_values(&imports),
+			Ok(vec![ConstValue::I32(1024), ConstValue::I32(1024), ConstValue::I32(-1)]),
+		);
+		assert_eq!(validated.data_offsets(&imports), Ok(vec![Some(1024), None]));
+		assert_eq!(validated.element_offsets(&imports), Ok(vec![]));
+		assert_eq!(validated.global_values(&[]), Err(ValidationError::ImportedGlobalValue(0)));
+		assert_eq!(validated.global_values(&[ConstValue::I64(0)]), Err(ValidationError::ImportedGlobalValue(0)));
+
+		let expr = InitExpr::new(vec![Instruction::I32Const(-1), Instruction::RefI31, Instruction::End]);
+		assert_eq!(validated.eval_init_expr(&expr, &imports), Ok(ConstValue::I31(0x7fff_ffff)));
+		let expr = InitExpr::new(vec![Instruction::StructNewDefault(0), Instruction::End]);
+		assert_eq!(validated.eval_init_expr(&expr, &imports), Err(ValidationError::HeapAllocation));
+	}
+
+	#[test]
+	fn imported_ref_globals() {
+		let import = |name: &str, ty: RefType| ImportEntry::new(
+			"env".into(), name.into(), External::Global(GlobalType::new(ty.into(), false)),
+		);
+		let module = with_code(vec![Instruction::End], vec![
+			Section::Import(ImportSection::with_entries(vec![
+				import("func", RefType::FUNCREF),
+				import("any", RefType::ANYREF),
+				import("extern", RefType::EXTERNREF),
+			])),
+		]);
+		let validated = validate(&module).expect("module to validate");
+
+		let func = ConstValue::Func(0);
+		let i31 = ConstValue::I31(1);
+		let external = ConstValue::Extern(Box::new(i31.clone()));
+		let imports = [func.clone(), i31.clone(), external.clone()];
+		assert_eq!(validated.global_values(&imports), Ok(imports.to_vec()));
+		assert_eq!(
+			validated.global_values(&[i31.clone(), i31.clone(), external.clone()]),
+			Err(ValidationError::ImportedGlobalValue(0)),
+		);
+		assert_eq!(
+			validated.global_values(&[func.clone(), func.clone(), external]),
+			Err(ValidationError::ImportedGlobalValue(1)),
+		);
+		assert_eq!(
+			validated.global_values(&[func, i31.clone(), i31.clone()]),
+			Err(ValidationError::ImportedGlobalValue(2)),
+		);
+
+		let expr = InitExpr::new(vec![
+			Instruction::I32Const(1), Instruction::RefI31, Instruction::ExternConvertAny, Instruction::End,
+		]);
+		assert_eq!(validated.eval_init_expr(&expr, &imports), Ok(ConstValue::Extern(Box::new(i31))));
+	}
+
+	#[test]
+	fn function_references() {
+		let module = with_code(vec![Instruction::RefFunc(0), Instruction::Drop, Instruction::End], vec![]);
+		assert_eq!(body_error(&module), ValidationError::UndeclaredFunctionReference(0));
+
+		let module = with_code(vec![Instruction::RefFunc(0), Instruction::Drop, Instruction::End], vec![
+			Section::Export(ExportSection::with_entries(vec![ExportEntry::new("f".into(), Internal::Function(0))])),
+		]);
+		assert!(validate(&module).is_ok());
+	}
+
+	#[test]
+	fn memory_limits() {
+		let memories = vec![MemoryType::new(2, Some(1), false)];
+		let module = with_code(vec![Instruction::End], vec![Section::Memory(MemorySection::with_entries(memories))]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::InvalidLimits);
+
+		let memories = vec![MemoryType::new(1, Some(65537), false)];
+		let module = with_code(vec![Instruction::End], vec![Section::Memory(MemorySection::with_entries(memories))]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::MemoryTooLarge);
+
+		let memories = vec![MemoryType::new(1, None, false), MemoryType::new(1, None, false)];
+		let module = with_code(vec![Instruction::End], vec![Section::Memory(MemorySection::with_entries(memories))]);
+		assert!(validate(&module).is_ok());
+	}
+
+	#[test]
+	fn multiple_memories() {
+		use self::Instruction::*;
+
+		let memories = || Section::Memory(MemorySection::with_entries(vec![
+			MemoryType::new(1, None, false),
+			MemoryType::new(1, None, false),
+		]));
+		let second = MemArg { align: 2, offset: 0, memory: 1 };
+		let offset = InitExpr::new(vec![I32Const(0), End]);
+		let data = DataSection::with_entries(vec![DataSegment::new(1, Some(offset.clone()), vec![1], false)]);
+		let code = vec![I32Const(0), I32Load(second), CurrentMemory(1), I32Add, Drop, End];
+		assert!(validate(&with_code(code, vec![memories(), Section::Data(data)])).is_ok());
+
+		let module = with_code(vec![I32Const(0), I32Load(MemArg { memory: 2, ..second }), Drop, End], vec![memories()]);
+		assert_eq!(body_error(&module), ValidationError::UnknownMemory(2));
+
+		let code = vec![I32Const(0), I32Const(0), I32Const(0), MemoryCopy(0, 2), End];
+		assert_eq!(body_error(&with_code(code, vec![memories()])), ValidationError::UnknownMemory(2));
+
+		let data = DataSection::with_entries(vec![DataSegment::new(2, Some(offset), vec![1], false)]);
+		let module = with_code(vec![End], vec![memories(), Section::Data(data)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::UnknownMemory(2));
+	}
+
+	#[test]
+	fn memory64() {
+		use self::Instruction::*;
+
+		let memories = || Section::Memory(MemorySection::with_entries(vec![
+			MemoryType::new64(1, None, false),
+			MemoryType::new(1, None, false),
+		]));
+		let offset = InitExpr::new(vec![I64Const(1 << 33), End]);
+		let data = DataSection::with_entries(vec![DataSegment::new(0, Some(offset), vec![1], false)]);
+		let code = vec![
+			I64Const(0), I32Load(MemArg::new(2, 1 << 40)), Drop,
+			CurrentMemory(0), GrowMemory(0), I32Const(0), I32Const(0), MemoryCopy(0, 1),
+			I64Const(0), I32Const(0), I64Const(1), MemoryFill(0), End,
+		];
+		let module = with_code(code, vec![memories(), Section::Data(data)]);
+		let validated = validate(&module).expect("module to validate");
+		assert_eq!(validated.data_offsets(&[]), Ok(vec![Some(1 << 33)]));
+		assert_eq!(validated.memory(0).map(|memory| memory.index_type()), Ok(NumType::I64));
+
+		let code = vec![I32Const(0), I32Load(MemArg::new(2, 0)), Drop, End];
+		assert_eq!(body_error(&with_code(code, vec![memories()])), ValidationError::TypeMismatch {
+			expected: NumType::I64.into(),
+			actual: Some(NumType::I32.into()),
+		});
+
+		let code = vec![I32Const(0), I32Load(MemArg { align: 2, offset: 1 << 32, memory: 1 }), Drop, End];
+		assert_eq!(body_error(&with_code(code, vec![memories()])), ValidationError::OffsetTooLarge(1 << 32));
+
+		let memories = vec![MemoryType::new64(1, Some((1 << 48) + 1), false)];
+		let module = with_code(vec![End], vec![Section::Memory(MemorySection::with_entries(memories))]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::MemoryTooLarge);
+	}
+
+	#[test]
+	fn multiple_tables() {
+		use self::Instruction::*;
+
+		let tables = || Section::Table(TableSection::with_entries(vec![
+			TableType::new(1, None),
+			TableType::with_elem_type(RefType::EXTERNREF, 1, None),
+		]));
+		let offset = InitExpr::new(vec![I32Const(0), End]);
+		let elements = |table| Section::Element(ElementSection::with_entries(vec![
+			ElementSegment::new(table, Some(offset.clone()), vec![0], false),
+		]));
+		let code = vec![
+			I32Const(0), I32Const(0), CallIndirect(0, 0),
+			TableSize(1), GetTable(1), I32Const(1), TableGrow(1), Drop,
+			I32Const(0), I32Const(0), I32Const(1), TableInit(0, 0),
+			I32Const(0), I32Const(0), I32Const(1), TableCopy(1, 1), End,
+		];
+		assert!(validate(&with_code(code, vec![tables(), elements(0)])).is_ok());
+
+		let code = vec![I32Const(0), I32Const(0), CallIndirect(0, 1), End];
+		assert!(matches!(body_error(&with_code(code, vec![tables()])), ValidationError::TypeMismatch { .. }));
+
+		let code = vec![I32Const(0), I32Const(0), I32Const(1), TableCopy(1, 0), End];
+		assert!(matches!(body_error(&with_code(code, vec![tables()])), ValidationError::TypeMismatch { .. }));
+
+		let code = vec![TableSize(2), Drop, End];
+		assert_eq!(body_error(&with_code(code, vec![tables()])), ValidationError::UnknownTable(2));
+
+		let module = with_code(vec![End], vec![tables(), elements(1)]);
+		assert!(matches!(validate(&module).unwrap_err(), ValidationError::TypeMismatch { .. }));
+	}
+
+	#[test]
+	fn element_segments() {
+		use self::Instruction::*;
+
+		let tables = || Section::Table(TableSection::with_entries(vec![
+			TableType::with_elem_type(RefType::EXTERNREF, 1, None),
+		]));
+		let null = InitExpr::new(vec![RefNull(RefType::EXTERNREF.heap_type()), End]);
+		let func = InitExpr::new(vec![RefFunc(0), End]);
+		let segments = |items| Section::Element(ElementSection::with_entries(vec![
+			ElementSegment::with_items(ElementMode::Passive, 0, None, RefType::EXTERNREF, items),
+			ElementSegment::with_items(ElementMode::Declarative, 0, None, RefType::FUNCREF, ElementItems::Expressions(vec![func.clone()])),
+		]));
+		let code = vec![
+			I32Const(0), I32Const(0), I32Const(1), TableInit(0, 0), TableDrop(1),
+			RefFunc(0), Drop, End,
+		];
+		let module = with_code(code.clone(), vec![tables(), segments(ElementItems::Expressions(vec![null.clone()]))]);
+		assert!(validate(&module).is_ok());
+
+		let module = with_code(code, vec![tables(), segments(ElementItems::Functions(vec![0]))]);
+		assert!(matches!(validate(&module).unwrap_err(), ValidationError::TypeMismatch { .. }));
+
+		let code = vec![I32Const(0), I32Const(0), I32Const(1), TableInit(1, 0), End];
+		let module = with_code(code, vec![tables(), segments(ElementItems::Expressions(vec![null]))]);
+		assert!(matches!(body_error(&module), ValidationError::TypeMismatch { .. }));
+
+		let module = with_code(vec![End], vec![tables(), segments(ElementItems::Expressions(vec![func.clone()]))]);
+		assert!(matches!(validate(&module).unwrap_err(), ValidationError::TypeMismatch { .. }));
+	}
+
+	#[test]
+	fn segment_offsets() {
+		let offset = || InitExpr::new(vec![Instruction::I32Const(0), Instruction::End]);
+		let tables = || Section::Table(TableSection::with_entries(vec![
+			TableType::with_elem_type(RefType::FUNCREF, 1, None),
+		]));
+		let elements = |mode, offset| Section::Element(ElementSection::with_entries(vec![
+			ElementSegment::with_items(mode, 0, offset, RefType::FUNCREF, ElementItems::Functions(vec![0])),
+		]));
+		let module = with_code(vec![Instruction::End], vec![tables(), elements(ElementMode::Active, Some(offset()))]);
+		assert!(validate(&module).is_ok());
+		let module = with_code(vec![Instruction::End], vec![tables(), elements(ElementMode::Active, None)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::ElementSegmentOffset(0));
+
+		let memories = || Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None, false)]));
+		let data = |offset, passive| Section::Data(DataSection::with_entries(vec![
+			DataSegment::new(0, offset, vec![1], passive),
+		]));
+		let module = with_code(vec![Instruction::End], vec![memories(), data(Some(offset()), false)]);
+		assert!(validate(&module).is_ok());
+		let module = with_code(vec![Instruction::End], vec![memories(), data(Some(offset()), true)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::DataSegmentOffset(0));
+		let module = with_code(vec![Instruction::End], vec![memories(), data(None, false)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::DataSegmentOffset(0));
+	}
+
+	#[test]
+	fn array_elem_segments() {
+		use self::Instruction::*;
+
+		let null = InitExpr::new(vec![RefNull(RefType::EXTERNREF.heap_type()), End]);
+		let segments = || Section::Element(ElementSection::with_entries(vec![
+			ElementSegment::with_items(ElementMode::Passive, 0, None, RefType::EXTERNREF, ElementItems::Expressions(vec![null.clone()])),
+		]));
+		let with_array = |elem_type: RefType| {
+			let code = vec![I32Const(0), I32Const(1), ArrayNewElem(1, 0), Drop, End];
+			let mut module = with_code(code, vec![segments()]);
+			let array = ArrayType::new(FieldType::new(ValueType::from(elem_type), true));
+			module.type_section_mut().unwrap().types_mut().push(Type::Array(array));
+			module
+		};
+
+		assert!(validate(&with_array(RefType::EXTERNREF)).is_ok());
+		assert_eq!(body_error(&with_array(RefType::FUNCREF)), ValidationError::InvalidArrayElement(1));
+	}
+
+	#[test]
+	fn subtypes() {
+		let empty = Type::Struct(StructType::new(vec![]));
+		let field = Type::Struct(StructType::new(vec![FieldType::new(ValueType::from(NumType::I32), false)]));
+		let mut types = TypeSection::with_types(vec![empty, field]);
+		types.set_subtype(1, SubType::new(true, vec![0]));
+		let module = Module::new(vec![Section::Type(types.clone())]);
+		// Type 0 is final.
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::InvalidSubtype(1));
+
+		types.set_subtype(0, SubType::new(false, vec![]));
+		let module = Module::new(vec![Section::Type(types.clone())]);
+		assert!(validate(&module).is_ok());
+
+		types.set_subtype(0, SubType::new(false, vec![1]));
+		let module = Module::new(vec![Section::Type(types)]);
+		assert_eq!(validate(&module).unwrap_err(), ValidationError::InvalidSubtype(0));
+	}
+}
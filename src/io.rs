@@ -6,13 +6,13 @@
 use crate::rust::result;
 
 #[cfg(feature="std")]
-use crate::rust::io;
+use crate::rust::{io, sync::Arc};
 
 #[cfg(not(feature="std"))]
 use crate::rust::vec::Vec;
 
 /// IO specific error.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
 	/// Some unexpected data left in the buffer after reading all data.
 	TrailingData,
@@ -24,7 +24,7 @@ pub enum Error {
 	InvalidData,
 
 	#[cfg(feature = "std")]
-	IoError(io::Error),
+	IoError(Arc<io::Error>),
 }
 
 /// IO specific Result.
@@ -42,6 +42,11 @@ pub trait Read {
 	///
 	/// If there is not enough data in this read then `UnexpectedEof` will be returned.
 	fn read(&mut self, buf: &mut [u8]) -> Result<()>;
+
+	/// Number of bytes read from the start of the input, if known.
+	fn position(&self) -> Option<usize> {
+		None
+	}
 }
 
 /// Reader that saves the last position.
@@ -75,6 +80,10 @@ impl<T: AsRef<[u8]>> Read for Cursor<T> {
 		self.pos += requested;
 		Ok(())
 	}
+
+	fn position(&self) -> Option<usize> {
+		Some(self.pos)
+	}
 }
 
 #[cfg(not(feature = "std"))]
@@ -89,14 +98,14 @@ impl Write for Vec<u8> {
 impl<T: io::Read> Read for T {
 	fn read(&mut self, buf: &mut [u8]) -> Result<()> {
 		self.read_exact(buf)
-			.map_err(Error::IoError)
+			.map_err(|e| Error::IoError(Arc::new(e)))
 	}
 }
 
 #[cfg(feature = "std")]
 impl<T: io::Write> Write for T {
 	fn write(&mut self, buf: &[u8]) -> Result<()> {
-		self.write_all(buf).map_err(Error::IoError)
+		self.write_all(buf).map_err(|e| Error::IoError(Arc::new(e)))
 	}
 }
 
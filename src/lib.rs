@@ -32,6 +32,7 @@ pub (crate) mod rust {
 	pub use ::alloc::string;
 	pub use ::alloc::boxed;
 	pub use ::alloc::borrow;
+	pub use ::alloc::collections;
 }
 
 #[cfg(feature="std")]